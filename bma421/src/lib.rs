@@ -0,0 +1,201 @@
+//! Driver for the Bosch BMA421 accelerometer found in the PineTime.
+//!
+//! The sensor is run at 25 Hz and buffers samples in its FIFO. INT1 is raised once the
+//! FIFO holds the configured number of samples, so the MCU only wakes up to read a batch.
+//! Only the raw accelerometer is used; the feature engine (which needs a config blob
+//! uploaded at boot) is left disabled.
+#![no_std]
+
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::I2c;
+use embedded_hal_async::digital::Wait;
+
+pub const ADDRESS: u8 = 0x18;
+
+/// Chip ids of the BMA421 and of the BMA425 used in some PineTime batches.
+const CHIP_IDS: [u8; 2] = [0x11, 0x13];
+
+/// Bytes per sample in headerless FIFO mode.
+const FRAME_SIZE: usize = 6;
+/// Samples the FIFO holds before INT1 fires, unless changed with [`Bma421::set_watermark`].
+pub const DEFAULT_WATERMARK: usize = 25;
+/// Largest batch the driver reads at once.
+pub const MAX_BATCH: usize = 32;
+
+#[repr(u8)]
+#[allow(unused)]
+enum Register {
+    ChipId = 0x00,
+    Data = 0x12,
+    IntStatus1 = 0x1D,
+    FifoLength0 = 0x24,
+    FifoData = 0x26,
+    AccConf = 0x40,
+    AccRange = 0x41,
+    FifoWtm0 = 0x46,
+    FifoConfig0 = 0x48,
+    FifoConfig1 = 0x49,
+    Int1IoCtrl = 0x53,
+    IntLatch = 0x55,
+    IntMapData = 0x58,
+    PwrConf = 0x7C,
+    PwrCtrl = 0x7D,
+    Cmd = 0x7E,
+}
+
+const CMD_SOFT_RESET: u8 = 0xB6;
+const CMD_FIFO_FLUSH: u8 = 0xB0;
+/// 25 Hz, averaging 4 samples, low power mode.
+const ACC_CONF_25HZ: u8 = 0x06 | (0x02 << 4);
+const ACC_RANGE_4G: u8 = 0x01;
+/// Accelerometer data in the FIFO, no frame headers.
+const FIFO_ACC_HEADERLESS: u8 = 1 << 6;
+/// INT1 as a push-pull, active high output.
+const INT1_OUTPUT: u8 = (1 << 3) | (1 << 1);
+const INT1_FIFO_WATERMARK: u8 = 1 << 1;
+const ACC_ENABLE: u8 = 1 << 2;
+const ADVANCED_POWER_SAVE: u8 = 1 << 0;
+const FIFO_SELF_WAKE_UP: u8 = 1 << 1;
+
+/// Milli-g per LSB at ±4 g range is 1000 / 512.
+const MG_PER_LSB_NUM: i32 = 1000;
+const MG_PER_LSB_DEN: i32 = 512;
+
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<I2C> {
+    I2c(I2C),
+    InvalidChipId(u8),
+}
+
+impl<I2C> From<I2C> for Error<I2C> {
+    fn from(e: I2C) -> Self {
+        Self::I2c(e)
+    }
+}
+
+/// Acceleration in milli-g.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Acceleration {
+    pub x: i16,
+    pub y: i16,
+    pub z: i16,
+}
+
+impl Acceleration {
+    fn from_raw(raw: &[u8]) -> Self {
+        let axis = |lsb: u8, msb: u8| {
+            // 12 bit value, left aligned
+            let value = i16::from_le_bytes([lsb, msb]) >> 4;
+            (value as i32 * MG_PER_LSB_NUM / MG_PER_LSB_DEN) as i16
+        };
+        Self {
+            x: axis(raw[0], raw[1]),
+            y: axis(raw[2], raw[3]),
+            z: axis(raw[4], raw[5]),
+        }
+    }
+}
+
+pub struct Bma421<I2C, INT> {
+    i2c: I2C,
+    int: INT,
+}
+
+impl<I2C: I2c, INT: Wait> Bma421<I2C, INT> {
+    pub fn new(i2c: I2C, int: INT) -> Self {
+        Self { i2c, int }
+    }
+
+    /// Reset the sensor and start sampling into the FIFO.
+    pub fn init(&mut self, delay: &mut impl DelayNs) -> Result<(), Error<I2C::Error>> {
+        self.write(Register::Cmd, CMD_SOFT_RESET)?;
+        delay.delay_ms(5);
+
+        let id = self.read(Register::ChipId)?;
+        if !CHIP_IDS.contains(&id) {
+            return Err(Error::InvalidChipId(id));
+        }
+
+        // Register writes need 450us between them while in advanced power save mode
+        self.write(Register::PwrConf, 0)?;
+        delay.delay_us(450);
+
+        self.write(Register::AccConf, ACC_CONF_25HZ)?;
+        self.write(Register::AccRange, ACC_RANGE_4G)?;
+        self.write(Register::FifoConfig0, 0)?;
+        self.write(Register::FifoConfig1, FIFO_ACC_HEADERLESS)?;
+        self.set_watermark(DEFAULT_WATERMARK)?;
+        self.write(Register::Int1IoCtrl, INT1_OUTPUT)?;
+        self.write(Register::IntLatch, 0)?;
+        self.write(Register::IntMapData, INT1_FIFO_WATERMARK)?;
+        self.write(Register::PwrCtrl, ACC_ENABLE)?;
+        self.write(Register::Cmd, CMD_FIFO_FLUSH)?;
+
+        self.write(Register::PwrConf, ADVANCED_POWER_SAVE | FIFO_SELF_WAKE_UP)?;
+        Ok(())
+    }
+
+    /// Number of samples to buffer before INT1 is raised, at most [`MAX_BATCH`].
+    pub fn set_watermark(&mut self, samples: usize) -> Result<(), Error<I2C::Error>> {
+        let bytes = (samples.clamp(1, MAX_BATCH) * FRAME_SIZE) as u16;
+        let [lsb, msb] = bytes.to_le_bytes();
+        self.i2c.write(ADDRESS, &[Register::FifoWtm0 as u8, lsb, msb])?;
+        Ok(())
+    }
+
+    /// The current acceleration.
+    pub fn acceleration(&mut self) -> Result<Acceleration, Error<I2C::Error>> {
+        let mut raw = [0; FRAME_SIZE];
+        self.i2c.write_read(ADDRESS, &[Register::Data as u8], &mut raw)?;
+        Ok(Acceleration::from_raw(&raw))
+    }
+
+    /// Read the samples buffered in the FIFO, oldest first, returning how many were read.
+    pub fn read_fifo(&mut self, samples: &mut [Acceleration]) -> Result<usize, Error<I2C::Error>> {
+        let mut len = [0; 2];
+        self.i2c.write_read(ADDRESS, &[Register::FifoLength0 as u8], &mut len)?;
+        let available = (u16::from_le_bytes(len) & 0x3FFF) as usize / FRAME_SIZE;
+        let count = available.min(samples.len()).min(MAX_BATCH);
+        if count == 0 {
+            return Ok(0);
+        }
+
+        let mut raw = [0; MAX_BATCH * FRAME_SIZE];
+        let raw = &mut raw[..count * FRAME_SIZE];
+        self.i2c.write_read(ADDRESS, &[Register::FifoData as u8], raw)?;
+        for (sample, frame) in samples.iter_mut().zip(raw.chunks_exact(FRAME_SIZE)) {
+            *sample = Acceleration::from_raw(frame);
+        }
+        Ok(count)
+    }
+
+    /// Wait for the FIFO to reach the watermark and read the batch.
+    pub async fn wait_samples(&mut self, samples: &mut [Acceleration]) -> Result<usize, Error<I2C::Error>> {
+        self.wait_watermark().await;
+        self.read_fifo(samples)
+    }
+
+    /// Wait for the FIFO to reach the watermark, without touching the bus.
+    pub async fn wait_watermark(&mut self) {
+        // A pin error means the level can not be observed, fall back to reading whatever is there
+        let _ = self.int.wait_for_high().await;
+    }
+
+    /// Drop any buffered samples, e.g. after the FIFO overflowed while nobody was reading.
+    pub fn flush(&mut self) -> Result<(), Error<I2C::Error>> {
+        self.write(Register::Cmd, CMD_FIFO_FLUSH)
+    }
+
+    fn read(&mut self, register: Register) -> Result<u8, Error<I2C::Error>> {
+        let mut value = [0];
+        self.i2c.write_read(ADDRESS, &[register as u8], &mut value)?;
+        Ok(value[0])
+    }
+
+    fn write(&mut self, register: Register, value: u8) -> Result<(), Error<I2C::Error>> {
+        self.i2c.write(ADDRESS, &[register as u8, value])?;
+        Ok(())
+    }
+}
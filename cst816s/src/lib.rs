@@ -0,0 +1,231 @@
+//! Async driver for the Hynitron CST816S touch controller found in the PineTime.
+//!
+//! The controller pulls its interrupt line low whenever it has recognized a gesture, so
+//! the MCU only talks to it over I2C when there is something to read. Between touches it
+//! drops to a slow scan rate on its own (standby). In deep sleep it stops scanning
+//! altogether and only a reset brings it back.
+#![no_std]
+
+use embedded_hal::digital::OutputPin;
+use embedded_hal::i2c::I2c;
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::digital::Wait;
+
+pub const ADDRESS: u8 = 0x15;
+
+/// Chip ids seen in PineTime batches.
+const CHIP_IDS: [u8; 3] = [0xB4, 0xB5, 0xB6];
+
+#[repr(u8)]
+#[allow(unused)]
+enum Register {
+    GestureId = 0x01,
+    FingerNum = 0x02,
+    PowerMode = 0xA5,
+    ChipId = 0xA7,
+    MotionMask = 0xEC,
+    IrqCtl = 0xFA,
+    AutoSleepTime = 0xF9,
+    DisAutoSleep = 0xFE,
+}
+
+const POWER_MODE_DEEP_SLEEP: u8 = 0x03;
+/// Interrupt on recognized gestures only, not on every touch report.
+const IRQ_MOTION: u8 = 1 << 4;
+const MOTION_DOUBLE_CLICK: u8 = 1 << 0;
+
+/// Bytes read from [`Register::GestureId`] on: gesture, fingers, then x and y, each with
+/// flags in the upper bits of the high byte.
+const REPORT_SIZE: usize = 6;
+
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<I2C, PIN> {
+    I2c(I2C),
+    Pin(PIN),
+    InvalidChipId(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Gesture {
+    None,
+    SlideDown,
+    SlideUp,
+    SlideLeft,
+    SlideRight,
+    SingleClick,
+    DoubleClick,
+    LongPress,
+}
+
+impl Gesture {
+    fn from_id(id: u8) -> Self {
+        match id {
+            0x01 => Self::SlideDown,
+            0x02 => Self::SlideUp,
+            0x03 => Self::SlideLeft,
+            0x04 => Self::SlideRight,
+            0x05 => Self::SingleClick,
+            0x0B => Self::DoubleClick,
+            0x0C => Self::LongPress,
+            _ => Self::None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TouchEvent {
+    pub gesture: Gesture,
+    /// Fingers on the screen when the gesture was reported, 0 once lifted.
+    pub fingers: u8,
+    pub x: u16,
+    pub y: u16,
+}
+
+impl TouchEvent {
+    fn from_raw(raw: &[u8; REPORT_SIZE]) -> Self {
+        Self {
+            gesture: Gesture::from_id(raw[0]),
+            fingers: raw[1] & 0x0F,
+            x: u16::from_be_bytes([raw[2] & 0x0F, raw[3]]),
+            y: u16::from_be_bytes([raw[4] & 0x0F, raw[5]]),
+        }
+    }
+}
+
+pub struct Cst816s<I2C, INT, RST> {
+    i2c: I2C,
+    int: INT,
+    rst: RST,
+}
+
+impl<I2C: I2c, INT: Wait, RST: OutputPin> Cst816s<I2C, INT, RST> {
+    pub fn new(i2c: I2C, int: INT, rst: RST) -> Self {
+        Self { i2c, int, rst }
+    }
+
+    /// Reset the controller, which also wakes it from deep sleep, and enable gesture
+    /// interrupts with standby after `standby_seconds` without touches.
+    pub async fn init(
+        &mut self,
+        delay: &mut impl DelayNs,
+        standby_seconds: u8,
+    ) -> Result<(), Error<I2C::Error, RST::Error>> {
+        self.rst.set_low().map_err(Error::Pin)?;
+        delay.delay_ms(5).await;
+        self.rst.set_high().map_err(Error::Pin)?;
+        // The controller ignores I2C until it has booted
+        delay.delay_ms(50).await;
+
+        let id = self.read(Register::ChipId)?;
+        if !CHIP_IDS.contains(&id) {
+            return Err(Error::InvalidChipId(id));
+        }
+
+        self.write(Register::IrqCtl, IRQ_MOTION)?;
+        self.write(Register::MotionMask, MOTION_DOUBLE_CLICK)?;
+        self.write(Register::AutoSleepTime, standby_seconds.max(1))?;
+        self.write(Register::DisAutoSleep, 0)?;
+        Ok(())
+    }
+
+    /// Stop scanning until the next [`init`](Self::init). The interrupt line stays quiet
+    /// in the meantime.
+    pub fn sleep(&mut self) -> Result<(), Error<I2C::Error, RST::Error>> {
+        self.write(Register::PowerMode, POWER_MODE_DEEP_SLEEP)
+    }
+
+    /// Read the last report, `None` if it holds no gesture.
+    pub fn read_event(&mut self) -> Result<Option<TouchEvent>, Error<I2C::Error, RST::Error>> {
+        let mut raw = [0; REPORT_SIZE];
+        self.i2c
+            .write_read(ADDRESS, &[Register::GestureId as u8], &mut raw)
+            .map_err(Error::I2c)?;
+        let event = TouchEvent::from_raw(&raw);
+        Ok(Some(event).filter(|e| e.gesture != Gesture::None))
+    }
+
+    /// Wait for the controller to signal a new report, without touching the bus. Read it
+    /// with [`read_event`](Self::read_event).
+    pub async fn wait_for_report(&mut self) {
+        // The line stays low for a moment after each report, don't wait on the same one twice.
+        // A pin error means the level can not be observed, fall back to reading whatever is there
+        let _ = self.int.wait_for_high().await;
+        let _ = self.int.wait_for_low().await;
+    }
+
+    /// Wait for the next gesture. Awaiting this in a loop gives the stream of touches.
+    pub async fn next_event(&mut self) -> Result<TouchEvent, Error<I2C::Error, RST::Error>> {
+        loop {
+            self.wait_for_report().await;
+            if let Some(event) = self.read_event()? {
+                return Ok(event);
+            }
+        }
+    }
+
+    fn read(&mut self, register: Register) -> Result<u8, Error<I2C::Error, RST::Error>> {
+        let mut value = [0];
+        self.i2c
+            .write_read(ADDRESS, &[register as u8], &mut value)
+            .map_err(Error::I2c)?;
+        Ok(value[0])
+    }
+
+    fn write(&mut self, register: Register, value: u8) -> Result<(), Error<I2C::Error, RST::Error>> {
+        self.i2c.write(ADDRESS, &[register as u8, value]).map_err(Error::I2c)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gesture_ids() {
+        assert_eq!(Gesture::from_id(0x00), Gesture::None);
+        assert_eq!(Gesture::from_id(0x01), Gesture::SlideDown);
+        assert_eq!(Gesture::from_id(0x02), Gesture::SlideUp);
+        assert_eq!(Gesture::from_id(0x03), Gesture::SlideLeft);
+        assert_eq!(Gesture::from_id(0x04), Gesture::SlideRight);
+        assert_eq!(Gesture::from_id(0x05), Gesture::SingleClick);
+        assert_eq!(Gesture::from_id(0x0B), Gesture::DoubleClick);
+        assert_eq!(Gesture::from_id(0x0C), Gesture::LongPress);
+        // Unknown ids are not gestures
+        assert_eq!(Gesture::from_id(0x06), Gesture::None);
+        assert_eq!(Gesture::from_id(0xFF), Gesture::None);
+    }
+
+    #[test]
+    fn report() {
+        let event = TouchEvent::from_raw(&[0x05, 0x01, 0x00, 0x78, 0x00, 0xEF]);
+        assert_eq!(
+            event,
+            TouchEvent {
+                gesture: Gesture::SingleClick,
+                fingers: 1,
+                x: 120,
+                y: 239,
+            }
+        );
+    }
+
+    #[test]
+    fn report_flags() {
+        // Event flags in the upper bits of the coordinates and finger count are dropped
+        let event = TouchEvent::from_raw(&[0x0C, 0xF0, 0x80, 0xEF, 0x40, 0x10]);
+        assert_eq!(
+            event,
+            TouchEvent {
+                gesture: Gesture::LongPress,
+                fingers: 0,
+                x: 239,
+                y: 16,
+            }
+        );
+        let event = TouchEvent::from_raw(&[0x01, 0x00, 0xFF, 0xFF, 0x0F, 0xFF]);
+        assert_eq!((event.x, event.y), (0xFFF, 0xFFF));
+    }
+}
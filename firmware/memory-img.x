@@ -3,4 +3,16 @@ MEMORY
   /* NOTE 1 K = 1 KiBi = 1024 bytes */
   FLASH                             : ORIGIN = 0x00008020, LENGTH = 256K
   RAM                               : ORIGIN = 0x20000008, LENGTH = 32760
+  /* Kept over warm resets for the clock, just above the RAM of the application. The
+     bootloader keeps its data at the start of RAM and its stack grows down from the top
+     of RAM, neither reaches this far. */
+  RETAINED                          : ORIGIN = 0x20008000, LENGTH = 64
 }
+
+SECTIONS
+{
+  .uninit.clock (NOLOAD) : ALIGN(8)
+  {
+    KEEP(*(.uninit.clock .uninit.clock.*));
+  } > RETAINED
+} INSERT AFTER .bss;
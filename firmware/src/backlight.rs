@@ -0,0 +1,134 @@
+use core::cell::Cell;
+
+use embassy_nrf::pac;
+use embassy_nrf::peripherals::PWM0;
+use embassy_nrf::pwm::{Prescaler, SimplePwm};
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+
+use crate::settings::SettingsStore;
+
+/// PWM counts per period, at 16 MHz this is a 16 kHz period, far above visible flicker.
+const MAX_DUTY: u16 = 1000;
+
+/// Percent the level moves per step, so a full fade takes 20 steps.
+const FADE_PERCENT: u8 = 5;
+const FADE_STEP: Duration = Duration::from_millis(10);
+
+/// The backlight pins on port 0, handed to the PWM in `main`.
+const PINS: [usize; 3] = [14, 22, 23];
+
+#[derive(Clone, Copy, PartialEq, Eq, defmt::Format)]
+enum Mode {
+    Off,
+    /// A quarter of the brightness setting, shortly before the screen times out.
+    Dim,
+    On,
+}
+
+/// The level the backlight should fade to. The PWM itself is driven by [`backlight_task`].
+pub struct Backlight {
+    mode: Mutex<ThreadModeRawMutex, Cell<Mode>>,
+    /// The level the task last set, in percent.
+    level: Mutex<ThreadModeRawMutex, Cell<u8>>,
+    changed: Signal<ThreadModeRawMutex, ()>,
+    reached: Signal<ThreadModeRawMutex, ()>,
+}
+
+impl Backlight {
+    pub const fn new() -> Self {
+        Self {
+            mode: Mutex::new(Cell::new(Mode::Off)),
+            level: Mutex::new(Cell::new(0)),
+            changed: Signal::new(),
+            reached: Signal::new(),
+        }
+    }
+
+    /// Fade to the brightness from the settings, also after that setting has changed.
+    pub fn on(&self) {
+        self.set_mode(Mode::On);
+    }
+
+    pub fn dim(&self) {
+        self.set_mode(Mode::Dim);
+    }
+
+    /// Fade out, returning once the backlight is dark.
+    pub async fn off(&self) {
+        self.set_mode(Mode::Off);
+        while self.level.lock(|l| l.get()) != 0 {
+            self.reached.wait().await;
+        }
+    }
+
+    fn set_mode(&self, mode: Mode) {
+        self.mode.lock(|m| m.set(mode));
+        self.changed.signal(());
+    }
+}
+
+/// Fades the backlight towards the level for the current mode, one small step at a time.
+#[embassy_executor::task]
+pub async fn backlight_task(
+    mut pwm: SimplePwm<'static, PWM0>,
+    backlight: &'static Backlight,
+    settings: &'static SettingsStore,
+) {
+    pwm.set_prescaler(Prescaler::Div1);
+    pwm.set_max_duty(MAX_DUTY);
+    set_level(&mut pwm, 0);
+
+    let mut level = 0;
+    loop {
+        let brightness = settings.get().brightness.min(100);
+        let target = match backlight.mode.lock(|m| m.get()) {
+            Mode::Off => 0,
+            Mode::Dim => (brightness / 4).max(1),
+            Mode::On => brightness,
+        };
+        if level == target {
+            backlight.changed.wait().await;
+            continue;
+        }
+
+        level = if target > level {
+            (level + FADE_PERCENT).min(target)
+        } else {
+            level.saturating_sub(FADE_PERCENT).max(target)
+        };
+        set_level(&mut pwm, level);
+        backlight.level.lock(|l| l.set(level));
+        if level == target {
+            backlight.reached.signal(());
+        }
+        Timer::after(FADE_STEP).await;
+    }
+}
+
+/// The three backlight pins are active low and driven together. The PWM output is low for
+/// the duty counts of each period, and the level is squared so that equal steps look equal.
+///
+/// At 0 the PWM is stopped, so that it does not keep the high frequency clock running
+/// while the screen is off, and the pins are held high from their output latch instead.
+fn set_level(pwm: &mut SimplePwm<'static, PWM0>, percent: u8) {
+    if percent == 0 {
+        // Latch high before the PWM lets go of the pins, the latch is low otherwise
+        pac::P0.outset().write(|w| {
+            for pin in PINS {
+                w.set_pin(pin, true);
+            }
+        });
+        pwm.disable();
+        return;
+    }
+    if !pwm.is_enabled() {
+        pwm.enable();
+    }
+    let duty = (percent as u32 * percent as u32 * MAX_DUTY as u32 / 10_000) as u16;
+    for channel in 0..3 {
+        pwm.set_duty(channel, duty);
+    }
+}
@@ -0,0 +1,327 @@
+use core::cell::{Cell, RefCell};
+
+use defmt::{info, warn};
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::{NoopRawMutex, ThreadModeRawMutex};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::mutex::Mutex as AsyncMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Ticker, Timer};
+use watchful_core::battery::{ChargeState, Gauge, Status, DEFAULT_CURVE};
+use watchful_core::battery_calibration::{self, Curve, Discharge, Reading, CURVE_ENCODED_SIZE};
+use watchful_core::battery_history::{History, Sample, ENCODED_SIZE, LOG_MINUTES, SLOTS, SLOT_MINUTES};
+use watchful_core::records::{Cursor, RecordLog};
+use watchful_core::shell::Level;
+
+use crate::clock::Clock;
+use crate::device::Battery;
+use crate::events::{Event, EventBus};
+use crate::power::{Peripheral, Power};
+use crate::{storage, ExternalFlash};
+
+/// Time between samples, the charger pins are watched in between.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Time between samples while calibrating.
+const CALIBRATION_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Rough current drawn by peripherals while they are powered, for load compensation.
+const LOADS_MA: [(Peripheral, u16); 2] = [(Peripheral::Display, 20), (Peripheral::HeartSensor, 5)];
+
+/// The latest battery status, kept up to date by [`battery_task`], and its history logged
+/// by [`log_task`].
+pub struct BatteryMonitor {
+    status: Mutex<ThreadModeRawMutex, Cell<Option<Status>>>,
+    changed: Signal<ThreadModeRawMutex, ()>,
+    history: Mutex<ThreadModeRawMutex, RefCell<History>>,
+    log: AsyncMutex<ThreadModeRawMutex, RecordLog>,
+    /// The calibrated curve, `None` until the device has been calibrated.
+    curve: Mutex<ThreadModeRawMutex, Cell<Option<Curve>>>,
+    calibrating: Mutex<ThreadModeRawMutex, Cell<bool>>,
+    /// Start or cancel calibration, handled by [`calibration_task`].
+    calibration: Signal<ThreadModeRawMutex, bool>,
+}
+
+impl BatteryMonitor {
+    pub const fn new() -> Self {
+        Self {
+            status: Mutex::new(Cell::new(None)),
+            changed: Signal::new(),
+            history: Mutex::new(RefCell::new(History::new())),
+            log: AsyncMutex::new(RecordLog::new(
+                storage::BATTERY.start,
+                storage::BATTERY.end,
+                ENCODED_SIZE,
+            )),
+            curve: Mutex::new(Cell::new(None)),
+            calibrating: Mutex::new(Cell::new(false)),
+            calibration: Signal::new(),
+        }
+    }
+
+    /// `None` until the first sample has been taken.
+    pub fn get(&self) -> Option<Status> {
+        self.status.lock(|s| s.get())
+    }
+
+    /// Level in percent, 0 before the first sample.
+    pub fn percent(&self) -> u8 {
+        self.get().map_or(0, |s| s.percent)
+    }
+
+    pub fn is_charging(&self) -> bool {
+        self.get().is_some_and(|s| s.state == ChargeState::Charging)
+    }
+
+    /// Wait until the level or the charge state changes.
+    pub async fn wait_changed(&self) {
+        self.changed.wait().await
+    }
+
+    /// Whether the level comes from a calibrated curve rather than the default one.
+    pub fn is_calibrated(&self) -> bool {
+        self.curve.lock(|c| c.get().is_some())
+    }
+
+    /// Whether a charge cycle is being recorded to calibrate the battery curve.
+    pub fn is_calibrating(&self) -> bool {
+        self.calibrating.lock(|c| c.get())
+    }
+
+    /// Start recording a charge cycle, or cancel the recording. Calibration waits for a
+    /// full charge and finishes once the watch is put back on the charger nearly empty.
+    pub fn set_calibrating(&self, calibrating: bool) {
+        self.calibrating.lock(|c| c.set(calibrating));
+        self.calibration.signal(calibrating);
+    }
+
+    pub fn history(&self) -> History {
+        self.history.lock(|h| h.borrow().clone())
+    }
+
+    /// A cursor before the newest logged sample, for [`read_next`](Self::read_next).
+    pub async fn newest(&self) -> Cursor {
+        self.log.lock().await.newest()
+    }
+
+    /// Read the logged sample after `cursor`, the next older one, `None` after the oldest.
+    pub async fn read_next(
+        &self,
+        flash: &AsyncMutex<NoopRawMutex, ExternalFlash>,
+        cursor: &mut Cursor,
+    ) -> Option<Sample> {
+        let _flash_power = crate::POWER.acquire(Peripheral::Flash);
+        let log = self.log.lock().await;
+        let mut flash = flash.lock().await;
+        let mut record = [0; ENCODED_SIZE];
+        loop {
+            let len = log.read_next(&mut *flash, cursor, &mut record).await.ok().flatten()?;
+            if let Some(sample) = Sample::decode(&record[..len]) {
+                return Some(sample);
+            }
+        }
+    }
+}
+
+/// Samples the battery, and publishes the level and charge state whenever either changes.
+#[embassy_executor::task]
+pub async fn battery_task(
+    mut battery: Battery<'static>,
+    monitor: &'static BatteryMonitor,
+    events: &'static EventBus,
+    power: &'static Power,
+) {
+    let mut gauge = Gauge::new();
+    loop {
+        let load_ma = LOADS_MA
+            .iter()
+            .filter(|(p, _)| power.is_powered(*p))
+            .map(|(_, ma)| ma)
+            .sum();
+        let millivolts = battery.millivolts().await;
+        let curve = monitor.curve.lock(|c| c.get());
+        let curve = curve.as_ref().map_or(&DEFAULT_CURVE[..], |c| &c[..]);
+        let status = gauge.update(curve, millivolts, load_ma, battery.charge_state());
+
+        let previous = monitor.status.lock(|s| s.replace(Some(status)));
+        let changed = previous.is_none_or(|p| p.percent != status.percent || p.state != status.state);
+        if changed {
+            info!(
+                "[battery] {}% at {} mV, {:?}",
+                status.percent, status.millivolts, status.state
+            );
+            monitor.changed.signal(());
+            events.publish(Event::BatteryChanged {
+                level: status.percent,
+                charging: status.state == ChargeState::Charging,
+            });
+        }
+
+        select(Timer::after(SAMPLE_INTERVAL), battery.wait_charger()).await;
+    }
+}
+
+/// Restores the history from flash, then logs a sample every few minutes while the time is
+/// known.
+#[embassy_executor::task]
+pub async fn log_task(
+    monitor: &'static BatteryMonitor,
+    clock: &'static Clock,
+    flash: &'static AsyncMutex<NoopRawMutex, ExternalFlash>,
+    power: &'static Power,
+) {
+    {
+        let _flash_power = power.acquire(Peripheral::Flash);
+        let mut log = monitor.log.lock().await;
+        let mut flash = flash.lock().await;
+        if log.mount(&mut *flash).await.is_err() {
+            warn!("[battery] unable to mount log");
+        }
+        let mut history = History::new();
+        let mut newest = None;
+        let mut record = [0; ENCODED_SIZE];
+        let mut cursor = log.newest();
+        while let Ok(Some(len)) = log.read_next(&mut *flash, &mut cursor, &mut record).await {
+            let Some(sample) = Sample::decode(&record[..len]) else {
+                continue;
+            };
+            // Newest first, stop once past what the history can hold
+            let newest = *newest.get_or_insert(sample.time);
+            if sample.time <= newest - SLOTS as i64 * SLOT_MINUTES * 60 {
+                break;
+            }
+            history.load(&sample);
+        }
+        info!("[battery] restored history");
+        monitor.history.lock(|h| *h.borrow_mut() = history);
+    }
+
+    let mut ticker = Ticker::every(Duration::from_secs(LOG_MINUTES as u64 * 60));
+    loop {
+        ticker.next().await;
+        let Some(status) = monitor.get() else { continue };
+        let Some(now) = clock.timestamp() else { continue };
+        let sample = Sample::new(now, status);
+        monitor.history.lock(|h| h.borrow_mut().record(&sample));
+
+        let _flash_power = power.acquire(Peripheral::Flash);
+        let mut log = monitor.log.lock().await;
+        let mut flash = flash.lock().await;
+        if log.append(&mut *flash, &sample.encode()).await.is_err() {
+            warn!("[battery] unable to log sample");
+        }
+    }
+}
+
+/// Loads the calibrated curve, and while calibrating records the battery until a discharge
+/// from full to nearly empty can be fitted to a new curve.
+#[embassy_executor::task]
+pub async fn calibration_task(
+    monitor: &'static BatteryMonitor,
+    clock: &'static Clock,
+    flash: &'static AsyncMutex<NoopRawMutex, ExternalFlash>,
+    power: &'static Power,
+) {
+    let mut samples = RecordLog::new(
+        storage::BATTERY_CALIBRATION.start,
+        storage::BATTERY_CALIBRATION.end,
+        ENCODED_SIZE,
+    );
+    let mut curves = RecordLog::new(
+        storage::BATTERY_CURVE.start,
+        storage::BATTERY_CURVE.end,
+        CURVE_ENCODED_SIZE,
+    );
+    // The charge state of the newest sample, calibration is on while there are any
+    let mut last = None;
+    {
+        let _flash_power = power.acquire(Peripheral::Flash);
+        let mut flash = flash.lock().await;
+        if samples.mount(&mut *flash).await.is_err() || curves.mount(&mut *flash).await.is_err() {
+            warn!("[battery] unable to mount calibration logs");
+        }
+        let mut record = [0; CURVE_ENCODED_SIZE];
+        if let Ok(Some(len)) = curves.read(&mut *flash, 0, &mut record).await {
+            if let Some(curve) = battery_calibration::decode_curve(&record[..len]) {
+                info!("[battery] using calibrated curve");
+                monitor.curve.lock(|c| c.set(Some(curve)));
+            }
+        }
+        if let Ok(Some(len)) = samples.read(&mut *flash, 0, &mut record).await {
+            last = Sample::decode(&record[..len]).map(|s| s.state);
+            monitor.calibrating.lock(|c| c.set(true));
+        }
+    }
+
+    let mut ticker = Ticker::every(CALIBRATION_INTERVAL);
+    loop {
+        match select(ticker.next(), monitor.calibration.wait()).await {
+            Either::First(_) if !monitor.is_calibrating() => continue,
+            Either::First(_) => {}
+            // Started over, or cancelled
+            Either::Second(calibrating) => {
+                let _flash_power = power.acquire(Peripheral::Flash);
+                let mut flash = flash.lock().await;
+                if samples.clear(&mut *flash).await.is_err() {
+                    warn!("[battery] unable to clear calibration log");
+                }
+                last = None;
+                if !calibrating {
+                    continue;
+                }
+            }
+        }
+
+        let Some(status) = monitor.get() else { continue };
+        let Some(now) = clock.timestamp() else { continue };
+        let sample = Sample::new(now, status);
+        let _flash_power = power.acquire(Peripheral::Flash);
+        let mut flash = flash.lock().await;
+        if samples.append(&mut *flash, &sample.encode()).await.is_err() {
+            warn!("[battery] unable to log calibration sample");
+            continue;
+        }
+
+        // Back on the charger after discharging, see whether it went from full to empty
+        let discharged = last == Some(ChargeState::Discharging) && sample.state != ChargeState::Discharging;
+        last = Some(sample.state);
+        if !discharged {
+            continue;
+        }
+        let mut discharge = Discharge::new();
+        let mut from_full = false;
+        let mut record = [0; ENCODED_SIZE];
+        // Past the sample just logged, back to the start of the discharge
+        let mut cursor = samples.newest();
+        let _ = samples.read_next(&mut *flash, &mut cursor, &mut record).await;
+        while let Ok(Some(len)) = samples.read_next(&mut *flash, &mut cursor, &mut record).await {
+            let Some(sample) = Sample::decode(&record[..len]) else {
+                continue;
+            };
+            if sample.state != ChargeState::Discharging {
+                from_full = sample.state == ChargeState::Charged;
+                break;
+            }
+            discharge.push(Reading {
+                time: sample.time,
+                millivolts: sample.millivolts,
+            });
+        }
+        let Some(curve) = discharge.fit().filter(|_| from_full) else {
+            info!("[battery] incomplete discharge, calibration continues");
+            continue;
+        };
+        info!("[battery] calibrated curve: {:?}", curve);
+        crate::SHELL_LOG.log(Level::Info, format_args!("battery calibrated"));
+        if curves
+            .append(&mut *flash, &battery_calibration::encode_curve(&curve))
+            .await
+            .is_err()
+            || samples.clear(&mut *flash).await.is_err()
+        {
+            warn!("[battery] unable to store calibrated curve");
+        }
+        monitor.curve.lock(|c| c.set(Some(curve)));
+        monitor.calibrating.lock(|c| c.set(false));
+    }
+}
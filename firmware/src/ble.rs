@@ -1,7 +1,9 @@
 use defmt::{info, unwrap, warn};
 use embassy_executor::Spawner;
-use embassy_futures::select::{select, Either};
-use embassy_time::{with_timeout, Duration, Timer};
+use embassy_futures::select::{select, select4, Either, Either4};
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::mutex::Mutex;
+use embassy_time::{with_timeout, Duration, Instant, Ticker, Timer};
 use embedded_storage_async::nor_flash::NorFlash;
 use heapless::Vec;
 use nrf_dfu_target::prelude::{DfuRequest, DfuStatus, DfuTarget, FirmwareInfo, FirmwareType, HardwareInfo};
@@ -9,37 +11,38 @@ use static_cell::StaticCell;
 use trouble_host::attribute::Characteristic;
 use trouble_host::gatt::GattEvent;
 use trouble_host::prelude::*;
-
-use crate::device::Battery;
-use crate::DfuConfig;
+use watchful_core::battery_history;
+use watchful_core::shell::LineBuffer;
+use watchful_core::timezone::LocalTimeInformation;
+use watchful_core::weather::{WeatherMessage, MAX_MESSAGE_SIZE as WEATHER_MESSAGE_SIZE};
+use watchful_core::workout::MAX_ENCODED_SIZE;
+
+use crate::battery::BatteryMonitor;
+use crate::clock::TimeSource;
+use crate::events::Event;
+use crate::firmware_validator::FirmwareValidator;
+use crate::power::Peripheral;
+use crate::shell::{self, After};
+use crate::version;
+use crate::{DfuConfig, ExternalFlash};
 
 pub const ATT_MTU: usize = L2CAP_MTU - 4 - 3;
 
 type Target = DfuTarget<256>;
 type NrfController = nrf_sdc::SoftdeviceController<'static>;
 
-/*
+// Nordic UART service, carries the debug shell
 #[gatt_service(uuid = "6E400001-B5A3-F393-E0A9-E50E24DCCA9E")]
 pub struct NrfUartService {
+    /// Command lines, in as many writes as it takes
     #[characteristic(uuid = "6E400002-B5A3-F393-E0A9-E50E24DCCA9E", write)]
     rx: Vec<u8, ATT_MTU>,
 
+    /// Answers and log lines, in chunks of the ATT MTU
     #[characteristic(uuid = "6E400003-B5A3-F393-E0A9-E50E24DCCA9E", notify)]
     tx: Vec<u8, ATT_MTU>,
 }
 
-impl NrfUartService {
-    fn handle(&self, _connection: &mut ConnectionHandle, event: NrfUartServiceEvent) {
-        match event {
-            NrfUartServiceEvent::TxCccdWrite { notifications } => {
-                info!("Enable logging: {}", notifications);
-            }
-            _ => {}
-        }
-    }
-}
-*/
-
 // Battery service
 #[gatt_service(uuid = service::BATTERY)]
 struct BatteryService {
@@ -50,6 +53,102 @@ struct BatteryService {
     level: u8,
 }
 
+// Heart rate service, for gym apps and bike computers
+#[gatt_service(uuid = service::HEART_RATE)]
+struct HeartRateService {
+    /// Flags followed by the rate in beats per minute
+    #[characteristic(uuid = characteristic::HEART_RATE_MEASUREMENT, notify)]
+    measurement: [u8; 2],
+    #[characteristic(uuid = characteristic::BODY_SENSOR_LOCATION, read, value = BODY_SENSOR_WRIST)]
+    location: u8,
+}
+
+/// Heart rate as a single byte, sensor contact detection supported.
+const HEART_RATE_CONTACT_SUPPORTED: u8 = 0b100;
+const HEART_RATE_CONTACT_DETECTED: u8 = 0b010;
+const BODY_SENSOR_WRIST: u8 = 2;
+/// How often subscribed centrals are sent the heart rate while the sensor is on.
+const HEART_RATE_INTERVAL: Duration = Duration::from_secs(1);
+
+// Current time service, written to by GadgetBridge and other companion apps
+#[gatt_service(uuid = "1805")]
+struct CurrentTimeService {
+    /// Local time, notified when it is set on the watch
+    #[characteristic(uuid = "2A2B", read, write, notify)]
+    current_time: [u8; 10],
+    #[characteristic(uuid = "2A0F", read, write)]
+    local_time_information: [u8; 2],
+}
+
+/// Adjust reason flag of the current time characteristic for a time set by hand.
+const ADJUST_MANUAL: u8 = 0b0001;
+
+/// How often the time is read again from the phone while connected.
+const RESYNC_INTERVAL: Duration = Duration::from_secs(4 * 60 * 60);
+
+// Device information service, companion apps tell the firmware by it
+#[gatt_service(uuid = "180A")]
+struct DeviceInformationService {
+    #[characteristic(uuid = "2A29", read)]
+    manufacturer: Vec<u8, ATT_MTU>,
+    #[characteristic(uuid = "2A24", read)]
+    model: Vec<u8, ATT_MTU>,
+    #[characteristic(uuid = "2A25", read)]
+    serial: Vec<u8, ATT_MTU>,
+    #[characteristic(uuid = "2A27", read)]
+    hardware_revision: Vec<u8, ATT_MTU>,
+    #[characteristic(uuid = "2A26", read)]
+    firmware_revision: Vec<u8, ATT_MTU>,
+    #[characteristic(uuid = "2A28", read)]
+    software_revision: Vec<u8, ATT_MTU>,
+}
+
+const MANUFACTURER: &str = "PINE64";
+const MODEL: &str = "PineTime";
+const HARDWARE_REVISION: &str = "1.0a";
+
+// InfiniTime SimpleWeather service, written to by GadgetBridge and other companion apps
+#[gatt_service(uuid = "00050000-78fc-48fe-8e23-433b3a1942d0")]
+struct SimpleWeatherService {
+    #[characteristic(uuid = "00050001-78fc-48fe-8e23-433b3a1942d0", write)]
+    data: Vec<u8, WEATHER_MESSAGE_SIZE>,
+}
+
+// InfiniTime navigation service
+#[gatt_service(uuid = "00010000-78fc-48fe-8e23-433b3a1942d0")]
+struct NavigationService {
+    #[characteristic(uuid = "00010001-78fc-48fe-8e23-433b3a1942d0", write)]
+    icon: Vec<u8, 32>,
+    #[characteristic(uuid = "00010002-78fc-48fe-8e23-433b3a1942d0", write)]
+    narrative: Vec<u8, ATT_MTU>,
+    #[characteristic(uuid = "00010003-78fc-48fe-8e23-433b3a1942d0", write)]
+    distance: Vec<u8, 16>,
+    #[characteristic(uuid = "00010004-78fc-48fe-8e23-433b3a1942d0", write)]
+    progress: u8,
+}
+
+// Watchful workout export, see the README for the protocol
+#[gatt_service(uuid = "57a70000-8d8c-4c1f-9f3a-1c0e5e7b3d21")]
+struct WorkoutService {
+    /// Index of the session to download, 0 for the newest
+    #[characteristic(uuid = "57a70001-8d8c-4c1f-9f3a-1c0e5e7b3d21", write)]
+    control: u8,
+    /// The session in chunks, each prefixed with its offset
+    #[characteristic(uuid = "57a70002-8d8c-4c1f-9f3a-1c0e5e7b3d21", notify)]
+    data: Vec<u8, ATT_MTU>,
+}
+
+// Watchful battery log export, see the README for the protocol
+#[gatt_service(uuid = "57a70100-8d8c-4c1f-9f3a-1c0e5e7b3d21")]
+struct BatteryLogService {
+    /// Hours of samples to download, counted back from the newest
+    #[characteristic(uuid = "57a70101-8d8c-4c1f-9f3a-1c0e5e7b3d21", write)]
+    control: u8,
+    /// Whole samples, newest first, each chunk prefixed with the index of its first sample
+    #[characteristic(uuid = "57a70102-8d8c-4c1f-9f3a-1c0e5e7b3d21", notify)]
+    data: Vec<u8, ATT_MTU>,
+}
+
 #[gatt_service(uuid = "FE59")]
 pub struct NrfDfuService {
     #[characteristic(uuid = "8EC90001-F315-4F60-9FB8-838830DAEA50", write, notify)]
@@ -62,6 +161,9 @@ pub struct NrfDfuService {
     packet: Vec<u8, ATT_MTU>,
 }
 
+/// Bytes of firmware received between progress events.
+const DFU_PROGRESS_STEP: u32 = 4096;
+
 // NOTE: Disabled as it doesn't properly deal with init/start specific to infinitime protocol
 //#[gatt_service(uuid = "23D1BCEA-5F78-2315-DEEF-121230150000")]
 //pub struct InfinitimeDfuService {
@@ -82,8 +184,15 @@ pub struct NrfDfuService {
 pub struct PineTimeServer {
     nrfdfu: NrfDfuService,
     battery: BatteryService,
+    heart_rate: HeartRateService,
+    weather: SimpleWeatherService,
+    navigation: NavigationService,
+    workouts: WorkoutService,
+    battery_log: BatteryLogService,
+    current_time: CurrentTimeService,
+    device_information: DeviceInformationService,
     //   infdfu: InfinitimeDfuService,
-    // uart: NrfUartService,
+    uart: NrfUartService,
 }
 
 impl PineTimeServer<'_> {
@@ -138,6 +247,239 @@ impl PineTimeServer<'_> {
         Some(status)
     }
 
+    /// Send the heart rate to subscribed centrals, only while the sensor is on.
+    pub async fn notify_heart_rate(&self, connection: &Connection<'static>) {
+        if !crate::HEART.is_running() {
+            return;
+        }
+        let value = match crate::HEART.get() {
+            Some(estimate) => [HEART_RATE_CONTACT_SUPPORTED | HEART_RATE_CONTACT_DETECTED, estimate.bpm],
+            None => [HEART_RATE_CONTACT_SUPPORTED, 0],
+        };
+        if let Err(e) = self.heart_rate.measurement.notify(self, connection, &value).await {
+            warn!("[ble] error notifying heart rate: {:?}", e);
+        }
+    }
+
+    /// Notify subscribers of the battery service of a new level.
+    pub async fn notify_battery(&self, connection: &Connection<'static>, level: u8) {
+        if let Err(e) = self.battery.level.notify(self, connection, &level.min(100)).await {
+            warn!("[ble] error notifying battery level: {:?}", e);
+        }
+    }
+
+    /// Send the requested workout session to the central.
+    pub async fn export_workout(
+        &self,
+        connection: &Connection<'static>,
+        flash: &'static Mutex<NoopRawMutex, ExternalFlash>,
+    ) {
+        let n = unwrap!(self.workouts.control.get(self));
+        let mut record = [0; MAX_ENCODED_SIZE];
+        let len = crate::WORKOUTS.read(flash, n as usize, &mut record).await.unwrap_or(0);
+        info!("[ble] exporting workout {}, {} bytes", n, len);
+
+        let mut offset = 0;
+        loop {
+            let end = (offset + ATT_MTU - 2).min(len);
+            let mut chunk: Vec<u8, ATT_MTU> = Vec::new();
+            unwrap!(chunk.extend_from_slice(&(offset as u16).to_le_bytes()));
+            unwrap!(chunk.extend_from_slice(&record[offset..end]));
+            if let Err(e) = self.workouts.data.notify(self, connection, &chunk).await {
+                warn!("[ble] error exporting workout: {:?}", e);
+                return;
+            }
+            // The final chunk is empty
+            if offset == len {
+                break;
+            }
+            offset = end;
+        }
+    }
+
+    /// Send the logged battery samples of the requested number of hours to the central.
+    pub async fn export_battery_log(
+        &self,
+        connection: &Connection<'static>,
+        battery: &BatteryMonitor,
+        flash: &'static Mutex<NoopRawMutex, ExternalFlash>,
+    ) {
+        let hours = unwrap!(self.battery_log.control.get(self));
+        info!("[ble] exporting {} hours of battery log", hours);
+
+        let mut cursor = battery.newest().await;
+        let mut index = 0;
+        let mut newest = None;
+        let mut done = false;
+        loop {
+            let mut chunk: Vec<u8, ATT_MTU> = Vec::new();
+            unwrap!(chunk.extend_from_slice(&(index as u16).to_le_bytes()));
+            while !done && chunk.len() + battery_history::ENCODED_SIZE <= ATT_MTU {
+                let Some(sample) = battery.read_next(flash, &mut cursor).await else {
+                    done = true;
+                    break;
+                };
+                let newest = *newest.get_or_insert(sample.time);
+                if sample.time <= newest - hours as i64 * 3600 {
+                    done = true;
+                    break;
+                }
+                unwrap!(chunk.extend_from_slice(&sample.encode()));
+                index += 1;
+            }
+            let last = chunk.len() == 2;
+            if let Err(e) = self.battery_log.data.notify(self, connection, &chunk).await {
+                warn!("[ble] error exporting battery log: {:?}", e);
+                return;
+            }
+            // The final chunk is empty
+            if last {
+                break;
+            }
+        }
+    }
+
+    /// Fill in the device information service, it never changes while running.
+    pub fn set_device_information(&self) {
+        // The device identifier in hex
+        let id = device_id();
+        let serial: [u8; 16] = core::array::from_fn(|i| b"0123456789ABCDEF"[(id >> (60 - 4 * i)) as usize & 0xf]);
+        let service = &self.device_information;
+        let values = [
+            (&service.manufacturer, MANUFACTURER.as_bytes()),
+            (&service.model, MODEL.as_bytes()),
+            (&service.serial, &serial[..]),
+            (&service.hardware_revision, HARDWARE_REVISION.as_bytes()),
+            (&service.firmware_revision, version::VERSION.as_bytes()),
+            (&service.software_revision, version::COMMIT.as_bytes()),
+        ];
+        for (characteristic, value) in values {
+            let value = unwrap!(Vec::from_slice(&value[..value.len().min(ATT_MTU)]));
+            if let Err(_) = characteristic.set(self, &value) {
+                warn!("[ble] error setting device information");
+            }
+        }
+    }
+
+    /// Run the command lines completed by a write to the shell, returning whether to reboot.
+    pub async fn handle_shell(
+        &self,
+        connection: &Connection<'static>,
+        lines: &mut LineBuffer,
+        battery: &BatteryMonitor,
+        validator: &FirmwareValidator<'static>,
+    ) -> bool {
+        let data = unwrap!(self.uart.rx.get(self));
+        let mut reboot = false;
+        for byte in data {
+            let Some(line) = lines.push(byte) else {
+                continue;
+            };
+            let mut out = shell::Output::new();
+            match line.and_then(watchful_core::shell::parse) {
+                Ok(command) => reboot |= shell::execute(command, battery, validator, &mut out).await == After::Reboot,
+                Err(e) => shell::error(e, &mut out),
+            }
+            self.notify_shell(connection, out.as_bytes()).await;
+        }
+        reboot
+    }
+
+    /// Send text to the shell, split to fit the negotiated MTU.
+    pub async fn notify_shell(&self, connection: &Connection<'static>, text: &[u8]) {
+        let size = watchful_core::shell::chunk_size(connection.att_mtu(), ATT_MTU);
+        for chunk in text.chunks(size) {
+            let chunk = unwrap!(Vec::from_slice(chunk));
+            if let Err(e) = self.uart.tx.notify(self, connection, &chunk).await {
+                warn!("[ble] error notifying shell: {:?}", e);
+                return;
+            }
+        }
+    }
+
+    /// Update the current time service with the time on the watch before a central reads it.
+    /// While the time is not known the current time keeps its initial value, all zeros,
+    /// which the specification reads as an unknown date.
+    pub fn update_current_time(&self, handle: u16) {
+        let service = &self.current_time;
+        if handle == service.current_time.handle {
+            let Some(now) = crate::CLOCK.get() else { return };
+            if let Err(_) = service.current_time.set(self, &encode_time(now, 0)) {
+                warn!("[ble] error updating current time");
+            }
+        } else if handle == service.local_time_information.handle {
+            // Without the time, whether daylight saving time applies is a guess
+            let now = crate::CLOCK.timestamp().unwrap_or(0);
+            let info = LocalTimeInformation::new(crate::CLOCK.zone(), now);
+            if let Err(_) = service.local_time_information.set(self, &info.encode()) {
+                warn!("[ble] error updating local time information");
+            }
+        }
+    }
+
+    pub fn handle_current_time(&self, handle: u16) {
+        let service = &self.current_time;
+        if handle == service.current_time.handle {
+            let data = unwrap!(service.current_time.get(self));
+            match parse_time(data) {
+                Some(time) => {
+                    crate::CLOCK.sync(time, TimeSource::Write);
+                    crate::EVENTS.publish(Event::TimeSynced);
+                }
+                None => warn!("[ble] invalid current time: {:?}", data),
+            }
+        } else if handle == service.local_time_information.handle {
+            let data = unwrap!(service.local_time_information.get(self));
+            match LocalTimeInformation::parse(&data) {
+                Some(info) => {
+                    info!("[ble] local time information: {:?}", info);
+                    crate::CLOCK.set_zone(info.apply(crate::CLOCK.zone()));
+                }
+                None => warn!("[ble] invalid local time information: {:?}", data),
+            }
+        }
+    }
+
+    /// Tell subscribers the time was set by hand on the watch.
+    pub async fn notify_current_time(&self, connection: &Connection<'static>) {
+        let Some(now) = crate::CLOCK.get() else { return };
+        let value = encode_time(now, ADJUST_MANUAL);
+        if let Err(e) = self.current_time.current_time.notify(self, connection, &value).await {
+            warn!("[ble] error notifying current time: {:?}", e);
+        }
+    }
+
+    pub fn handle_weather(&self) {
+        let data = unwrap!(self.weather.data.get(self));
+        match WeatherMessage::parse(&data) {
+            Ok(message) => {
+                info!("[ble] weather update: {:?}", message);
+                // Reports are only shown while recent, which needs the time
+                match crate::CLOCK.get() {
+                    Some(now) => crate::WEATHER.update(message, now),
+                    None => warn!("[ble] weather update before the time is known"),
+                }
+            }
+            Err(e) => warn!("[ble] invalid weather message: {:?}", e),
+        }
+    }
+
+    pub fn handle_navigation(&self, handle: u16) {
+        let navigation = &self.navigation;
+        if handle == navigation.icon.handle {
+            let data = unwrap!(navigation.icon.get(self));
+            crate::NAVIGATION.set_icon(utf8_prefix(&data));
+        } else if handle == navigation.narrative.handle {
+            let data = unwrap!(navigation.narrative.get(self));
+            crate::NAVIGATION.set_narrative(utf8_prefix(&data));
+        } else if handle == navigation.distance.handle {
+            let data = unwrap!(navigation.distance.get(self));
+            crate::NAVIGATION.set_distance(utf8_prefix(&data));
+        } else if handle == navigation.progress.handle {
+            crate::NAVIGATION.set_progress(unwrap!(navigation.progress.get(self)));
+        }
+    }
+
     pub async fn handle<DFU: NorFlash>(
         &self,
         target: &mut Target,
@@ -157,6 +499,21 @@ impl PineTimeServer<'_> {
         //        } else if handle == self.infdfu.packet.handle {
         //            self.handle_dfu_packet(target, dfu, connection, &self.infdfu.control, &self.infdfu.packet)
         //                .await
+        } else if handle == self.weather.data.handle {
+            self.handle_weather();
+            None
+        } else if handle == self.current_time.current_time.handle
+            || handle == self.current_time.local_time_information.handle
+        {
+            self.handle_current_time(handle);
+            None
+        } else if handle == self.navigation.icon.handle
+            || handle == self.navigation.narrative.handle
+            || handle == self.navigation.distance.handle
+            || handle == self.navigation.progress.handle
+        {
+            self.handle_navigation(handle);
+            None
         } else {
             // Ignore, no need to handle
             None
@@ -164,8 +521,8 @@ impl PineTimeServer<'_> {
     }
 }
 
-/// Size of L2CAP packets
-pub const L2CAP_MTU: usize = 27;
+/// Size of L2CAP packets, large enough for a complete SimpleWeather message in a single write
+pub const L2CAP_MTU: usize = 64;
 pub const L2CAP_TXQ: u8 = 10;
 pub const L2CAP_RXQ: u8 = 10;
 
@@ -175,6 +532,12 @@ type BleResources = HostResources<CONNECTIONS_MAX, L2CAP_CHANNELS_MAX, L2CAP_MTU
 static RESOURCES: StaticCell<BleResources> = StaticCell::new();
 static STACK: StaticCell<Stack<'static, NrfController>> = StaticCell::new();
 
+/// The unique device identifier programmed into the chip by Nordic.
+fn device_id() -> u64 {
+    let ficr = embassy_nrf::pac::FICR;
+    u64::from(ficr.deviceid(1).read()) << 32 | u64::from(ficr.deviceid(0).read())
+}
+
 fn ble_addr() -> Address {
     let ficr = embassy_nrf::pac::FICR;
     let high = u64::from((ficr.deviceaddr(1).read() & 0x0000ffff) | 0x0000c000);
@@ -188,7 +551,9 @@ pub fn start(
     spawner: Spawner,
     controller: NrfController,
     dfu_config: DfuConfig<'static>,
-    battery: &'static Battery<'static>,
+    validator: FirmwareValidator<'static>,
+    battery: &'static BatteryMonitor,
+    flash: &'static Mutex<NoopRawMutex, ExternalFlash>,
 ) {
     let resources = RESOURCES.init(BleResources::new());
     let stack = STACK.init(trouble_host::new(controller, resources).set_random_address(ble_addr()));
@@ -203,9 +568,12 @@ pub fn start(
     ),));
     static SERVER: StaticCell<PineTimeServer<'static>> = StaticCell::new();
     let server = SERVER.init(gatt);
+    server.set_device_information();
 
     spawner.must_spawn(ble_task(runner));
-    spawner.must_spawn(advertise_task(stack, peripheral, server, dfu_config, battery));
+    spawner.must_spawn(advertise_task(
+        stack, peripheral, server, dfu_config, validator, battery, flash,
+    ));
 }
 
 #[embassy_executor::task]
@@ -219,15 +587,18 @@ async fn advertise_task(
     mut peripheral: Peripheral<'static, NrfController>,
     server: &'static PineTimeServer<'static>,
     mut dfu_config: DfuConfig<'static>,
-    battery: &'static Battery<'static>,
+    validator: FirmwareValidator<'static>,
+    battery: &'static BatteryMonitor,
+    flash: &'static Mutex<NoopRawMutex, ExternalFlash>,
 ) {
     const BAS: [u8; 2] = [0x0F, 0x18];
+    const HRS: [u8; 2] = [0x0D, 0x18];
     const DFU: [u8; 2] = [0x59, 0xFE];
     let mut advertiser_data = [0; 31];
     unwrap!(AdStructure::encode_slice(
         &[
             AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
-            AdStructure::ServiceUuids16(&[Uuid::Uuid16(BAS), Uuid::Uuid16(DFU)]),
+            AdStructure::ServiceUuids16(&[Uuid::Uuid16(BAS), Uuid::Uuid16(HRS), Uuid::Uuid16(DFU)]),
             AdStructure::CompleteLocalName(NAME.as_bytes()),
         ],
         &mut advertiser_data[..],
@@ -246,7 +617,7 @@ async fn advertise_task(
                 .await
         );
         match advertiser.accept().await {
-            Ok(conn) => process(stack, conn, server, &mut dfu_config, battery).await,
+            Ok(conn) => process(stack, conn, server, &mut dfu_config, &validator, battery, flash).await,
             Err(e) => {
                 warn!("Error advertising: {:?}", e);
             }
@@ -259,7 +630,30 @@ async fn process(
     connection: Connection<'static>,
     server: &'static PineTimeServer<'_>,
     dfu_config: &mut DfuConfig<'static>,
-    battery: &'static Battery<'static>,
+    validator: &FirmwareValidator<'static>,
+    battery: &'static BatteryMonitor,
+    flash: &'static Mutex<NoopRawMutex, ExternalFlash>,
+) {
+    crate::EVENTS.publish(Event::Connected);
+    // A time set by hand while disconnected is no news to this central
+    crate::CLOCK.reset_adjusted();
+
+    // Synchronize time for as long as the connection lasts
+    let sync = async {
+        sync_time(stack, &connection).await;
+        core::future::pending::<()>().await
+    };
+    select(serve(&connection, server, dfu_config, validator, battery, flash), sync).await;
+}
+
+/// Answer the central until it disconnects.
+async fn serve(
+    connection: &Connection<'static>,
+    server: &'static PineTimeServer<'_>,
+    dfu_config: &mut DfuConfig<'static>,
+    validator: &FirmwareValidator<'static>,
+    battery: &'static BatteryMonitor,
+    flash: &'static Mutex<NoopRawMutex, ExternalFlash>,
 ) {
     let ficr = embassy_nrf::pac::FICR;
     let part = ficr.info().part().read().part().to_bits();
@@ -275,46 +669,107 @@ async fn process(
 
     let fw_info = FirmwareInfo {
         ftype: FirmwareType::Application,
-        version: 1,
+        version: version::NUMBER,
         addr: 0,
         len: 0,
     };
 
-    // Synchronize time
-    let s = Spawner::for_current_executor().await;
-    s.must_spawn(sync_time(stack, connection.clone()));
-
     let mut dfu = dfu_config.dfu();
     let mut target = DfuTarget::new(dfu.size(), fw_info, hw_info);
 
+    let mut heart_rate = Ticker::every(HEART_RATE_INTERVAL);
+    let mut dfu_received = 0;
+    let mut shell_lines = LineBuffer::new();
+    // Keeps the flash awake from the first DFU write until the connection goes away
+    let mut dfu_power = None;
     loop {
-        let event = connection.next().await;
+        let event = match select4(
+            connection.next(),
+            heart_rate.next(),
+            battery.wait_changed(),
+            select(crate::CLOCK.wait_adjusted(), crate::SHELL_LOG.next()),
+        )
+        .await
+        {
+            Either4::First(event) => event,
+            Either4::Second(_) => {
+                server.notify_heart_rate(connection).await;
+                continue;
+            }
+            Either4::Third(_) => {
+                server.notify_battery(connection, battery.percent()).await;
+                continue;
+            }
+            Either4::Fourth(Either::First(_)) => {
+                server.notify_current_time(connection).await;
+                continue;
+            }
+            Either4::Fourth(Either::Second(line)) => {
+                server.notify_shell(connection, line.as_bytes()).await;
+                server.notify_shell(connection, b"\n").await;
+                continue;
+            }
+        };
         match event {
             ConnectionEvent::Disconnected { reason } => {
                 defmt::info!("[ble] disconnected: {:?}", reason);
+                crate::EVENTS.publish(Event::Disconnected);
                 break;
             }
             ConnectionEvent::Gatt { data } => match data.process(server).await {
                 Ok(Some(GattEvent::Read(event))) => {
                     let handle = event.handle();
                     if handle == server.battery.level.handle {
-                        let value = battery.measure().await.max(100) as u8;
+                        let value = battery.percent().min(100);
                         if let Err(_) = server.battery.level.set(server, &value) {
                             warn!("error updating battery level");
                         }
                     }
+                    server.update_current_time(handle);
                     let reply = unwrap!(event.accept());
                     reply.send().await;
                 }
                 Ok(Some(GattEvent::Write(event))) => {
                     let handle = event.handle();
+                    if handle == server.nrfdfu.control.handle || handle == server.nrfdfu.packet.handle {
+                        dfu_power.get_or_insert_with(|| crate::POWER.acquire(Peripheral::Flash));
+                    }
                     let reply = unwrap!(event.accept());
-                    let result = server.handle(&mut target, &mut dfu, &connection, handle).await;
+                    let result = server.handle(&mut target, &mut dfu, connection, handle).await;
                     reply.send().await;
 
+                    if handle == server.nrfdfu.packet.handle {
+                        let received = dfu_received + unwrap!(server.nrfdfu.packet.get(server)).len() as u32;
+                        // Let the UI know every few kilobytes, not for every packet
+                        if received / DFU_PROGRESS_STEP != dfu_received / DFU_PROGRESS_STEP {
+                            crate::EVENTS.publish(Event::DfuProgress { received });
+                        }
+                        dfu_received = received;
+                    }
+
+                    if handle == server.workouts.control.handle {
+                        server.export_workout(connection, flash).await;
+                    }
+
+                    if handle == server.battery_log.control.handle {
+                        server.export_battery_log(connection, battery, flash).await;
+                    }
+
+                    if handle == server.uart.rx.handle
+                        && server
+                            .handle_shell(connection, &mut shell_lines, battery, validator)
+                            .await
+                    {
+                        info!("[ble] rebooting from the shell");
+                        Timer::after(Duration::from_secs(1)).await;
+                        crate::CLOCK.retain();
+                        cortex_m::peripheral::SCB::sys_reset();
+                    }
+
                     if let Some(DfuStatus::DoneReset) = result {
                         warn!("DFU done! Supposed to reset!");
                         Timer::after(Duration::from_secs(4)).await;
+                        crate::CLOCK.retain();
                         cortex_m::peripheral::SCB::sys_reset();
                     }
                 }
@@ -324,39 +779,113 @@ async fn process(
     }
 }
 
-#[embassy_executor::task]
-async fn sync_time(stack: &'static Stack<'static, NrfController>, conn: Connection<'static>) {
+async fn sync_time(stack: &'static Stack<'static, NrfController>, conn: &Connection<'static>) {
     info!("[ble] synchronizing time");
-    let client = unwrap!(GattClient::<_, 10, ATT_MTU>::new(stack, &conn).await);
-    match select(
-        client.task(),
-        with_timeout(Duration::from_secs(8), async {
-            let services = client.services_by_uuid(&Uuid::new_short(0x1805)).await?;
-            if let Some(service) = services.first() {
-                let c: Characteristic<u8> = client
-                    .characteristic_by_uuid(&service, &Uuid::new_short(0x2a2b))
-                    .await?;
-
-                let mut data = [0; 10];
-                client.read_characteristic(&c, &mut data[..]).await?;
-
-                if let Some(time) = parse_time(data) {
-                    crate::CLOCK.set(time);
-                }
+    let client = match GattClient::<_, 10, ATT_MTU>::new(stack, conn).await {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("[ble] unable to start gatt client: {:?}", e);
+            return;
+        }
+    };
+    match select(client.task(), follow_time(&client, conn)).await {
+        Either::First(_) => info!("[ble] gatt client exited"),
+        Either::Second(Ok(_)) => info!("[ble] time sync completed"),
+        Either::Second(Err(e)) => warn!("[ble] time sync error: {:?}", e),
+    }
+}
+
+/// Read the time from the phone's current time service, then keep it in sync from its
+/// notifications and by reading it again every [`RESYNC_INTERVAL`] until disconnected.
+async fn follow_time(
+    client: &GattClient<'_, NrfController, 10, ATT_MTU>,
+    conn: &Connection<'static>,
+) -> Result<(), BleHostError<nrf_sdc::Error>> {
+    let found = with_timeout(Duration::from_secs(8), async {
+        let services = client.services_by_uuid(&Uuid::new_short(0x1805)).await?;
+        let Some(service) = services.first() else {
+            return Ok(None);
+        };
+        // The zone first, the current time is local time
+        if let Ok(c) = client
+            .characteristic_by_uuid::<u8>(service, &Uuid::new_short(0x2a0f))
+            .await
+        {
+            let mut data = [0; 2];
+            client.read_characteristic(&c, &mut data[..]).await?;
+            if let Some(info) = LocalTimeInformation::parse(&data) {
+                info!("[ble] local time information: {:?}", info);
+                crate::CLOCK.set_zone(info.apply(crate::CLOCK.zone()));
             }
-            Ok::<(), BleHostError<nrf_sdc::Error>>(())
-        }),
-    )
-    .await
-    {
-        Either::First(_) => panic!("[ble] gatt client exited prematurely"),
-        Either::Second(Ok(_)) => {
-            info!("[ble] time sync completed");
         }
-        Either::Second(Err(e)) => {
-            warn!("[ble] time sync error: {:?}", e);
+        let c: Characteristic<u8> = client.characteristic_by_uuid(service, &Uuid::new_short(0x2a2b)).await?;
+        read_time(client, &c).await?;
+        Ok::<_, BleHostError<nrf_sdc::Error>>(Some(c))
+    })
+    .await;
+    let c = match found {
+        Ok(result) => match result? {
+            Some(c) => c,
+            None => return Ok(()),
+        },
+        Err(_) => {
+            warn!("[ble] time sync timed out");
+            return Ok(());
+        }
+    };
+
+    // Not every phone notifies, resyncing covers those
+    let mut listener = match client.subscribe(&c, false).await {
+        Ok(listener) => Some(listener),
+        Err(e) => {
+            warn!("[ble] unable to subscribe to current time: {:?}", e);
+            None
+        }
+    };
+    while conn.is_connected() {
+        let since = crate::CLOCK.last_sync().map_or(Instant::now(), |(at, _)| at);
+        let notified = async {
+            match listener.as_mut() {
+                Some(listener) => listener.next().await,
+                None => core::future::pending().await,
+            }
+        };
+        match select(Timer::at(since + RESYNC_INTERVAL), notified).await {
+            Either::First(_) => read_time(client, &c).await?,
+            Either::Second(notification) => {
+                let data = notification.as_ref().get(..10).and_then(|data| data.try_into().ok());
+                match data.and_then(parse_time) {
+                    Some(time) => {
+                        crate::CLOCK.sync(time, TimeSource::Notification);
+                        crate::EVENTS.publish(Event::TimeSynced);
+                    }
+                    None => warn!("[ble] invalid current time notification"),
+                }
+            }
         }
     }
+    Ok(())
+}
+
+async fn read_time(
+    client: &GattClient<'_, NrfController, 10, ATT_MTU>,
+    c: &Characteristic<u8>,
+) -> Result<(), BleHostError<nrf_sdc::Error>> {
+    let mut data = [0; 10];
+    client.read_characteristic(c, &mut data[..]).await?;
+    if let Some(time) = parse_time(data) {
+        crate::CLOCK.sync(time, TimeSource::Read);
+        crate::EVENTS.publish(Event::TimeSynced);
+    }
+    Ok(())
+}
+
+/// The longest valid UTF-8 prefix, phones may cut strings in the middle of a character.
+fn utf8_prefix(data: &[u8]) -> &str {
+    match core::str::from_utf8(data) {
+        Ok(s) => s,
+        Err(e) => unwrap!(core::str::from_utf8(&data[..e.valid_up_to()])),
+    }
 }
 
 fn parse_time(data: [u8; 10]) -> Option<time::PrimitiveDateTime> {
@@ -380,3 +909,20 @@ fn parse_time(data: [u8; 10]) -> Option<time::PrimitiveDateTime> {
     }
     None
 }
+
+/// Encode `time` as the current time characteristic, with the given adjust reason flags.
+fn encode_time(time: time::PrimitiveDateTime, adjust_reason: u8) -> [u8; 10] {
+    let year = (time.year() as u16).to_le_bytes();
+    [
+        year[0],
+        year[1],
+        time.month() as u8,
+        time.day(),
+        time.hour(),
+        time.minute(),
+        time.second(),
+        time.weekday().number_from_monday(),
+        (time.microsecond() as u64 * 256 / 1_000_000) as u8,
+        adjust_reason,
+    ]
+}
@@ -1,43 +1,267 @@
-use core::cell::RefCell;
-use core::ops::Add;
+use core::cell::{Cell, RefCell};
+use core::mem::MaybeUninit;
+use core::ptr::{addr_of, addr_of_mut};
 
+use defmt::info;
 use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
 use embassy_sync::blocking_mutex::Mutex;
-use embassy_time::{Duration, Ticker};
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Ticker};
+use watchful_core::clock::TimeBase;
+use watchful_core::drift::Drift;
+use watchful_core::shell::Level;
+use watchful_core::timezone::TimeZone;
 
+use crate::settings::SettingsStore;
+
+/// Marks the snapshot in [`RETAINED`] as written by us rather than left over in RAM.
+const MARKER: u32 = 0x7761_7463;
+
+/// How often [`retain_task`] snapshots the time. A reset that is not seen coming, such as
+/// the watchdog, loses at most this much.
+const RETAIN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The time as of the last [`Clock::retain`], in RAM the runtime does not initialize, so that
+/// it survives resets that keep RAM powered: the reset menu, a panic, the watchdog or holding
+/// the button. After power loss it holds garbage, which the marker and check catch.
+///
+/// With the bootloader the section gets RAM of its own, see `memory-img.x`, since the
+/// bootloader runs in between and may clear the application's RAM.
+#[link_section = ".uninit.clock"]
+static mut RETAINED: MaybeUninit<Snapshot> = MaybeUninit::uninit();
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Snapshot {
+    marker: u32,
+    /// Seconds since 1970 at `ticks`.
+    time: i64,
+    /// RTC ticks when the snapshot was taken.
+    ticks: u64,
+    drift_ppm: i32,
+    check: u32,
+}
+
+impl Snapshot {
+    fn checksum(&self) -> u32 {
+        let time = self.time as u64;
+        self.marker
+            ^ (time as u32)
+            ^ ((time >> 32) as u32).rotate_left(8)
+            ^ (self.ticks as u32).rotate_left(16)
+            ^ ((self.ticks >> 32) as u32).rotate_left(24)
+            ^ self.drift_ppm as u32
+    }
+}
+
+/// Keeps the time in UTC, local time follows the time zone in the settings.
+///
+/// The time is counted from the RTC: it is the time when the clock was last set plus the
+/// RTC time since, corrected for the drift measured between syncs with the phone.
 pub struct Clock {
-    time: Mutex<ThreadModeRawMutex, RefCell<time::PrimitiveDateTime>>,
+    state: Mutex<ThreadModeRawMutex, RefCell<TimeBase>>,
+    /// When and how the time was last synchronized with a phone.
+    last_sync: Mutex<ThreadModeRawMutex, Cell<Option<(Instant, TimeSource)>>>,
+    /// Signalled when the time is set by hand on the watch.
+    adjusted: Signal<ThreadModeRawMutex, ()>,
+    settings: &'static SettingsStore,
+}
+
+/// Where a synchronized time came from.
+#[derive(Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum TimeSource {
+    /// Read from the phone's Current Time Service.
+    Read,
+    /// Notified by the phone's Current Time Service.
+    Notification,
+    /// Written to the watch's Current Time Service.
+    Write,
+}
+
+impl TimeSource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Notification => "notification",
+            Self::Write => "write",
+        }
+    }
+}
+
+/// The date and time at `unix_us` microseconds since 1970, `None` past the dates `time`
+/// can represent.
+fn datetime(unix_us: i64) -> Option<time::PrimitiveDateTime> {
+    let time = time::OffsetDateTime::from_unix_timestamp_nanos(unix_us as i128 * 1000).ok()?;
+    Some(time::PrimitiveDateTime::new(time.date(), time.time()))
+}
+
+fn unix_us(time: time::PrimitiveDateTime) -> i64 {
+    (time.assume_utc().unix_timestamp_nanos() / 1000) as i64
+}
+
+fn now_us() -> i64 {
+    Instant::now().as_micros() as i64
 }
 
 impl Clock {
-    pub const fn new() -> Self {
+    pub const fn new(settings: &'static SettingsStore) -> Self {
         Self {
-            time: Mutex::new(RefCell::new(time::PrimitiveDateTime::MIN)),
+            state: Mutex::new(RefCell::new(TimeBase::new())),
+            last_sync: Mutex::new(Cell::new(None)),
+            adjusted: Signal::new(),
+            settings,
+        }
+    }
+
+    /// Set the local time by hand.
+    pub fn set(&self, local: time::PrimitiveDateTime) {
+        let utc = self.to_utc(local);
+        self.state.lock(|s| s.borrow_mut().drift.reset());
+        self.set_utc(utc);
+        self.adjusted.signal(());
+    }
+
+    /// Wait until the time is set by hand.
+    pub async fn wait_adjusted(&self) {
+        self.adjusted.wait().await
+    }
+
+    /// Forget that the time was set by hand, for a new connection that only needs to hear
+    /// about what happens from now on.
+    pub fn reset_adjusted(&self) {
+        self.adjusted.reset();
+    }
+
+    /// Set the local time from a phone, which also tells how far the clock drifted.
+    pub fn sync(&self, local: time::PrimitiveDateTime, source: TimeSource) {
+        let utc = self.to_utc(local);
+        info!("[clock] synchronized by {:?}", source);
+        crate::SHELL_LOG.log(Level::Info, format_args!("clock synchronized by {}", source.name()));
+        let now = now_us();
+        if let Some(current) = self.state.lock(|s| s.borrow().utc_us(now)) {
+            let error = unix_us(utc) - current;
+            let changed = self.state.lock(|s| s.borrow_mut().drift.sync(now, error));
+            if changed {
+                info!("[clock] {} us off, drift now {} ppm", error, self.drift_ppm());
+            }
+        }
+        self.set_utc(utc);
+        self.last_sync.lock(|s| s.set(Some((Instant::now(), source))));
+    }
+
+    /// When the time was last synchronized with a phone, and how. `None` if it has not been
+    /// since boot.
+    pub fn last_sync(&self) -> Option<(Instant, TimeSource)> {
+        self.last_sync.lock(|s| s.get())
+    }
+
+    pub fn set_utc(&self, time: time::PrimitiveDateTime) {
+        let now = now_us();
+        self.state.lock(|s| s.borrow_mut().set(now, unix_us(time)));
+        self.retain();
+    }
+
+    fn to_utc(&self, local: time::PrimitiveDateTime) -> time::PrimitiveDateTime {
+        let local_seconds = local.assume_utc().unix_timestamp();
+        let offset = local_seconds - self.zone().to_utc(local_seconds);
+        local - time::Duration::seconds(offset)
+    }
+
+    /// How much faster real time runs than the RTC, in parts per million.
+    pub fn drift_ppm(&self) -> i32 {
+        self.state.lock(|s| s.borrow().drift.ppm())
+    }
+
+    pub fn zone(&self) -> TimeZone {
+        self.settings.get().time_zone
+    }
+
+    /// Change the time zone, the UTC time stays as it is.
+    pub fn set_zone(&self, zone: TimeZone) {
+        if zone != self.zone() {
+            self.settings.update(|s| s.time_zone = zone);
+        }
+    }
+
+    /// Pick up the time kept over a warm reset. Returns whether there was one.
+    pub fn restore(&self) -> bool {
+        // Every bit pattern is a valid snapshot, the marker and check tell whether it is ours
+        let snapshot = unsafe { core::ptr::read_volatile(addr_of!(RETAINED).cast::<Snapshot>()) };
+        if snapshot.marker != MARKER || snapshot.check != snapshot.checksum() {
+            return false;
         }
+        // The RTC normally starts over on reset, but if it kept counting the time since the
+        // snapshot is known as well
+        let now = Instant::now().as_ticks();
+        let elapsed = now.checked_sub(snapshot.ticks).unwrap_or(0) / embassy_time::TICK_HZ;
+        let Ok(time) = time::OffsetDateTime::from_unix_timestamp(snapshot.time + elapsed as i64) else {
+            return false;
+        };
+        self.state
+            .lock(|s| s.borrow_mut().drift = Drift::new(snapshot.drift_ppm));
+        self.set_utc(time::PrimitiveDateTime::new(time.date(), time.time()));
+        true
+    }
+
+    /// Snapshot the time for [`Clock::restore`] after a reset. [`retain_task`] does this
+    /// regularly, call it right before resetting on purpose.
+    ///
+    /// Only from thread mode. Does nothing while the clock is borrowed, so that it can be
+    /// called when panicking.
+    pub fn retain(&self) {
+        let now = Instant::now();
+        let retained = self.state.lock(|s| {
+            let s = s.try_borrow().ok()?;
+            Some((s.utc_us(now.as_micros() as i64)?, s.drift.ppm()))
+        });
+        let Some((utc_us, drift_ppm)) = retained else {
+            return;
+        };
+        let mut snapshot = Snapshot {
+            marker: MARKER,
+            time: utc_us.div_euclid(1_000_000),
+            ticks: now.as_ticks(),
+            drift_ppm,
+            check: 0,
+        };
+        snapshot.check = snapshot.checksum();
+        unsafe { core::ptr::write_volatile(addr_of_mut!(RETAINED).cast::<Snapshot>(), snapshot) };
+    }
+
+    /// Today's date, `None` while the time is not known.
+    pub fn today(&self) -> Option<time::Date> {
+        self.get().map(|now| now.date())
+    }
+
+    /// The local time, `None` while the time is not known.
+    pub fn get(&self) -> Option<time::PrimitiveDateTime> {
+        self.get_in(self.zone())
     }
 
-    pub fn set(&self, time: time::PrimitiveDateTime) {
-        self.time.lock(|f| *f.borrow_mut() = time)
+    /// The time in another `zone`, `None` while the time is not known.
+    pub fn get_in(&self, zone: TimeZone) -> Option<time::PrimitiveDateTime> {
+        let now = now_us();
+        datetime(self.state.lock(|s| s.borrow().local_us(now, zone))?)
     }
 
-    pub fn get(&self) -> time::PrimitiveDateTime {
-        self.time.lock(|f| f.borrow().clone())
+    /// UTC, `None` while the time is not known.
+    pub fn utc(&self) -> Option<time::PrimitiveDateTime> {
+        let now = now_us();
+        datetime(self.state.lock(|s| s.borrow().utc_us(now))?)
     }
 
-    fn add(&self, duration: time::Duration) {
-        self.time.lock(|f| {
-            let mut val = f.borrow_mut();
-            *val = val.add(duration);
-        })
+    /// Seconds since 1970, `None` while the time is not known.
+    pub fn timestamp(&self) -> Option<i64> {
+        self.utc().map(|utc| utc.assume_utc().unix_timestamp())
     }
 }
 
+/// Keeps the snapshot for [`Clock::restore`] recent.
 #[embassy_executor::task]
-pub async fn clock(clock: &'static Clock) {
-    const TICK: Duration = Duration::from_secs(1);
-    let mut ticker = Ticker::every(TICK);
+pub async fn retain_task(clock: &'static Clock) {
+    let mut ticker = Ticker::every(RETAIN_INTERVAL);
     loop {
         ticker.next().await;
-        clock.add(time::Duration::seconds(1));
+        clock.retain();
     }
 }
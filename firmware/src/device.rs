@@ -2,19 +2,36 @@ use display_interface_spi::SPIInterface;
 use embassy_embedded_hal::shared_bus::blocking::i2c::I2cDevice;
 use embassy_embedded_hal::shared_bus::blocking::spi::SpiDevice;
 use embassy_futures::select::{select, Either};
-use embassy_nrf::gpio::{AnyPin, Input, Level, Output, OutputDrive};
+use embassy_nrf::gpio::{Input, Output};
 use embassy_nrf::peripherals::{TWISPI0, TWISPI1};
 use embassy_nrf::spim::Spim;
 use embassy_nrf::{saadc, twim};
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::mutex::Mutex;
-use embassy_time::{Duration, Timer};
+use embassy_time::{Duration, Instant, Timer};
+use embedded_hal::delay::DelayNs;
+use mipidsi::dcs::{SetDisplayOff, SetDisplayOn};
 use mipidsi::models::ST7789;
+use watchful_core::battery::ChargeState;
 
+use crate::backlight::Backlight;
+use crate::battery::BatteryMonitor;
 use crate::clock::Clock;
+use crate::events::EventBus;
 use crate::firmware_validator::FirmwareValidator;
-
-pub type Touchpad<'a> = cst816s::CST816S<I2cDevice<'a, NoopRawMutex, twim::Twim<'a, TWISPI1>>, Input<'a>, Output<'a>>;
+use crate::heart::{HeartMonitor, HeartRate};
+use crate::motion::Motion;
+use crate::navigation::Navigation;
+use crate::power::{Peripheral, Power, PowerGuard};
+use crate::settings::SettingsStore;
+use crate::sleep::Sleep;
+use crate::steps::Steps;
+use crate::vibrator::Vibrator;
+use crate::weather::Weather;
+use crate::workouts::Workouts;
+use crate::ExternalFlash;
+
+pub type Touchpad<'a> = cst816s::Cst816s<I2cDevice<'a, NoopRawMutex, twim::Twim<'a, TWISPI1>>, Input<'a>, Output<'a>>;
 pub type Hrs<'a> = hrs3300::Hrs3300<I2cDevice<'a, NoopRawMutex, twim::Twim<'a, TWISPI1>>>;
 pub type Display<'a> = mipidsi::Display<
     SPIInterface<SpiDevice<'a, NoopRawMutex, Spim<'a, TWISPI0>, Output<'a>>, Output<'a>>,
@@ -24,13 +41,22 @@ pub type Display<'a> = mipidsi::Display<
 
 pub struct Device<'a> {
     pub clock: &'a Clock,
+    pub weather: &'a Weather,
+    pub navigation: &'a Navigation,
+    pub steps: &'a Steps,
+    pub motion: &'a Motion,
+    pub settings: &'a SettingsStore,
+    pub sleep: &'a Sleep,
     pub screen: Screen<'static>,
-    pub button: Button,
-    pub battery: &'a Battery<'static>,
-    pub touchpad: Touchpad<'static>,
-    pub hrs: Hrs<'static>,
+    pub backlight: &'a Backlight,
+    pub battery: &'a BatteryMonitor,
+    pub events: &'a EventBus,
+    pub heart: &'a HeartRate,
+    pub heart_monitor: &'a HeartMonitor,
+    pub workouts: &'a Workouts,
+    pub flash: &'a Mutex<NoopRawMutex, ExternalFlash>,
     pub firmware_validator: FirmwareValidator<'static>,
-    pub vibrator: Vibrator<'static>,
+    pub vibrator: &'a Vibrator,
 }
 
 impl<'a> Device<'a> {}
@@ -49,6 +75,7 @@ impl Button {
             match select(Timer::after(Duration::from_secs(8)), self.pin.wait_for_falling_edge()).await {
                 Either::First(_) => {
                     if self.pin.is_high() {
+                        crate::CLOCK.retain();
                         cortex_m::peripheral::SCB::sys_reset();
                     }
                 }
@@ -58,164 +85,143 @@ impl Button {
     }
 }
 
+/// The battery voltage and charger pins, interpreted by the [`battery`](crate::battery)
+/// task.
 pub struct Battery<'a> {
+    adc: saadc::Saadc<'a, 1>,
+    /// Low while charging.
     charging: Input<'a>,
-    adc: Mutex<NoopRawMutex, saadc::Saadc<'a, 1>>,
+    /// Low while on the charger.
+    power_present: Input<'a>,
 }
 
 impl<'a> Battery<'a> {
-    pub fn new(adc: saadc::Saadc<'a, 1>, charging: Input<'a>) -> Self {
+    pub fn new(adc: saadc::Saadc<'a, 1>, charging: Input<'a>, power_present: Input<'a>) -> Self {
         Self {
-            adc: Mutex::new(adc),
+            adc,
             charging,
+            power_present,
         }
     }
 
-    pub async fn measure(&self) -> u32 {
+    pub async fn millivolts(&mut self) -> u16 {
         let mut buf = [0i16; 1];
-        let mut adc = self.adc.lock().await;
-        adc.sample(&mut buf).await;
-        let voltage = buf[0] as u32 * (8 * 600) / 1024;
-        //let voltage = buf[0] as u32 * 2000 / 1241;
-        approximate_charge(voltage)
-    }
-
-    pub fn is_charging(&self) -> bool {
-        self.charging.is_low()
-    }
-}
-
-pub struct Vibrator<'a> {
-    motor: Output<'a>,
-}
-
-impl<'a> Vibrator<'a> {
-    pub fn new(motor: Output<'a>) -> Self {
-        Self { motor }
+        self.adc.sample(&mut buf).await;
+        (buf[0].max(0) as u32 * (8 * 600) / 1024) as u16
     }
 
-    pub async fn on_for(&mut self, ms: u64) {
-        self.motor.set_low();
-        Timer::after(Duration::from_millis(ms)).await;
-        self.motor.set_high();
+    pub fn charge_state(&self) -> ChargeState {
+        ChargeState::new(self.power_present.is_low(), self.charging.is_low())
     }
 
-    pub fn off(&mut self) {
-        self.motor.set_high();
+    /// Wait for the charger to be connected or disconnected, or to finish charging.
+    pub async fn wait_charger(&mut self) {
+        select(
+            self.charging.wait_for_any_edge(),
+            self.power_present.wait_for_any_edge(),
+        )
+        .await;
     }
 }
 
-pub enum BacklightLevel {
-    Low,
-    Medium,
-    High,
+pub struct Screen<'a> {
+    display: Display<'a>,
+    backlight: &'a Backlight,
+    power: &'a Power,
+    display_power: Option<PowerGuard<'a>>,
+    touch_power: Option<PowerGuard<'a>>,
+    panel: Panel,
 }
 
-pub struct Backlight<'a> {
-    low: Output<'a>,
-    med: Output<'a>,
-    high: Output<'a>,
-    level: BacklightLevel,
+/// Power state of the ST7789 controller. It keeps its frame memory while asleep, but the
+/// states redraw on wake anyway since the view may have changed.
+#[derive(Clone, Copy, PartialEq)]
+enum Panel {
+    /// Scanning out the frame memory.
+    On,
+    /// Awake with the output off, so a new frame can be drawn before it is shown.
+    Off,
+    /// Sleep-in since the given instant, oscillator and scanning stopped.
+    Asleep(Instant),
 }
 
-impl<'a> Backlight<'a> {
-    pub fn new(low_pin: AnyPin, med_pin: AnyPin, high_pin: AnyPin) -> Self {
-        let backlight_low = Output::new(low_pin, Level::High, OutputDrive::Standard); // Low backlight
-        let backlight_med = Output::new(med_pin, Level::High, OutputDrive::Standard); // Medium backlight
-        let backlight_high = Output::new(high_pin, Level::High, OutputDrive::Standard); // High backlight
-        Self {
-            low: backlight_low,
-            med: backlight_med,
-            high: backlight_high,
-            level: BacklightLevel::Medium,
-        }
-    }
-
-    fn set_level(&mut self, level: BacklightLevel) {
-        self.level = level;
-    }
-
-    fn off(&mut self) {
-        self.low.set_high();
-        self.med.set_high();
-        self.high.set_high();
-    }
-
-    fn on(&mut self) {
-        match self.level {
-            BacklightLevel::Low => self.set_low(),
-            BacklightLevel::Medium => self.set_medium(),
-            BacklightLevel::High => self.set_high(),
-        }
-    }
-
-    fn set_low(&mut self) {
-        self.low.set_low();
-        self.med.set_high();
-        self.high.set_high();
-    }
-
-    fn set_medium(&mut self) {
-        self.low.set_high();
-        self.med.set_low();
-        self.high.set_high();
-    }
+/// The controller needs this long after sleep-in before it accepts sleep-out.
+const SLEEP_IN_DELAY: Duration = Duration::from_millis(120);
+/// And this long after sleep-out before it accepts sleep-in again.
+const SLEEP_OUT_DELAY: Duration = Duration::from_millis(120);
 
-    fn set_high(&mut self) {
-        self.low.set_high();
-        self.med.set_high();
-        self.high.set_low();
-    }
-}
+/// Lets [`mipidsi::Display::sleep`] and [`mipidsi::Display::wake`] return right away, the
+/// screen waits for the controller with a timer instead of blocking the executor.
+struct NoDelay;
 
-pub struct Screen<'a> {
-    display: Display<'a>,
-    backlight: Backlight<'a>,
+impl DelayNs for NoDelay {
+    fn delay_ns(&mut self, _ns: u32) {}
 }
 
 impl<'a> Screen<'a> {
-    pub fn new(display: Display<'a>, backlight: Backlight<'a>) -> Self {
-        Self { display, backlight }
+    pub fn new(display: Display<'a>, backlight: &'a Backlight, power: &'a Power) -> Self {
+        Self {
+            display,
+            backlight,
+            power,
+            display_power: None,
+            touch_power: None,
+            panel: Panel::On,
+        }
     }
 
-    pub fn display(&mut self) -> &mut Display<'a> {
+    pub async fn display(&mut self) -> &mut Display<'a> {
+        // Drawing goes over the SPI bus, even while the backlight is still off
+        let power = self.power;
+        self.display_power
+            .get_or_insert_with(|| power.acquire(Peripheral::Display));
+        self.wake().await;
         &mut self.display
     }
 
-    pub fn on(&mut self) {
+    pub async fn on(&mut self) {
+        let power = self.power;
+        self.display_power
+            .get_or_insert_with(|| power.acquire(Peripheral::Display));
+        self.touch_power.get_or_insert_with(|| power.acquire(Peripheral::Touch));
+        self.wake().await;
+        if self.panel == Panel::Off {
+            // Safety: a single command that leaves the controller state as mipidsi expects it,
+            // mipidsi has no call for turning the output on and off
+            if unsafe { self.display.dcs() }.write_command(SetDisplayOn).is_err() {
+                defmt::warn!("[screen] unable to turn display on");
+            }
+            self.panel = Panel::On;
+        }
         self.backlight.on();
     }
 
-    pub fn off(&mut self) {
-        self.backlight.off();
-    }
-
-    pub fn change_brightness(&mut self) {
-        match self.backlight.level {
-            BacklightLevel::Low => self.backlight.set_level(BacklightLevel::Medium),
-            BacklightLevel::Medium => self.backlight.set_level(BacklightLevel::High),
-            BacklightLevel::High => self.backlight.set_level(BacklightLevel::Low),
+    /// Fade out the backlight, then put the panel to sleep.
+    pub async fn off(&mut self) {
+        self.backlight.off().await;
+        if !matches!(self.panel, Panel::Asleep(_)) {
+            let _spi = self.power.acquire(Peripheral::Display);
+            // Safety: see `on`
+            let display_off = unsafe { self.display.dcs() }.write_command(SetDisplayOff);
+            if display_off.is_err() || self.display.sleep(&mut NoDelay).is_err() {
+                defmt::warn!("[screen] unable to put display to sleep");
+            }
+            self.panel = Panel::Asleep(Instant::now());
         }
+        self.display_power = None;
+        self.touch_power = None;
     }
-}
 
-fn approximate_charge(voltage_millis: u32) -> u32 {
-    let level_approx = &[(3500, 0), (3616, 3), (3723, 22), (3776, 48), (3979, 79), (4180, 100)];
-    let approx = |value| {
-        if value < level_approx[0].0 {
-            level_approx[0].1
-        } else {
-            let mut ret = level_approx[level_approx.len() - 1].1;
-            for i in 1..level_approx.len() {
-                let prev = level_approx[i - 1];
-                let val = level_approx[i];
-                if value < val.0 {
-                    ret = prev.1 + (value - prev.0) * (val.1 - prev.1) / (val.0 - prev.0);
-                    break;
-                }
+    /// Bring the controller out of sleep with its output still off, so the next frame can
+    /// be drawn before [`on`](Self::on) shows it.
+    async fn wake(&mut self) {
+        if let Panel::Asleep(since) = self.panel {
+            Timer::at(since + SLEEP_IN_DELAY).await;
+            if self.display.wake(&mut NoDelay).is_err() {
+                defmt::warn!("[screen] unable to wake display");
             }
-            ret
+            Timer::after(SLEEP_OUT_DELAY).await;
+            self.panel = Panel::Off;
         }
-    };
-    approx(voltage_millis)
+    }
 }
@@ -0,0 +1,82 @@
+use defmt::warn;
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::channel::Channel;
+use watchful_ui::TouchGesture;
+
+use crate::device::Button;
+
+/// Events queued before the UI gets to them, anything beyond is dropped.
+const QUEUE_SIZE: usize = 8;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Event {
+    /// The side button was pressed.
+    Button,
+    Touch(TouchGesture),
+    /// A phone connected over BLE.
+    Connected,
+    Disconnected,
+    /// The clock was set from the phone.
+    TimeSynced,
+    BatteryChanged {
+        level: u8,
+        charging: bool,
+    },
+    Alarm,
+    Notification,
+    /// Bytes of a firmware update received so far.
+    DfuProgress {
+        received: u32,
+    },
+}
+
+impl defmt::Format for Event {
+    fn format(&self, fmt: defmt::Formatter) {
+        match self {
+            Self::Button => defmt::write!(fmt, "Button"),
+            Self::Touch(_) => defmt::write!(fmt, "Touch"),
+            Self::Connected => defmt::write!(fmt, "Connected"),
+            Self::Disconnected => defmt::write!(fmt, "Disconnected"),
+            Self::TimeSynced => defmt::write!(fmt, "TimeSynced"),
+            Self::BatteryChanged { level, charging } => {
+                defmt::write!(fmt, "BatteryChanged({}%, charging {})", level, charging)
+            }
+            Self::Alarm => defmt::write!(fmt, "Alarm"),
+            Self::Notification => defmt::write!(fmt, "Notification"),
+            Self::DfuProgress { received } => defmt::write!(fmt, "DfuProgress({})", received),
+        }
+    }
+}
+
+/// Events from input devices and background tasks, consumed by the UI state machine.
+pub struct EventBus {
+    channel: Channel<ThreadModeRawMutex, Event, QUEUE_SIZE>,
+}
+
+impl EventBus {
+    pub const fn new() -> Self {
+        Self {
+            channel: Channel::new(),
+        }
+    }
+
+    /// Queue an event for the UI, without waiting for room in the queue.
+    pub fn publish(&self, event: Event) {
+        if self.channel.try_send(event).is_err() {
+            warn!("[events] queue full, dropping {:?}", event);
+        }
+    }
+
+    /// Wait for the next event.
+    pub async fn next(&self) -> Event {
+        self.channel.receive().await
+    }
+}
+
+#[embassy_executor::task]
+pub async fn button_task(mut button: Button, events: &'static EventBus) {
+    loop {
+        button.wait().await;
+        events.publish(Event::Button);
+    }
+}
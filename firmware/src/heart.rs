@@ -0,0 +1,239 @@
+use core::cell::RefCell;
+
+use defmt::{info, warn};
+use embassy_sync::blocking_mutex::raw::{NoopRawMutex, ThreadModeRawMutex};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::mutex::Mutex as AsyncMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Ticker, Timer};
+use watchful_core::heart_history::{Alert, Alerts, History};
+use watchful_core::ppg::{Estimate, HeartRateEstimator, MIN_CONFIDENCE, MIN_SKIN_LEVEL, SAMPLE_RATE};
+
+use crate::clock::Clock;
+use crate::device::Hrs;
+use crate::power::{Peripheral, Power};
+use crate::settings::SettingsStore;
+
+struct State {
+    /// Number of [`Measurement`]s alive.
+    users: u8,
+    running: bool,
+    estimate: Option<Estimate>,
+}
+
+/// Heart rate measured by the HRS3300, for as long as anyone needs it.
+pub struct HeartRate {
+    state: Mutex<ThreadModeRawMutex, RefCell<State>>,
+    wake: Signal<ThreadModeRawMutex, ()>,
+    updated: Signal<ThreadModeRawMutex, ()>,
+}
+
+impl HeartRate {
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(RefCell::new(State {
+                users: 0,
+                running: false,
+                estimate: None,
+            })),
+            wake: Signal::new(),
+            updated: Signal::new(),
+        }
+    }
+
+    /// Turn on the sensor until the returned measurement is dropped.
+    pub fn measure(&self) -> Measurement<'_> {
+        self.state.lock(|s| s.borrow_mut().users += 1);
+        self.wake.signal(());
+        Measurement { heart: self }
+    }
+
+    /// Whether the sensor is on.
+    pub fn is_running(&self) -> bool {
+        self.state.lock(|s| s.borrow().running)
+    }
+
+    /// The latest estimate while the sensor is on.
+    pub fn get(&self) -> Option<Estimate> {
+        self.state.lock(|s| s.borrow().estimate)
+    }
+
+    /// Wait for the next change of the estimate.
+    pub async fn wait_updated(&self) -> Option<Estimate> {
+        self.updated.wait().await;
+        self.get()
+    }
+
+    fn users(&self) -> u8 {
+        self.state.lock(|s| s.borrow().users)
+    }
+
+    fn publish(&self, running: bool, estimate: Option<Estimate>) {
+        let changed = self.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            let changed = s.running != running || s.estimate != estimate;
+            s.running = running;
+            s.estimate = estimate;
+            changed
+        });
+        if changed {
+            self.updated.signal(());
+        }
+    }
+}
+
+/// Keeps the heart rate sensor on while alive.
+pub struct Measurement<'a> {
+    heart: &'a HeartRate,
+}
+
+impl Drop for Measurement<'_> {
+    fn drop(&mut self) {
+        self.heart.state.lock(|s| s.borrow_mut().users -= 1);
+        self.heart.wake.signal(());
+    }
+}
+
+/// Samples the sensor at the rate the estimator needs whenever a measurement is running.
+#[embassy_executor::task]
+pub async fn heart_task(
+    heart: &'static HeartRate,
+    hrs: &'static AsyncMutex<NoopRawMutex, Hrs<'static>>,
+    power: &'static Power,
+) {
+    loop {
+        while heart.users() == 0 {
+            heart.wake.wait().await;
+        }
+
+        let mut hrs = hrs.lock().await;
+        let sensor_power = power.acquire(Peripheral::HeartSensor);
+        let started = hrs.init().is_ok()
+            && hrs.set_conversion_delay(hrs3300::ConversionDelay::Ms12_5).is_ok()
+            && hrs.enable_hrs().is_ok()
+            && hrs.enable_oscillator().is_ok();
+        if !started {
+            warn!("[heart] unable to start sensor");
+            let _ = hrs.disable_hrs();
+            drop(sensor_power);
+            drop(hrs);
+            Timer::after(Duration::from_secs(1)).await;
+            continue;
+        }
+        info!("[heart] sensor on");
+        heart.publish(true, None);
+
+        let mut estimator = HeartRateEstimator::new();
+        let mut estimate = None;
+        let mut ticker = Ticker::every(Duration::from_hz(SAMPLE_RATE as u64));
+        while heart.users() > 0 {
+            match hrs.read_hrs() {
+                Ok(raw) => {
+                    if let Some(e) = estimator.update(raw) {
+                        estimate = Some(e);
+                        heart.publish(true, estimate);
+                    } else if raw < MIN_SKIN_LEVEL && estimate.is_some() {
+                        // Taken off the wrist
+                        estimate = None;
+                        heart.publish(true, None);
+                    }
+                }
+                Err(_) => {
+                    warn!("[heart] error reading sensor");
+                    estimator.reset();
+                }
+            }
+            ticker.next().await;
+        }
+
+        let _ = hrs.disable_oscillator();
+        let _ = hrs.disable_hrs();
+        info!("[heart] sensor off");
+        heart.publish(false, None);
+    }
+}
+
+/// How long a background reading may take to settle on a confident estimate.
+const READING_TIMEOUT: Duration = Duration::from_secs(30);
+/// Confident estimates in a row needed for a background reading.
+const READING_ESTIMATES: u8 = 3;
+
+/// Heart rate taken in the background every few minutes, when turned on in settings.
+pub struct HeartMonitor {
+    history: Mutex<ThreadModeRawMutex, RefCell<History>>,
+    alert: Signal<ThreadModeRawMutex, Alert>,
+}
+
+impl HeartMonitor {
+    pub const fn new() -> Self {
+        Self {
+            history: Mutex::new(RefCell::new(History::new())),
+            alert: Signal::new(),
+        }
+    }
+
+    pub fn history(&self) -> History {
+        self.history.lock(|h| h.borrow().clone())
+    }
+
+    /// Wait for a resting heart rate out of the configured range.
+    pub async fn wait_alert(&self) -> Alert {
+        self.alert.wait().await
+    }
+}
+
+/// Takes a short reading whenever the configured interval has passed, unless the sensor is
+/// already in use.
+#[embassy_executor::task]
+pub async fn monitor_task(
+    heart: &'static HeartRate,
+    monitor: &'static HeartMonitor,
+    settings: &'static SettingsStore,
+    clock: &'static Clock,
+) {
+    let mut alerts = Alerts::new();
+    let mut minutes = 0;
+    let mut ticker = Ticker::every(Duration::from_secs(60));
+    loop {
+        ticker.next().await;
+        let settings = settings.get();
+        minutes += 1;
+        if settings.heart_interval == 0 || minutes < settings.heart_interval || heart.is_running() {
+            continue;
+        }
+        minutes = 0;
+
+        let Some(bpm) = reading(heart).await else {
+            info!("[heart] no background reading");
+            continue;
+        };
+        info!("[heart] background reading {} bpm", bpm);
+        if let Some(now) = clock.timestamp() {
+            monitor.history.lock(|h| h.borrow_mut().record(now, bpm));
+        }
+        if let Some(alert) = alerts.check(bpm, settings.heart_low, settings.heart_high) {
+            warn!("[heart] resting heart rate alert {:?}", alert);
+            monitor.alert.signal(alert);
+        }
+    }
+}
+
+async fn reading(heart: &HeartRate) -> Option<u8> {
+    let _measurement = heart.measure();
+    let deadline = Instant::now() + READING_TIMEOUT;
+    let mut ticker = Ticker::every(Duration::from_secs(1));
+    let mut confident = 0;
+    while Instant::now() < deadline {
+        ticker.next().await;
+        match heart.get().filter(|e| e.confidence >= MIN_CONFIDENCE) {
+            Some(estimate) => {
+                confident += 1;
+                if confident >= READING_ESTIMATES {
+                    return Some(estimate.bpm);
+                }
+            }
+            None => confident = 0,
+        }
+    }
+    None
+}
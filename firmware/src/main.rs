@@ -6,7 +6,6 @@ use core::cell::RefCell;
 
 use defmt::unwrap;
 use defmt_rtt as _;
-use device::Backlight;
 use display_interface_spi::SPIInterface;
 use embassy_embedded_hal::flash::partition::Partition;
 use embassy_embedded_hal::shared_bus::blocking::i2c::I2cDevice;
@@ -18,7 +17,7 @@ use embassy_nrf::peripherals::{RNG, TWISPI0, TWISPI1};
 use embassy_nrf::spim::Spim;
 use embassy_nrf::spis::MODE_3;
 use embassy_nrf::twim::Twim;
-use embassy_nrf::{bind_interrupts, peripherals, rng, saadc, spim, twim};
+use embassy_nrf::{bind_interrupts, peripherals, pwm, rng, saadc, spim, twim};
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::blocking_mutex::Mutex as BMutex;
 use embassy_sync::mutex::Mutex;
@@ -32,13 +31,29 @@ use static_cell::StaticCell;
 
 use crate::firmware_validator::FirmwareValidator;
 
+mod backlight;
+mod battery;
 mod ble;
 mod clock;
 mod device;
+mod events;
 mod firmware_validator;
+mod heart;
+mod motion;
+mod navigation;
+mod power;
+mod settings;
+mod shell;
+mod sleep;
 mod state;
-use crate::clock::clock;
-use crate::device::{Battery, Button, Device, Hrs, Screen, Vibrator};
+mod steps;
+mod storage;
+mod touch;
+mod version;
+mod vibrator;
+mod weather;
+mod workouts;
+use crate::device::{Battery, Button, Device, Hrs, Screen};
 use crate::state::WatchState;
 
 bind_interrupts!(struct Irqs {
@@ -53,8 +68,23 @@ bind_interrupts!(struct Irqs {
     RTC0 => mpsl::HighPrioInterruptHandler;
 });
 
-static CLOCK: clock::Clock = clock::Clock::new();
-static BATTERY: StaticCell<device::Battery<'static>> = StaticCell::new();
+static CLOCK: clock::Clock = clock::Clock::new(&SETTINGS);
+static WEATHER: weather::Weather = weather::Weather::new();
+static NAVIGATION: navigation::Navigation = navigation::Navigation::new();
+static STEPS: steps::Steps = steps::Steps::new();
+static MOTION: motion::Motion = motion::Motion::new();
+static SETTINGS: settings::SettingsStore = settings::SettingsStore::new();
+static SLEEP: sleep::Sleep = sleep::Sleep::new();
+static EVENTS: events::EventBus = events::EventBus::new();
+static TOUCH: touch::Touch = touch::Touch::new();
+static POWER: power::Power = power::Power::new();
+static BACKLIGHT: backlight::Backlight = backlight::Backlight::new();
+static VIBRATOR: vibrator::Vibrator = vibrator::Vibrator::new();
+static BATTERY: battery::BatteryMonitor = battery::BatteryMonitor::new();
+static HEART: heart::HeartRate = heart::HeartRate::new();
+static HEART_MONITOR: heart::HeartMonitor = heart::HeartMonitor::new();
+static WORKOUTS: workouts::Workouts = workouts::Workouts::new();
+static SHELL_LOG: shell::ShellLog = shell::ShellLog::new();
 
 type ExternalFlash = XtFlash<SpiDevice<'static, NoopRawMutex, Spim<'static, TWISPI0>, Output<'static>>>;
 
@@ -70,7 +100,18 @@ use core::panic::PanicInfo;
 #[inline(never)]
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
-    cortex_m::peripheral::SCB::sys_reset();
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    use cortex_m::peripheral::scb::VectActive;
+    use cortex_m::peripheral::SCB;
+
+    // Keep the time over the reset. The clock is only for thread mode, and is retained once
+    // in case that panics too.
+    static RETAINED: AtomicBool = AtomicBool::new(false);
+    if SCB::vect_active() == VectActive::ThreadMode && !RETAINED.swap(true, Ordering::Relaxed) {
+        CLOCK.retain();
+    }
+    SCB::sys_reset();
 }
 
 #[embassy_executor::task]
@@ -138,7 +179,10 @@ async fn main(s: Spawner) {
 
     let sdc = unwrap!(build_sdc(sdc_p, rng, mpsl, sdc_mem));
 
-    s.must_spawn(clock(&CLOCK));
+    if CLOCK.restore() {
+        defmt::info!("Time kept over reset: {}", defmt::Debug2Format(&CLOCK.get()));
+    }
+    s.must_spawn(clock::retain_task(&CLOCK));
 
     // Battery measurement
     let mut bat_config = saadc::ChannelConfig::single_ended(p.P0_31);
@@ -149,8 +193,13 @@ async fn main(s: Spawner) {
     let mut adc_config = saadc::Config::default();
     adc_config.resolution = saadc::Resolution::_10BIT;
     let saadc = saadc::Saadc::new(p.SAADC, Irqs, adc_config, [bat_config]);
-    let battery = Battery::new(saadc, Input::new(p.P0_12.degrade(), Pull::Up));
-    let battery = BATTERY.init(battery);
+    // Charging indication on P0.12 and power presence on P0.19, both active low
+    let battery = Battery::new(
+        saadc,
+        Input::new(p.P0_12.degrade(), Pull::Up),
+        Input::new(p.P0_19.degrade(), Pull::Up),
+    );
+    s.must_spawn(battery::battery_task(battery, &BATTERY, &EVENTS, &POWER));
 
     // Touch peripheral
     let mut twim_config = twim::Config::default();
@@ -159,7 +208,10 @@ async fn main(s: Spawner) {
     let i2c_bus = I2C_BUS.init(BMutex::new(RefCell::new(i2c)));
 
     let i2c = I2cDevice::new(i2c_bus);
-    let hrs = Hrs::new(i2c);
+    static HRS: StaticCell<Mutex<NoopRawMutex, Hrs<'static>>> = StaticCell::new();
+    let hrs = HRS.init(Mutex::new(Hrs::new(i2c)));
+    s.must_spawn(heart::heart_task(&HEART, hrs, &POWER));
+    s.must_spawn(heart::monitor_task(&HEART, &HEART_MONITOR, &SETTINGS, &CLOCK));
 
     // setup touchpad external interrupt pin: P0.28/AIN4 (TP_INT)
     let touch_int = Input::new(p.P0_28, Pull::Up);
@@ -167,13 +219,33 @@ async fn main(s: Spawner) {
     let touch_rst = Output::new(p.P0_10, Level::High, OutputDrive::Standard);
 
     let i2c = I2cDevice::new(i2c_bus);
-    let mut touchpad = cst816s::CST816S::new(i2c, touch_int, touch_rst);
-    touchpad.setup(&mut embassy_time::Delay).unwrap();
+    let mut touchpad = cst816s::Cst816s::new(i2c, touch_int, touch_rst);
+    match touchpad.init(&mut embassy_time::Delay, touch::STANDBY_SECONDS).await {
+        Ok(()) => s.must_spawn(touch::touch_task(touchpad, &TOUCH, &EVENTS, &POWER)),
+        Err(_) => defmt::warn!("Error initializing touchpad"),
+    }
+
+    // Accelerometer, interrupt on P0.08
+    let i2c = I2cDevice::new(i2c_bus);
+    let mut accelerometer = bma421::Bma421::new(i2c, Input::new(p.P0_08, Pull::None));
+    match accelerometer.init(&mut embassy_time::Delay) {
+        Ok(()) => s.must_spawn(motion::motion_task(
+            accelerometer,
+            &MOTION,
+            &SETTINGS,
+            &CLOCK,
+            &STEPS,
+            &SLEEP,
+            &POWER,
+        )),
+        Err(_) => defmt::warn!("Error initializing accelerometer"),
+    }
 
     // Button enable
     let _btn_enable = Output::new(p.P0_15, Level::High, OutputDrive::Standard);
 
     let btn = Button::new(Input::new(p.P0_13.degrade(), Pull::Down));
+    s.must_spawn(events::button_task(btn, &EVENTS));
 
     let mut default_config = spim::Config::default();
     default_config.frequency = spim::Frequency::M8;
@@ -188,6 +260,13 @@ async fn main(s: Spawner) {
     let xt_flash = XtFlash::new(flash_spi).unwrap();
     static EXTERNAL_FLASH: StaticCell<Mutex<NoopRawMutex, ExternalFlash>> = StaticCell::new();
     let external_flash = EXTERNAL_FLASH.init(Mutex::new(xt_flash));
+    s.must_spawn(settings::settings_task(&SETTINGS, external_flash, &POWER));
+    s.must_spawn(steps::steps_task(&STEPS, &CLOCK, external_flash, &POWER));
+    s.must_spawn(sleep::sleep_task(&SLEEP, &CLOCK, hrs, external_flash, &POWER));
+    s.must_spawn(battery::log_task(&BATTERY, &CLOCK, external_flash, &POWER));
+    s.must_spawn(battery::calibration_task(&BATTERY, &CLOCK, external_flash, &POWER));
+    s.must_spawn(power::power_task(&POWER, external_flash, &TOUCH));
+    WORKOUTS.load(external_flash).await;
 
     let internal_flash = mpsl::Flash::take(mpsl, p.NVMC);
     static INTERNAL_FLASH: StaticCell<Mutex<NoopRawMutex, InternalFlash>> = StaticCell::new();
@@ -198,14 +277,22 @@ async fn main(s: Spawner) {
     let firmware_validator = FirmwareValidator::new(internal_flash);
 
     // BLE
-    ble::start(s, sdc, dfu_config, battery);
+    ble::start(
+        s,
+        sdc,
+        dfu_config,
+        FirmwareValidator::new(internal_flash),
+        &BATTERY,
+        external_flash,
+    );
 
     // Vibration
     let motor = Output::new(p.P0_16, Level::High, OutputDrive::Standard0Disconnect1);
-    let vibrator = Vibrator::new(motor);
+    s.must_spawn(vibrator::vibrator_task(motor, &VIBRATOR));
 
     // Display
-    let backlight = Backlight::new(p.P0_14.degrade(), p.P0_22.degrade(), p.P0_23.degrade());
+    let backlight_pwm = pwm::SimplePwm::new_3ch(p.PWM0, p.P0_14, p.P0_22, p.P0_23);
+    s.must_spawn(backlight::backlight_task(backlight_pwm, &BACKLIGHT, &SETTINGS));
     let rst = Output::new(p.P0_26, Level::Low, OutputDrive::Standard);
     let display_cs = Output::new(p.P0_25, Level::High, OutputDrive::Standard); // Keep low while driving display
     let display_spi = SpiDevice::new(spi_bus, display_cs);
@@ -219,16 +306,25 @@ async fn main(s: Spawner) {
         .unwrap();
     display.set_orientation(Orientation::new()).unwrap();
 
-    let screen = Screen::new(display, backlight);
+    let screen = Screen::new(display, &BACKLIGHT, &POWER);
     let mut device: Device<'_> = Device {
         clock: &CLOCK,
+        weather: &WEATHER,
+        navigation: &NAVIGATION,
+        steps: &STEPS,
+        motion: &MOTION,
+        settings: &SETTINGS,
+        sleep: &SLEEP,
         screen,
-        button: btn,
-        battery,
-        touchpad,
-        hrs,
+        backlight: &BACKLIGHT,
+        battery: &BATTERY,
+        events: &EVENTS,
+        heart: &HEART,
+        heart_monitor: &HEART_MONITOR,
+        workouts: &WORKOUTS,
+        flash: external_flash,
         firmware_validator,
-        vibrator,
+        vibrator: &VIBRATOR,
     };
 
     let mut state = WatchState::default();
@@ -267,8 +363,8 @@ impl<'a> DfuConfig<'a> {
         internal: &'a Mutex<NoopRawMutex, InternalFlash>,
         external: &'a Mutex<NoopRawMutex, ExternalFlash>,
     ) -> Self {
-        let dfu_start = 0x40000;
-        let dfu_end = 0xB4000;
+        let dfu_start = storage::DFU.start;
+        let dfu_end = storage::DFU.end;
 
         Partition::new(external, dfu_start, dfu_end - dfu_start);
         Self {
@@ -0,0 +1,104 @@
+use bma421::{Acceleration, Bma421, DEFAULT_WATERMARK, MAX_BATCH};
+use defmt::warn;
+use embassy_embedded_hal::shared_bus::blocking::i2c::I2cDevice;
+use embassy_nrf::gpio::Input;
+use embassy_nrf::peripherals::TWISPI1;
+use embassy_nrf::twim::Twim;
+use embassy_sync::blocking_mutex::raw::{NoopRawMutex, ThreadModeRawMutex};
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+use watchful_core::motion::Sample;
+use watchful_core::sleep::ActivityCounter;
+use watchful_core::steps::StepCounter;
+use watchful_core::wrist::RaiseDetector;
+
+use crate::clock::Clock;
+use crate::power::{Peripheral, Power};
+use crate::settings::SettingsStore;
+use crate::sleep::Sleep;
+use crate::steps::Steps;
+
+pub type Accelerometer = Bma421<I2cDevice<'static, NoopRawMutex, Twim<'static, TWISPI1>>, Input<'static>>;
+
+/// Samples per batch while raise to wake is on, short enough for the screen to turn on
+/// without a noticeable delay.
+const RAISE_WATERMARK: usize = 8;
+
+/// Gestures detected by the motion task.
+pub struct Motion {
+    raised: Signal<ThreadModeRawMutex, ()>,
+}
+
+impl Motion {
+    pub const fn new() -> Self {
+        Self { raised: Signal::new() }
+    }
+
+    /// Wait for the next wrist raise.
+    pub async fn wait_raised(&self) {
+        // Raises from while the screen was on are of no interest
+        self.raised.reset();
+        self.raised.wait().await
+    }
+}
+
+/// Reads batches of samples from the accelerometer and feeds them to the step counter, the
+/// raise detector and the sleep tracker.
+#[embassy_executor::task]
+pub async fn motion_task(
+    mut accelerometer: Accelerometer,
+    motion: &'static Motion,
+    settings: &'static SettingsStore,
+    clock: &'static Clock,
+    steps: &'static Steps,
+    sleep: &'static Sleep,
+    power: &'static Power,
+) {
+    let mut counter = StepCounter::new();
+    let mut raise = RaiseDetector::new();
+    let mut activity = ActivityCounter::new();
+    let mut raise_to_wake = false;
+    let mut batch = [Acceleration::default(); MAX_BATCH];
+    loop {
+        accelerometer.wait_watermark().await;
+        let awake = power.acquire(Peripheral::Accelerometer);
+
+        if settings.get().raise_to_wake != raise_to_wake {
+            raise_to_wake = !raise_to_wake;
+            let watermark = if raise_to_wake { RAISE_WATERMARK } else { DEFAULT_WATERMARK };
+            if accelerometer.set_watermark(watermark).is_err() {
+                warn!("[motion] error setting watermark");
+            }
+            raise.reset();
+        }
+
+        match accelerometer.read_fifo(&mut batch) {
+            Ok(n) => {
+                let mut new = 0;
+                let mut raised = false;
+                for a in &batch[..n] {
+                    let sample = Sample::new(a.x, a.y, a.z);
+                    new += counter.update(sample);
+                    raised |= raise_to_wake && raise.update(sample);
+                    if let Some(count) = activity.update(sample) {
+                        sleep.activity(count);
+                    }
+                }
+                if new > 0 {
+                    steps.add(clock.today(), new);
+                }
+                if raised {
+                    motion.raised.signal(());
+                }
+            }
+            Err(_) => {
+                warn!("[motion] error reading accelerometer");
+                let _ = accelerometer.flush();
+                counter.reset();
+                raise.reset();
+                drop(awake);
+                Timer::after(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
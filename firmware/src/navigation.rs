@@ -0,0 +1,102 @@
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::signal::Signal;
+use watchful_core::navigation::Direction;
+
+pub const NARRATIVE_SIZE: usize = 64;
+pub const DISTANCE_SIZE: usize = 16;
+
+#[derive(Clone, PartialEq)]
+pub struct Instruction {
+    pub direction: Direction,
+    pub narrative: heapless::String<NARRATIVE_SIZE>,
+    pub distance: heapless::String<DISTANCE_SIZE>,
+    pub progress: u8,
+}
+
+/// Turn-by-turn instructions written by the phone through the InfiniTime navigation service.
+pub struct Navigation {
+    instruction: Mutex<ThreadModeRawMutex, RefCell<Option<Instruction>>>,
+    /// Raised when a new manoeuvre is announced.
+    manoeuvre: Signal<ThreadModeRawMutex, ()>,
+    /// Raised on any change, including distance and progress updates.
+    changed: Signal<ThreadModeRawMutex, ()>,
+}
+
+impl Navigation {
+    pub const fn new() -> Self {
+        Self {
+            instruction: Mutex::new(RefCell::new(None)),
+            manoeuvre: Signal::new(),
+            changed: Signal::new(),
+        }
+    }
+
+    pub fn get(&self) -> Option<Instruction> {
+        self.instruction.lock(|i| i.borrow().clone())
+    }
+
+    pub fn set_icon(&self, name: &str) {
+        let direction = Direction::from_icon_name(name);
+        self.modify(|i| i.direction = direction);
+    }
+
+    pub fn set_narrative(&self, narrative: &str) {
+        let mut value = heapless::String::new();
+        push_truncated(&mut value, narrative);
+        let new = self.modify(|i| {
+            let new = i.narrative != value;
+            i.narrative = value;
+            new
+        });
+        if new {
+            self.manoeuvre.signal(());
+        }
+    }
+
+    pub fn set_distance(&self, distance: &str) {
+        self.modify(|i| {
+            i.distance.clear();
+            push_truncated(&mut i.distance, distance);
+        });
+    }
+
+    pub fn set_progress(&self, progress: u8) {
+        self.modify(|i| i.progress = progress.min(100));
+    }
+
+    /// Wait until the phone announces a new manoeuvre.
+    pub async fn wait_manoeuvre(&self) {
+        self.manoeuvre.wait().await
+    }
+
+    /// Wait until any part of the instruction changes.
+    pub async fn wait_changed(&self) {
+        self.changed.wait().await
+    }
+
+    fn modify<R>(&self, f: impl FnOnce(&mut Instruction) -> R) -> R {
+        let r = self.instruction.lock(|i| {
+            let mut i = i.borrow_mut();
+            let i = i.get_or_insert_with(|| Instruction {
+                direction: Direction::Unknown,
+                narrative: heapless::String::new(),
+                distance: heapless::String::new(),
+                progress: 0,
+            });
+            f(i)
+        });
+        self.changed.signal(());
+        r
+    }
+}
+
+fn push_truncated<const N: usize>(s: &mut heapless::String<N>, value: &str) {
+    for c in value.chars() {
+        if s.push(c).is_err() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use core::cell::RefCell;
+
+use defmt::{info, warn};
+use embassy_nrf::pac;
+use embassy_sync::blocking_mutex::raw::{NoopRawMutex, ThreadModeRawMutex};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::mutex::Mutex as AsyncMutex;
+use embassy_sync::signal::Signal;
+
+use crate::touch::Touch;
+use crate::ExternalFlash;
+
+const PERIPHERALS: usize = 5;
+const BUSES: usize = 2;
+
+#[derive(Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Peripheral {
+    Display,
+    Flash,
+    Touch,
+    Accelerometer,
+    HeartSensor,
+}
+
+impl Peripheral {
+    const ALL: [Self; PERIPHERALS] = [
+        Self::Display,
+        Self::Flash,
+        Self::Touch,
+        Self::Accelerometer,
+        Self::HeartSensor,
+    ];
+
+    fn bus(self) -> Bus {
+        match self {
+            Self::Display | Self::Flash => Bus::Spi,
+            Self::Touch | Self::Accelerometer | Self::HeartSensor => Bus::I2c,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Bus {
+    /// SPIM0, shared by the display and the external flash.
+    Spi,
+    /// TWIM1, shared by the touch controller, the accelerometer and the heart rate sensor.
+    I2c,
+}
+
+impl Bus {
+    /// The drivers keep their configuration while disabled, so re-enabling is all it takes
+    /// to use them again.
+    fn set_enabled(self, enabled: bool) {
+        match self {
+            Self::Spi => pac::SPIM0.enable().write(|w| {
+                w.set_enable(if enabled {
+                    pac::spim::vals::Enable::ENABLED
+                } else {
+                    pac::spim::vals::Enable::DISABLED
+                })
+            }),
+            Self::I2c => pac::TWIM1.enable().write(|w| {
+                w.set_enable(if enabled {
+                    pac::twim::vals::Enable::ENABLED
+                } else {
+                    pac::twim::vals::Enable::DISABLED
+                })
+            }),
+        }
+    }
+}
+
+struct State {
+    users: [u8; PERIPHERALS],
+    /// Direct users of each bus, on top of the peripherals on it.
+    holds: [u8; BUSES],
+}
+
+impl State {
+    fn bus_users(&self, bus: Bus) -> u8 {
+        Peripheral::ALL
+            .iter()
+            .filter(|p| p.bus() == bus)
+            .map(|p| self.users[*p as usize])
+            .sum::<u8>()
+            + self.holds[bus as usize]
+    }
+}
+
+/// Keeps track of who needs which peripheral, so that everything nobody needs can be put
+/// in its lowest power state.
+///
+/// The buses are switched synchronously when their first user arrives and their last
+/// leaves. Powering peripherals down happens in [`power_task`]; the flash wakes up on its
+/// own with the next operation.
+pub struct Power {
+    state: Mutex<ThreadModeRawMutex, RefCell<State>>,
+    changed: Signal<ThreadModeRawMutex, ()>,
+}
+
+impl Power {
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(RefCell::new(State {
+                users: [0; PERIPHERALS],
+                holds: [0; BUSES],
+            })),
+            changed: Signal::new(),
+        }
+    }
+
+    /// Keep `peripheral`, and the bus it is on, powered until the guard is dropped.
+    pub fn acquire(&self, peripheral: Peripheral) -> PowerGuard<'_> {
+        let first = self.update(peripheral.bus(), |s| {
+            s.users[peripheral as usize] += 1;
+            s.users[peripheral as usize] == 1
+        });
+        if first {
+            self.changed.signal(());
+        }
+        PowerGuard {
+            power: self,
+            peripheral,
+        }
+    }
+
+    /// Keep `bus` enabled until the guard is dropped, without powering up anything on it.
+    pub fn hold(&self, bus: Bus) -> BusGuard<'_> {
+        self.update(bus, |s| s.holds[bus as usize] += 1);
+        BusGuard { power: self, bus }
+    }
+
+    pub fn is_powered(&self, peripheral: Peripheral) -> bool {
+        self.state.lock(|s| s.borrow().users[peripheral as usize] > 0)
+    }
+
+    /// Change the state, and enable or disable `bus` if its users came or went.
+    fn update<R>(&self, bus: Bus, f: impl FnOnce(&mut State) -> R) -> R {
+        let (result, before, after) = self.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            let before = s.bus_users(bus);
+            let result = f(&mut s);
+            (result, before, s.bus_users(bus))
+        });
+        if before == 0 && after > 0 {
+            bus.set_enabled(true);
+        } else if before > 0 && after == 0 {
+            bus.set_enabled(false);
+        }
+        result
+    }
+}
+
+pub struct PowerGuard<'a> {
+    power: &'a Power,
+    peripheral: Peripheral,
+}
+
+impl Drop for PowerGuard<'_> {
+    fn drop(&mut self) {
+        let peripheral = self.peripheral;
+        let last = self.power.update(peripheral.bus(), |s| {
+            s.users[peripheral as usize] -= 1;
+            s.users[peripheral as usize] == 0
+        });
+        if last {
+            self.power.changed.signal(());
+        }
+    }
+}
+
+pub struct BusGuard<'a> {
+    power: &'a Power,
+    bus: Bus,
+}
+
+impl Drop for BusGuard<'_> {
+    fn drop(&mut self) {
+        let bus = self.bus;
+        self.power.update(bus, |s| s.holds[bus as usize] -= 1);
+    }
+}
+
+/// Puts the flash in deep power-down and the touch controller to sleep while nobody needs
+/// them.
+#[embassy_executor::task]
+pub async fn power_task(
+    power: &'static Power,
+    flash: &'static AsyncMutex<NoopRawMutex, ExternalFlash>,
+    touch: &'static Touch,
+) {
+    loop {
+        power.changed.wait().await;
+
+        touch.set_active(power.is_powered(Peripheral::Touch));
+
+        let mut flash = flash.lock().await;
+        // Checked again with the lock held, a DFU or BLE transfer may have started meanwhile
+        if !power.is_powered(Peripheral::Flash) && !flash.is_powered_down() {
+            let _spi = power.hold(Bus::Spi);
+            match flash.power_down() {
+                Ok(()) => info!("[power] flash powered down"),
+                Err(_) => warn!("[power] unable to power down flash"),
+            }
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use core::cell::RefCell;
+
+use defmt::{info, warn};
+use embassy_sync::blocking_mutex::raw::{NoopRawMutex, ThreadModeRawMutex};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::mutex::Mutex as AsyncMutex;
+use embassy_sync::signal::Signal;
+use watchful_core::records::RecordLog;
+use watchful_core::settings::{Settings, MAX_ENCODED_SIZE};
+use watchful_core::shell::Level;
+
+use crate::power::{Peripheral, Power};
+use crate::{storage, ExternalFlash};
+
+/// User settings, saved to external flash whenever they change.
+pub struct SettingsStore {
+    settings: Mutex<ThreadModeRawMutex, RefCell<Settings>>,
+    changed: Signal<ThreadModeRawMutex, ()>,
+}
+
+impl SettingsStore {
+    pub const fn new() -> Self {
+        Self {
+            settings: Mutex::new(RefCell::new(Settings::DEFAULT)),
+            changed: Signal::new(),
+        }
+    }
+
+    pub fn get(&self) -> Settings {
+        self.settings.lock(|s| *s.borrow())
+    }
+
+    pub fn update(&self, f: impl FnOnce(&mut Settings)) {
+        self.settings.lock(|s| f(&mut s.borrow_mut()));
+        self.changed.signal(());
+    }
+}
+
+/// Loads the settings at boot and saves them on every change.
+#[embassy_executor::task]
+pub async fn settings_task(
+    store: &'static SettingsStore,
+    flash: &'static AsyncMutex<NoopRawMutex, ExternalFlash>,
+    power: &'static Power,
+) {
+    let mut log = RecordLog::new(storage::SETTINGS.start, storage::SETTINGS.end, MAX_ENCODED_SIZE);
+    {
+        let _flash_power = power.acquire(Peripheral::Flash);
+        let mut flash = flash.lock().await;
+        if log.mount(&mut *flash).await.is_err() {
+            warn!("[settings] unable to mount log");
+        }
+        let mut record = [0; MAX_ENCODED_SIZE];
+        if let Ok(Some(len)) = log.read(&mut *flash, 0, &mut record).await {
+            match Settings::decode(&record[..len]) {
+                Ok(settings) => {
+                    info!("[settings] loaded {:?}", settings);
+                    store.settings.lock(|s| *s.borrow_mut() = settings);
+                }
+                Err(e) => warn!("[settings] unable to decode: {:?}", e),
+            }
+        }
+    }
+
+    let mut saved = store.get();
+    loop {
+        store.changed.wait().await;
+        let current = store.get();
+        if current == saved {
+            continue;
+        }
+        let mut record = [0; MAX_ENCODED_SIZE];
+        let len = current.encode(&mut record);
+        let _flash_power = power.acquire(Peripheral::Flash);
+        let mut flash = flash.lock().await;
+        match log.append(&mut *flash, &record[..len]).await {
+            Ok(()) => saved = current,
+            Err(_) => {
+                warn!("[settings] unable to save");
+                crate::SHELL_LOG.log(Level::Warn, format_args!("settings not saved"));
+            }
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Debug shell over the Nordic UART service, see [`watchful_core::shell`] for the commands.
+
+use core::fmt::Write as _;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use defmt::info;
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_time::Instant;
+use heapless::String;
+use watchful_core::battery::ChargeState;
+use watchful_core::shell::{self, Command, Level};
+
+use crate::battery::BatteryMonitor;
+use crate::firmware_validator::FirmwareValidator;
+use crate::{storage, version};
+
+/// Longest answer to a command.
+pub const MAX_OUTPUT: usize = 512;
+/// Longest log line sent to the shell, longer lines are cut.
+pub const MAX_LOG_LINE: usize = 64;
+
+pub type Output = String<MAX_OUTPUT>;
+
+/// Log lines for the shell, above the level chosen with `log level`. The level only
+/// applies here, what goes to the debug probe is fixed when building.
+pub struct ShellLog {
+    level: AtomicU8,
+    lines: Channel<ThreadModeRawMutex, String<MAX_LOG_LINE>, 4>,
+}
+
+impl ShellLog {
+    pub const fn new() -> Self {
+        Self {
+            level: AtomicU8::new(Level::Off as u8),
+            lines: Channel::new(),
+        }
+    }
+
+    pub fn level(&self) -> Level {
+        Level::from_u8(self.level.load(Ordering::Relaxed)).unwrap_or(Level::Off)
+    }
+
+    pub fn set_level(&self, level: Level) {
+        self.level.store(level.to_u8(), Ordering::Relaxed);
+    }
+
+    /// Queue a line for the shell, dropped when nobody keeps up with reading them.
+    pub fn log(&self, level: Level, args: core::fmt::Arguments) {
+        if level == Level::Off || level > self.level() {
+            return;
+        }
+        let mut line = String::new();
+        // Cut off when too long
+        let _ = line.write_fmt(args);
+        let _ = self.lines.try_send(line);
+    }
+
+    pub async fn next(&self) -> String<MAX_LOG_LINE> {
+        self.lines.receive().await
+    }
+}
+
+/// What to do once the answer to a command has been sent.
+#[derive(PartialEq)]
+pub enum After {
+    Nothing,
+    Reboot,
+}
+
+/// Run `command`, writing the answer to `out`. The answer is cut when it does not fit.
+pub async fn execute(
+    command: Command,
+    battery: &BatteryMonitor,
+    validator: &FirmwareValidator<'static>,
+    out: &mut Output,
+) -> After {
+    info!("[shell] {:?}", command);
+    // Running out of room only cuts the answer short
+    let _ = match command {
+        Command::Help => out.write_str(shell::HELP),
+        Command::Status => status(battery, validator, out),
+        Command::Battery => battery_status(battery, out),
+        Command::TimeGet => time(out),
+        Command::TimeSet { time, second } => {
+            let month = time::Month::try_from(time.month());
+            let date = month.and_then(|month| time::Date::from_calendar_date(time.year(), month, time.day()));
+            match (date, time::Time::from_hms(time.hour(), time.minute(), second)) {
+                (Ok(date), Ok(time)) => {
+                    crate::CLOCK.set(time::PrimitiveDateTime::new(date, time));
+                    crate::EVENTS.publish(crate::events::Event::TimeSynced);
+                    self::time(out)
+                }
+                _ => writeln!(out, "{}", shell::Error::InvalidArgument.message()),
+            }
+        }
+        Command::SettingsGet => shell::write_settings(&crate::SETTINGS.get(), out),
+        Command::SettingsSet(setting) => {
+            crate::SETTINGS.update(|s| setting.apply(s));
+            shell::write_settings(&crate::SETTINGS.get(), out)
+        }
+        Command::Reboot => {
+            crate::CLOCK.retain();
+            writeln!(out, "rebooting")
+        }
+        Command::Validate => {
+            validator.validate().await;
+            writeln!(out, "validated {}", yes_no(validator.is_valid()))
+        }
+        Command::FlashInfo => flash_info(out),
+        Command::LogLevel(level) => {
+            if let Some(level) = level {
+                crate::SHELL_LOG.set_level(level);
+            }
+            writeln!(out, "log level {}", crate::SHELL_LOG.level().name())
+        }
+    };
+    match command {
+        Command::Reboot => After::Reboot,
+        _ => After::Nothing,
+    }
+}
+
+/// Answer to a line that is not a command.
+pub fn error(error: shell::Error, out: &mut Output) {
+    let _ = writeln!(out, "{}", error.message());
+}
+
+fn status(battery: &BatteryMonitor, validator: &FirmwareValidator<'static>, out: &mut Output) -> core::fmt::Result {
+    writeln!(out, "{} {} ({})", version::NAME, version::VERSION, version::COMMIT)?;
+    writeln!(out, "built {}", version::BUILD_TIMESTAMP)?;
+    writeln!(out, "uptime {} s", Instant::now().as_secs())?;
+    time(out)?;
+    match crate::CLOCK.last_sync() {
+        Some((at, source)) => writeln!(out, "synced {} s ago by {}", at.elapsed().as_secs(), source.name())?,
+        None => writeln!(out, "never synced")?,
+    }
+    writeln!(out, "drift {} ppm", crate::CLOCK.drift_ppm())?;
+    writeln!(out, "battery {}%", battery.percent())?;
+    writeln!(out, "validated {}", yes_no(validator.is_valid()))
+}
+
+fn battery_status(battery: &BatteryMonitor, out: &mut Output) -> core::fmt::Result {
+    let Some(status) = battery.get() else {
+        return writeln!(out, "not sampled yet");
+    };
+    let state = match status.state {
+        ChargeState::Discharging => "discharging",
+        ChargeState::Charging => "charging",
+        ChargeState::Charged => "charged",
+    };
+    writeln!(out, "level {}%", status.percent)?;
+    writeln!(out, "voltage {} mV", status.millivolts)?;
+    writeln!(out, "state {}", state)?;
+    writeln!(out, "calibrated {}", yes_no(battery.is_calibrated()))?;
+    writeln!(out, "calibrating {}", yes_no(battery.is_calibrating()))
+}
+
+fn time(out: &mut Output) -> core::fmt::Result {
+    let (Some(time), Some(timestamp)) = (crate::CLOCK.get(), crate::CLOCK.timestamp()) else {
+        return writeln!(out, "time not set");
+    };
+    write!(
+        out,
+        "time {}-{:02}-{:02} {:02}:{:02}:{:02} ",
+        time.year(),
+        time.month() as u8,
+        time.day(),
+        time.hour(),
+        time.minute(),
+        time.second()
+    )?;
+    crate::CLOCK.zone().write_offset(timestamp, out)?;
+    writeln!(out)
+}
+
+fn flash_info(out: &mut Output) -> core::fmt::Result {
+    let regions = [
+        ("dfu", storage::DFU),
+        ("steps", storage::STEPS),
+        ("settings", storage::SETTINGS),
+        ("sleep", storage::SLEEP),
+        ("workouts", storage::WORKOUTS),
+        ("battery", storage::BATTERY),
+        ("calibration", storage::BATTERY_CALIBRATION),
+        ("curve", storage::BATTERY_CURVE),
+    ];
+    for (name, range) in regions {
+        let size = (range.end - range.start) / 1024;
+        writeln!(out, "{} {:#08x}-{:#08x} {} KiB", name, range.start, range.end, size)?;
+    }
+    Ok(())
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
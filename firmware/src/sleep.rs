@@ -0,0 +1,197 @@
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use defmt::{info, warn};
+use embassy_sync::blocking_mutex::raw::{NoopRawMutex, ThreadModeRawMutex};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::mutex::Mutex as AsyncMutex;
+use embassy_time::{Duration, Timer};
+use watchful_core::ppg::MIN_SKIN_LEVEL;
+use watchful_core::records::RecordLog;
+use watchful_core::sleep::{Classifier, Epoch, Night, NightSummary, ENCODED_SIZE, HYPNOGRAM_SLOTS, SLOT_MINUTES};
+
+use crate::clock::Clock;
+use crate::device::Hrs;
+use crate::power::{Peripheral, Power};
+use crate::{storage, ExternalFlash};
+
+/// Nights kept in memory for the sleep view.
+pub const NIGHTS: usize = 7;
+
+/// Tracking happens automatically between these hours, local time.
+const NIGHT_START_HOUR: u8 = 22;
+const NIGHT_END_HOUR: u8 = 9;
+
+/// How often to check with the heart rate sensor that the watch is still worn.
+const WORN_CHECK_MINUTES: u16 = 30;
+
+/// Nights shorter than this (in minutes) are not worth saving.
+const MIN_NIGHT_MINUTES: u16 = 60;
+
+pub struct Sleep {
+    /// Track sleep now, regardless of the time.
+    mode: AtomicBool,
+    /// Activity counts per minute from the motion task.
+    activity: Channel<ThreadModeRawMutex, u16, 4>,
+    /// Summaries of the last nights, newest first.
+    nights: Mutex<ThreadModeRawMutex, RefCell<heapless::Vec<NightSummary, NIGHTS>>>,
+}
+
+impl Sleep {
+    pub const fn new() -> Self {
+        Self {
+            mode: AtomicBool::new(false),
+            activity: Channel::new(),
+            nights: Mutex::new(RefCell::new(heapless::Vec::new())),
+        }
+    }
+
+    pub fn mode(&self) -> bool {
+        self.mode.load(Ordering::Relaxed)
+    }
+
+    pub fn set_mode(&self, on: bool) {
+        self.mode.store(on, Ordering::Relaxed);
+    }
+
+    /// Report the activity count of the last minute.
+    pub fn activity(&self, count: u16) {
+        let _ = self.activity.try_send(count);
+    }
+
+    pub fn nights(&self) -> heapless::Vec<NightSummary, NIGHTS> {
+        self.nights.lock(|n| n.borrow().clone())
+    }
+
+    fn add_night(&self, night: NightSummary) {
+        self.nights.lock(|n| {
+            let mut n = n.borrow_mut();
+            n.truncate(NIGHTS - 1);
+            let _ = n.insert(0, night);
+        })
+    }
+}
+
+struct Tracking {
+    classifier: Classifier,
+    night: Night,
+    worn: bool,
+    since_check: u16,
+}
+
+/// Scores every minute of the night and saves a summary in external flash when it ends.
+#[embassy_executor::task]
+pub async fn sleep_task(
+    sleep: &'static Sleep,
+    clock: &'static Clock,
+    hrs: &'static AsyncMutex<NoopRawMutex, Hrs<'static>>,
+    flash: &'static AsyncMutex<NoopRawMutex, ExternalFlash>,
+    power: &'static Power,
+) {
+    let mut log = RecordLog::new(storage::SLEEP.start, storage::SLEEP.end, ENCODED_SIZE);
+    {
+        let _flash_power = power.acquire(Peripheral::Flash);
+        let mut flash = flash.lock().await;
+        if log.mount(&mut *flash).await.is_err() {
+            warn!("[sleep] unable to mount log");
+        }
+        let mut record = [0; ENCODED_SIZE];
+        for n in (0..NIGHTS).rev() {
+            if let Ok(Some(len)) = log.read(&mut *flash, n, &mut record).await {
+                if let Some(night) = NightSummary::decode(&record[..len]) {
+                    sleep.add_night(night);
+                }
+            }
+        }
+    }
+
+    let mut tracking: Option<Tracking> = None;
+    loop {
+        let activity = sleep.activity.receive().await;
+        // Sleep is only tracked once the time is known, nights are stored by date
+        let Some(now) = clock.get() else { continue };
+        let night_time = now.hour() >= NIGHT_START_HOUR || now.hour() < NIGHT_END_HOUR;
+        let active = sleep.mode() || night_time;
+
+        if active && tracking.is_none() {
+            // Nights belong to the evening they started on
+            let date = if now.hour() < 12 {
+                now.date().previous_day().unwrap_or(now.date())
+            } else {
+                now.date()
+            };
+            let start = now.hour() as u16 * 60 + now.minute() as u16;
+            info!("[sleep] tracking started");
+            tracking = Some(Tracking {
+                classifier: Classifier::new(),
+                night: Night::new(date.to_julian_day(), start),
+                worn: true,
+                since_check: WORN_CHECK_MINUTES,
+            });
+        }
+
+        let full = tracking
+            .as_ref()
+            .map(|t| t.night.minutes() >= HYPNOGRAM_SLOTS as u16 * SLOT_MINUTES)
+            .unwrap_or(false);
+        if !active || full {
+            if let Some(mut t) = tracking.take() {
+                for epoch in t.classifier.finish() {
+                    t.night.record(epoch);
+                }
+                let summary = t.night.summary();
+                info!("[sleep] tracking stopped, asleep {} of {} minutes", summary.asleep, summary.minutes);
+                if summary.minutes >= MIN_NIGHT_MINUTES {
+                    let _flash_power = power.acquire(Peripheral::Flash);
+                    let mut flash = flash.lock().await;
+                    if log.append(&mut *flash, &summary.encode()).await.is_err() {
+                        warn!("[sleep] unable to save night");
+                    }
+                    sleep.add_night(summary);
+                }
+            }
+            if !active {
+                continue;
+            }
+        }
+
+        if let Some(t) = tracking.as_mut() {
+            t.since_check += 1;
+            if t.since_check >= WORN_CHECK_MINUTES {
+                t.since_check = 0;
+                if let Some(worn) = check_worn(hrs, power).await {
+                    t.worn = worn;
+                }
+            }
+
+            if t.worn {
+                if let Some(epoch) = t.classifier.push(activity) {
+                    t.night.record(epoch);
+                }
+            } else {
+                for epoch in t.classifier.finish() {
+                    t.night.record(epoch);
+                }
+                t.classifier = Classifier::new();
+                t.night.record(Epoch::OffWrist);
+            }
+        }
+    }
+}
+
+/// Briefly turn on the heart rate sensor to see if any LED light is reflected by skin.
+/// Returns `None` if the sensor is busy or can not be read.
+async fn check_worn(hrs: &AsyncMutex<NoopRawMutex, Hrs<'static>>, power: &Power) -> Option<bool> {
+    let mut hrs = hrs.try_lock().ok()?;
+    let _sensor_power = power.acquire(Peripheral::HeartSensor);
+    let mut reflection = None;
+    if hrs.init().is_ok() && hrs.enable_hrs().is_ok() && hrs.enable_oscillator().is_ok() {
+        // Let a few conversions complete
+        Timer::after(Duration::from_millis(300)).await;
+        reflection = hrs.read_hrs().ok();
+    }
+    let _ = hrs.disable_oscillator();
+    let _ = hrs.disable_hrs();
+    reflection.map(|r| r >= MIN_SKIN_LEVEL)
+}
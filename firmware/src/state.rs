@@ -1,12 +1,38 @@
-use defmt::info;
-use embassy_futures::select::{select, select3, Either, Either3};
+use defmt::{info, warn};
+use embassy_futures::select::{select, select3, select4, Either, Either3, Either4};
 use embassy_time::{Duration, Instant, Ticker, Timer};
-use embedded_graphics::prelude::*;
-use watchful_ui::{FirmwareDetails, MenuAction, MenuView, TimeDetails, TimerView, TimeView, WorkoutView};
-
+use watchful_core::battery_history;
+use watchful_core::calendar::{DateTime, Field};
+use watchful_core::heart_history::{Alert, History};
+use watchful_core::ppg::MIN_CONFIDENCE;
+use watchful_core::sleep::NightSummary;
+use watchful_core::timezone::{DstRule, WORLD_ZONES};
+use watchful_core::vibration::Pattern;
+use watchful_core::workout::{Recorder, Summary};
+use watchful_ui::{
+    BatteryHistoryView, BrightnessView, CurrentConditions, FirmwareDetails, HeartHistoryView, InputEvent, MenuAction,
+    MenuView, NavigationView, SleepView, StepsView, TimeDetails, TimePage, TimeView, TimerView, WeatherComplication,
+    WeatherView, WorkoutHistoryView, WorkoutView, WorldClockComplication,
+};
+
+use crate::backlight::Backlight;
+use crate::clock::Clock;
 use crate::device::Device;
+use crate::events::{Event, EventBus};
+use crate::version;
 
 const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a finished timer stays on screen when it is not dismissed.
+const TIMER_DONE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The backlight dims this long before the screen turns off.
+const DIM_BEFORE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Weather older than this is not shown on the watch face.
+const WEATHER_MAX_AGE: time::Duration = time::Duration::hours(6);
+
+/// Minutes between background heart rate readings the settings cycle through, 0 is off.
+const HEART_INTERVALS: [u8; 4] = [0, 10, 30, 60];
 
 #[derive(PartialEq, Clone, Copy)]
 pub struct Timeout {
@@ -21,8 +47,14 @@ impl Timeout {
             duration,
         }
     }
-    pub fn timer(&self) -> Timer {
-        Timer::after(self.time_left())
+    /// Wait for the timeout, dimming the backlight shortly before it runs out.
+    pub async fn wait(&self, backlight: &Backlight) {
+        let left = self.time_left();
+        if left > DIM_BEFORE_TIMEOUT {
+            Timer::after(left - DIM_BEFORE_TIMEOUT).await;
+        }
+        backlight.dim();
+        Timer::after(self.time_left()).await;
     }
 
     fn time_left(&self) -> Duration {
@@ -43,6 +75,14 @@ pub enum WatchState {
     //  FindPhone,
     Timer(TimerState),
     Workout(WorkoutState),
+    Weather(WeatherState),
+    Navigation(NavigationState),
+    Steps(StepsState),
+    Sleep(SleepState),
+    WorkoutHistory(WorkoutHistoryState),
+    HeartHistory(HeartHistoryState),
+    BatteryHistory(BatteryHistoryState),
+    Brightness(BrightnessState),
 }
 
 impl Default for WatchState {
@@ -59,6 +99,14 @@ impl defmt::Format for WatchState {
             Self::Menu(_) => defmt::write!(fmt, "Menu"),
             Self::Timer(_) => defmt::write!(fmt, "Timer"),
             Self::Workout(_) => defmt::write!(fmt, "Workout"),
+            Self::Weather(_) => defmt::write!(fmt, "Weather"),
+            Self::Navigation(_) => defmt::write!(fmt, "Navigation"),
+            Self::Steps(_) => defmt::write!(fmt, "Steps"),
+            Self::Sleep(_) => defmt::write!(fmt, "Sleep"),
+            Self::WorkoutHistory(_) => defmt::write!(fmt, "WorkoutHistory"),
+            Self::HeartHistory(_) => defmt::write!(fmt, "HeartHistory"),
+            Self::BatteryHistory(_) => defmt::write!(fmt, "BatteryHistory"),
+            Self::Brightness(_) => defmt::write!(fmt, "Brightness"),
         }
     }
 }
@@ -71,6 +119,14 @@ impl WatchState {
             WatchState::Menu(state) => state.draw(device).await,
             WatchState::Workout(state) => state.draw(device).await,
             WatchState::Timer(state) => state.draw(device).await,
+            WatchState::Weather(state) => state.draw(device).await,
+            WatchState::Navigation(state) => state.draw(device).await,
+            WatchState::Steps(state) => state.draw(device).await,
+            WatchState::Sleep(state) => state.draw(device).await,
+            WatchState::WorkoutHistory(state) => state.draw(device).await,
+            WatchState::HeartHistory(state) => state.draw(device).await,
+            WatchState::BatteryHistory(state) => state.draw(device).await,
+            WatchState::Brightness(state) => state.draw(device).await,
         }
     }
 
@@ -81,6 +137,14 @@ impl WatchState {
             WatchState::Menu(state) => state.next(device).await,
             WatchState::Workout(state) => state.next(device).await,
             WatchState::Timer(state) => state.next(device).await,
+            WatchState::Weather(state) => state.next(device).await,
+            WatchState::Navigation(state) => state.next(device).await,
+            WatchState::Steps(state) => state.next(device).await,
+            WatchState::Sleep(state) => state.next(device).await,
+            WatchState::WorkoutHistory(state) => state.next(device).await,
+            WatchState::HeartHistory(state) => state.next(device).await,
+            WatchState::BatteryHistory(state) => state.next(device).await,
+            WatchState::Brightness(state) => state.next(device).await,
         }
     }
 }
@@ -93,12 +157,27 @@ impl IdleState {
     }
 
     pub async fn draw(&mut self, device: &mut Device<'_>) {
-        device.screen.off();
+        device.screen.off().await;
     }
 
     pub async fn next(&mut self, device: &mut Device<'_>) -> WatchState {
-        device.button.wait().await;
-        WatchState::Time(TimeState::new(device, Timeout::new(IDLE_TIMEOUT)).await)
+        loop {
+            match select4(
+                device.events.next(),
+                device.navigation.wait_manoeuvre(),
+                device.motion.wait_raised(),
+                device.heart_monitor.wait_alert(),
+            )
+            .await
+            {
+                Either4::First(Event::Button | Event::Alarm | Event::Notification) | Either4::Third(_) => {
+                    return WatchState::Time(TimeState::new(device, Timeout::new(IDLE_TIMEOUT)).await)
+                }
+                Either4::First(_) => {}
+                Either4::Second(_) => return WatchState::Navigation(NavigationState::new(true)),
+                Either4::Fourth(alert) => return WatchState::HeartHistory(HeartHistoryState::new(device, Some(alert))),
+            }
+        }
     }
 }
 
@@ -111,43 +190,65 @@ pub struct TimeState {
 impl TimeState {
     pub async fn new(device: &mut Device<'_>, timeout: Timeout) -> TimeState {
         let now = device.clock.get();
-        let battery_level = device.battery.measure().await;
+        let battery_level = device.battery.percent() as u32;
         let charging = device.battery.is_charging();
+        let weather = device
+            .weather
+            .current()
+            .filter(|report| now.is_some_and(|now| now - report.received < WEATHER_MAX_AGE))
+            .map(|report| WeatherComplication::new(report.data.temperature.celsius(), report.data.icon));
+        let steps = device.steps.get(device.clock.today());
+        let world_clock = device
+            .settings
+            .get()
+            .world_zone()
+            .and_then(|(name, zone)| Some(WorldClockComplication::new(name, device.clock.get_in(zone)?)));
         Self {
-            view: TimeView::new(now, battery_level, charging),
+            view: TimeView::new(now, battery_level, charging, weather, world_clock, Some(steps)),
             timeout,
         }
     }
 
     pub async fn draw(&mut self, device: &mut Device<'_>) {
-        self.view.draw(device.screen.display()).unwrap();
-        device.screen.on();
+        self.view.draw(device.screen.display().await).unwrap();
+        device.screen.on().await;
     }
 
     pub async fn next(&mut self, device: &mut Device<'_>) -> WatchState {
         loop {
-            match select3(
+            match select4(
                 Timer::after(Duration::from_secs(2)),
-                self.timeout.timer(),
-                device.button.wait(),
+                self.timeout.wait(device.backlight),
+                device.events.next(),
+                device.navigation.wait_manoeuvre(),
             )
             .await
             {
-                Either3::First(_) => {
-                    let t = device.clock.get();
-                    let b = device.battery.measure().await;
-                    let l = device.battery.is_charging();
-                    if t.minute() != self.view.time.minute()
-                        || b != self.view.battery_level
-                        || l != self.view.battery_charging
+                Either4::First(_) => {
+                    let next = TimeState::new(device, self.timeout).await;
+                    // Only what is shown, the views hold the time to the second
+                    let minute = |view: &TimeView| view.time.map(|t| t.minute());
+                    let world_clock =
+                        |view: &TimeView| view.world_clock.map(|w| (w.name, w.time.hour(), w.time.minute()));
+                    if minute(&next.view) != minute(&self.view)
+                        || world_clock(&next.view) != world_clock(&self.view)
+                        || next.view.battery_level != self.view.battery_level
+                        || next.view.battery_charging != self.view.battery_charging
+                        || next.view.weather != self.view.weather
+                        || next.view.steps != self.view.steps
                     {
-                        return WatchState::Time(TimeState::new(device, self.timeout).await);
+                        return WatchState::Time(next);
                     }
                 }
-                Either3::Second(_) => {
+                Either4::Second(_) => {
                     return WatchState::Idle(IdleState::new(device));
                 }
-                Either3::Third(_) => return WatchState::Menu(MenuState::new(MenuView::main())),
+                Either4::Third(Event::Button) => return WatchState::Menu(MenuState::new(MenuView::main())),
+                Either4::Third(Event::TimeSynced | Event::BatteryChanged { .. }) => {
+                    return WatchState::Time(TimeState::new(device, self.timeout).await)
+                }
+                Either4::Third(_) => {}
+                Either4::Fourth(_) => return WatchState::Navigation(NavigationState::new(true)),
             }
         }
     }
@@ -166,132 +267,402 @@ impl MenuState {
     }
 
     pub async fn draw(&mut self, device: &mut Device<'_>) {
-        self.view.draw(device.screen.display()).unwrap();
-        device.screen.on();
+        self.view.draw(device.screen.display().await).unwrap();
+        device.screen.on().await;
     }
 
     pub async fn next(&mut self, device: &mut Device<'_>) -> WatchState {
-        match select3(self.timeout.timer(), device.button.wait(), async {
-            let selected;
-            loop {
-                if let Some(evt) = device.touchpad.read_one_touch_event(true) {
-                    if let cst816s::TouchGesture::SingleClick = evt.gesture {
-                        let touched = Point::new(evt.x, evt.y);
-                        if let Some(s) =
-                            self.view
-                                .on_event(watchful_ui::InputEvent::Touch(watchful_ui::TouchGesture::SingleTap(
-                                    touched,
-                                )))
-                        {
-                            selected = s;
-                            break;
-                        }
+        let selected = loop {
+            match select(self.timeout.wait(device.backlight), device.events.next()).await {
+                Either::First(_) => return WatchState::Idle(IdleState::new(device)),
+                Either::Second(Event::Button) => return self.back(device).await,
+                Either::Second(Event::Touch(gesture)) => {
+                    if let Some(action) = self.view.on_event(InputEvent::Touch(gesture)) {
+                        break action;
                     }
-                } else {
-                    Timer::after(Duration::from_micros(2)).await;
                 }
+                Either::Second(_) => {}
             }
-            selected
-        })
-        .await
-        {
-            Either3::First(_) => WatchState::Idle(IdleState::new(device)),
-            Either3::Second(_) => {
-                if let MenuView::Settings { .. } = &self.view {
-                    WatchState::Menu(MenuState::new(MenuView::main()))
-                } else if let MenuView::Firmware { .. } = &self.view {
-                    WatchState::Menu(MenuState::new(MenuView::settings()))
-                } else {
-                    WatchState::Time(TimeState::new(device, Timeout::new(IDLE_TIMEOUT)).await)
-                }
+        };
+        match selected {
+            MenuAction::Workout => WatchState::Workout(WorkoutState {}),
+            MenuAction::Timer => {
+                defmt::info!("Not implemented");
+                WatchState::Timer(TimerState {})
             }
-            Either3::Third(selected) => match selected {
-                MenuAction::Workout => {
-                    defmt::info!("Not implemented");
-                    WatchState::Workout(WorkoutState {})
-                }
-                MenuAction::Timer => {
-                    defmt::info!("Not implemented");
-                    WatchState::Timer(TimerState {})
-                }
-                MenuAction::FindPhone => {
-                    defmt::info!("Not implemented");
-                    WatchState::Time(TimeState::new(device, Timeout::new(IDLE_TIMEOUT)).await)
-                }
-                MenuAction::Settings => WatchState::Menu(MenuState::new(MenuView::settings())),
-                MenuAction::Brightness => {
-                    device.screen.change_brightness();
-                    WatchState::Menu(MenuState::new(MenuView::settings()))
-                }
-                MenuAction::TimeSettings => {
-                    WatchState::Menu(MenuState::new(MenuView::time_settings(
-                    	time_details(device).await,
-                    )))
-                }
-                MenuAction::Reset => {
-                    cortex_m::peripheral::SCB::sys_reset();
-                }
-                MenuAction::FirmwareSettings => {
-                    let validated = device.firmware_validator.is_valid();
-                    WatchState::Menu(MenuState::new(MenuView::firmware_settings(
-                        firmware_details(device.battery, validated).await,
-                    )))
-                }
-                MenuAction::ValidateFirmware => {
-                    info!("Validate firmware");
-                    device.firmware_validator.validate().await;
-                    info!("Firmware marked as valid");
-                    WatchState::Menu(MenuState::new(MenuView::main()))
-                }
-                MenuAction::ChangeTimeMinInc => {
-                    let new_time = device.clock.get()
-                    	.checked_add(time::Duration::new(60, 0));
-					match new_time {
-    					Some(time) => device.clock.set(time),
-    					None => { info!("Incremented time 1 minute too many"); }
-					}
-					
-                    WatchState::Menu(MenuState::new(MenuView::time_settings(
-                    	time_details(device).await,
-                    )))
-                }
-                MenuAction::ChangeTimeHourInc => {
-                    let new_time = device.clock.get()
-                    	.checked_add(time::Duration::new(3600, 0));
-					match new_time {
-    					Some(time) => device.clock.set(time),
-    					None => { info!("Incremented time 1 hour too many"); }
-					}
-					
-                    WatchState::Menu(MenuState::new(MenuView::time_settings(
-                    	time_details(device).await,
-                    )))
+            MenuAction::FindPhone => {
+                defmt::info!("Not implemented");
+                WatchState::Time(TimeState::new(device, Timeout::new(IDLE_TIMEOUT)).await)
+            }
+            MenuAction::Settings => WatchState::Menu(MenuState::new(MenuView::settings())),
+            MenuAction::MoreSettings => WatchState::Menu(MenuState::new(more_settings(device))),
+            MenuAction::ToggleRaiseToWake => {
+                device.settings.update(|s| s.raise_to_wake = !s.raise_to_wake);
+                WatchState::Menu(MenuState::new(more_settings(device)))
+            }
+            MenuAction::ToggleSleepMode => {
+                device.sleep.set_mode(!device.sleep.mode());
+                WatchState::Menu(MenuState::new(more_settings(device)))
+            }
+            MenuAction::CycleHeartInterval => {
+                device.settings.update(|s| {
+                    let next = HEART_INTERVALS
+                        .iter()
+                        .position(|i| *i == s.heart_interval)
+                        .map_or(0, |i| i + 1);
+                    s.heart_interval = HEART_INTERVALS[next % HEART_INTERVALS.len()];
+                });
+                WatchState::Menu(MenuState::new(more_settings(device)))
+            }
+            MenuAction::ZoneSettings => WatchState::Menu(MenuState::new(zone_settings(device))),
+            MenuAction::CycleDstRule => {
+                let mut zone = device.clock.zone();
+                let next = DstRule::ALL.iter().position(|r| *r == zone.dst).map_or(0, |i| i + 1);
+                zone.dst = DstRule::ALL[next % DstRule::ALL.len()];
+                device.clock.set_zone(zone);
+                WatchState::Menu(MenuState::new(zone_settings(device)))
+            }
+            MenuAction::CycleWorldClock => {
+                device
+                    .settings
+                    .update(|s| s.world_clock = (s.world_clock + 1) % (WORLD_ZONES.len() as u8 + 1));
+                WatchState::Menu(MenuState::new(zone_settings(device)))
+            }
+            MenuAction::ToggleBatteryCalibration => {
+                device.battery.set_calibrating(!device.battery.is_calibrating());
+                WatchState::Menu(MenuState::new(more_settings(device)))
+            }
+            MenuAction::Apps => WatchState::Menu(MenuState::new(MenuView::apps())),
+            MenuAction::MoreApps => WatchState::Menu(MenuState::new(MenuView::more_apps())),
+            MenuAction::HeartHistory => WatchState::HeartHistory(HeartHistoryState::new(device, None)),
+            MenuAction::BatteryHistory => WatchState::BatteryHistory(BatteryHistoryState::new(device)),
+            MenuAction::Weather => WatchState::Weather(WeatherState::new(device)),
+            MenuAction::Steps => WatchState::Steps(StepsState::new(device)),
+            MenuAction::Sleep => WatchState::Sleep(SleepState::new(device)),
+            MenuAction::WorkoutHistory => WatchState::WorkoutHistory(WorkoutHistoryState::new(device)),
+            MenuAction::Back => self.back(device).await,
+            MenuAction::Brightness => WatchState::Brightness(BrightnessState::new(device)),
+            MenuAction::TimeSettings => WatchState::Menu(MenuState::new(MenuView::time_settings(
+                time_details(device, TimePage::Time).await,
+            ))),
+            MenuAction::Reset => {
+                device.clock.retain();
+                cortex_m::peripheral::SCB::sys_reset();
+            }
+            MenuAction::FirmwareSettings => {
+                let validated = device.firmware_validator.is_valid();
+                WatchState::Menu(MenuState::new(MenuView::firmware_settings(
+                    firmware_details(device.battery, validated).await,
+                )))
+            }
+            MenuAction::ValidateFirmware => {
+                info!("Validate firmware");
+                device.firmware_validator.validate().await;
+                info!("Firmware marked as valid");
+                WatchState::Menu(MenuState::new(MenuView::main()))
+            }
+            MenuAction::TimeSettingsPage(page) => WatchState::Menu(MenuState::new(MenuView::time_settings(
+                time_details(device, page).await,
+            ))),
+            MenuAction::AdjustTime(field, delta) => {
+                adjust_time(device.clock, field, delta);
+                let page = match &self.view {
+                    MenuView::TimeSettings { details, .. } => details.page(),
+                    _ => TimePage::Time,
+                };
+                WatchState::Menu(MenuState::new(MenuView::time_settings(
+                    time_details(device, page).await,
+                )))
+            }
+        }
+    }
+
+    async fn back(&self, device: &mut Device<'_>) -> WatchState {
+        match &self.view {
+            MenuView::Settings { .. } | MenuView::Apps { .. } => WatchState::Menu(MenuState::new(MenuView::main())),
+            MenuView::Firmware { .. } | MenuView::MoreSettings { .. } | MenuView::TimeSettings { .. } => {
+                WatchState::Menu(MenuState::new(MenuView::settings()))
+            }
+            MenuView::MoreApps { .. } => WatchState::Menu(MenuState::new(MenuView::apps())),
+            MenuView::ZoneSettings { .. } => WatchState::Menu(MenuState::new(more_settings(device))),
+            _ => WatchState::Time(TimeState::new(device, Timeout::new(IDLE_TIMEOUT)).await),
+        }
+    }
+}
+
+fn more_settings(device: &Device<'_>) -> MenuView {
+    let settings = device.settings.get();
+    MenuView::more_settings(
+        settings.raise_to_wake,
+        device.sleep.mode(),
+        settings.heart_interval,
+        device.battery.is_calibrating(),
+    )
+}
+
+fn zone_settings(device: &Device<'_>) -> MenuView {
+    let settings = device.settings.get();
+    MenuView::zone_settings(settings.time_zone.dst, settings.world_zone().map(|(name, _)| name))
+}
+
+/// Wait for the side button, ignoring any other events.
+async fn button_pressed(events: &EventBus) {
+    while events.next().await != Event::Button {}
+}
+
+#[derive(PartialEq)]
+pub struct WeatherState {
+    view: WeatherView,
+    timeout: Timeout,
+}
+
+impl WeatherState {
+    pub fn new(device: &mut Device<'_>) -> Self {
+        let current = device.weather.current().map(|report| CurrentConditions {
+            location: report.data.location.clone(),
+            temperature: report.data.temperature.celsius(),
+            min: report.data.min.celsius(),
+            max: report.data.max.celsius(),
+            icon: report.data.icon,
+            updated: report.received,
+        });
+        let forecast = device.weather.forecast().map(|report| report.data);
+        Self {
+            view: WeatherView::new(current, forecast),
+            timeout: Timeout::new(IDLE_TIMEOUT),
+        }
+    }
+
+    pub async fn draw(&mut self, device: &mut Device<'_>) {
+        self.view.draw(device.screen.display().await).unwrap();
+        device.screen.on().await;
+    }
+
+    pub async fn next(&mut self, device: &mut Device<'_>) -> WatchState {
+        match select(self.timeout.wait(device.backlight), button_pressed(device.events)).await {
+            Either::First(_) => WatchState::Idle(IdleState::new(device)),
+            Either::Second(_) => WatchState::Menu(MenuState::new(MenuView::apps())),
+        }
+    }
+}
+
+#[derive(PartialEq)]
+pub struct StepsState {
+    view: StepsView,
+    timeout: Timeout,
+}
+
+impl StepsState {
+    pub fn new(device: &mut Device<'_>) -> Self {
+        let steps = device.steps.get(device.clock.today());
+        Self {
+            view: StepsView::new(steps, crate::steps::DEFAULT_GOAL),
+            timeout: Timeout::new(IDLE_TIMEOUT),
+        }
+    }
+
+    pub async fn draw(&mut self, device: &mut Device<'_>) {
+        self.view.draw(device.screen.display().await).unwrap();
+        device.screen.on().await;
+    }
+
+    pub async fn next(&mut self, device: &mut Device<'_>) -> WatchState {
+        loop {
+            match select3(
+                Timer::after(Duration::from_secs(2)),
+                self.timeout.wait(device.backlight),
+                button_pressed(device.events),
+            )
+            .await
+            {
+                Either3::First(_) => {
+                    let steps = device.steps.get(device.clock.today());
+                    if steps != self.view.steps {
+                        self.view.steps = steps;
+                        self.draw(device).await;
+                    }
                 }
-                MenuAction::ChangeTimeMinDec => {
-                    let new_time = device.clock.get()
-                    	.checked_sub(time::Duration::new(60, 0));
-					match new_time {
-    					Some(time) => device.clock.set(time),
-    					None => { info!("Decremented time 1 minute too many"); }
-					}
-					
-                    WatchState::Menu(MenuState::new(MenuView::time_settings(
-                    	time_details(device).await,
-                    )))
+                Either3::Second(_) => return WatchState::Idle(IdleState::new(device)),
+                Either3::Third(_) => return WatchState::Menu(MenuState::new(MenuView::apps())),
+            }
+        }
+    }
+}
+
+#[derive(PartialEq)]
+pub struct SleepState {
+    nights: heapless::Vec<NightSummary, { crate::sleep::NIGHTS }>,
+    timeout: Timeout,
+}
+
+impl SleepState {
+    pub fn new(device: &mut Device<'_>) -> Self {
+        Self {
+            nights: device.sleep.nights(),
+            timeout: Timeout::new(IDLE_TIMEOUT),
+        }
+    }
+
+    pub async fn draw(&mut self, device: &mut Device<'_>) {
+        SleepView::new(&self.nights)
+            .draw(device.screen.display().await)
+            .unwrap();
+        device.screen.on().await;
+    }
+
+    pub async fn next(&mut self, device: &mut Device<'_>) -> WatchState {
+        match select(self.timeout.wait(device.backlight), button_pressed(device.events)).await {
+            Either::First(_) => WatchState::Idle(IdleState::new(device)),
+            Either::Second(_) => WatchState::Menu(MenuState::new(MenuView::apps())),
+        }
+    }
+}
+
+#[derive(PartialEq)]
+pub struct HeartHistoryState {
+    history: History,
+    now: i64,
+    low: u8,
+    high: u8,
+    alert: Option<Alert>,
+    vibrate: bool,
+    timeout: Timeout,
+}
+
+impl HeartHistoryState {
+    /// With an `alert`, the watch vibrates when the history is shown.
+    pub fn new(device: &mut Device<'_>, alert: Option<Alert>) -> Self {
+        let settings = device.settings.get();
+        Self {
+            history: device.heart_monitor.history(),
+            // Nothing is recorded while the time is not known, so the history is empty anyway
+            now: device.clock.timestamp().unwrap_or(0),
+            low: settings.heart_low,
+            high: settings.heart_high,
+            alert,
+            vibrate: alert.is_some(),
+            timeout: Timeout::new(IDLE_TIMEOUT),
+        }
+    }
+
+    pub async fn draw(&mut self, device: &mut Device<'_>) {
+        HeartHistoryView::new(&self.history, self.now, self.low, self.high, self.alert)
+            .draw(device.screen.display().await)
+            .unwrap();
+        device.screen.on().await;
+        if self.vibrate {
+            self.vibrate = false;
+            device.vibrator.play(Pattern::NOTIFICATION);
+        }
+    }
+
+    pub async fn next(&mut self, device: &mut Device<'_>) -> WatchState {
+        match select(self.timeout.wait(device.backlight), button_pressed(device.events)).await {
+            Either::First(_) => WatchState::Idle(IdleState::new(device)),
+            Either::Second(_) if self.alert.is_some() => {
+                WatchState::Time(TimeState::new(device, Timeout::new(IDLE_TIMEOUT)).await)
+            }
+            Either::Second(_) => WatchState::Menu(MenuState::new(MenuView::more_apps())),
+        }
+    }
+}
+
+#[derive(PartialEq)]
+pub struct BatteryHistoryState {
+    history: battery_history::History,
+    now: i64,
+    percent: u8,
+    charging: bool,
+    /// `None` until the time is known, samples are only logged with a valid time.
+    hours_remaining: Option<u32>,
+    timeout: Timeout,
+}
+
+impl BatteryHistoryState {
+    pub fn new(device: &mut Device<'_>) -> Self {
+        let history = device.battery.history();
+        let now = device.clock.timestamp();
+        let hours_remaining = now.and_then(|now| history.hours_remaining(now));
+        Self {
+            history,
+            // Nothing is recorded while the time is not known, so the history is empty anyway
+            now: now.unwrap_or(0),
+            percent: device.battery.percent(),
+            charging: device.battery.is_charging(),
+            hours_remaining,
+            timeout: Timeout::new(IDLE_TIMEOUT),
+        }
+    }
+
+    pub async fn draw(&mut self, device: &mut Device<'_>) {
+        BatteryHistoryView::new(
+            &self.history,
+            self.now,
+            self.percent,
+            self.charging,
+            self.hours_remaining,
+        )
+        .draw(device.screen.display().await)
+        .unwrap();
+        device.screen.on().await;
+    }
+
+    pub async fn next(&mut self, device: &mut Device<'_>) -> WatchState {
+        match select(self.timeout.wait(device.backlight), button_pressed(device.events)).await {
+            Either::First(_) => WatchState::Idle(IdleState::new(device)),
+            Either::Second(_) => WatchState::Menu(MenuState::new(MenuView::more_apps())),
+        }
+    }
+}
+
+#[derive(PartialEq)]
+pub struct NavigationState {
+    timeout: Timeout,
+    alert: bool,
+}
+
+impl NavigationState {
+    pub fn new(alert: bool) -> Self {
+        Self {
+            timeout: Timeout::new(IDLE_TIMEOUT),
+            alert,
+        }
+    }
+
+    pub async fn draw(&mut self, device: &mut Device<'_>) {
+        if let Some(i) = device.navigation.get() {
+            NavigationView::new(i.direction, &i.distance, &i.narrative, i.progress)
+                .draw(device.screen.display().await)
+                .unwrap();
+        }
+        device.screen.on().await;
+        if self.alert {
+            self.alert = false;
+            device.vibrator.play(Pattern::NOTIFICATION);
+        }
+    }
+
+    pub async fn next(&mut self, device: &mut Device<'_>) -> WatchState {
+        loop {
+            match select4(
+                self.timeout.wait(device.backlight),
+                button_pressed(device.events),
+                device.navigation.wait_manoeuvre(),
+                device.navigation.wait_changed(),
+            )
+            .await
+            {
+                Either4::First(_) => return WatchState::Idle(IdleState::new(device)),
+                Either4::Second(_) => {
+                    return WatchState::Time(TimeState::new(device, Timeout::new(IDLE_TIMEOUT)).await)
                 }
-                MenuAction::ChangeTimeHourDec => {
-                    let new_time = device.clock.get()
-                    	.checked_sub(time::Duration::new(3600, 0));
-					match new_time {
-    					Some(time) => device.clock.set(time),
-    					None => { info!("Decremented time 1 hour too many"); }
-					}
-					
-                    WatchState::Menu(MenuState::new(MenuView::time_settings(
-                    	time_details(device).await,
-                    )))
+                Either4::Third(_) => {
+                    self.timeout = Timeout::new(IDLE_TIMEOUT);
+                    self.alert = true;
+                    self.draw(device).await;
                 }
-            },
+                Either4::Fourth(_) => self.draw(device).await,
+            }
         }
     }
 }
@@ -303,33 +674,60 @@ impl WorkoutState {
     pub async fn draw(&mut self, _device: &mut Device<'_>) {}
     pub async fn next(&mut self, device: &mut Device<'_>) -> WatchState {
         let screen = &mut device.screen;
-        let button = &mut device.button;
-        let hrs = &mut device.hrs;
-        let mut ticker = Ticker::every(Duration::from_secs(2));
-        hrs.init().unwrap();
-        hrs.enable_hrs().unwrap();
-        hrs.enable_oscillator().unwrap();
-
-        let mut seconds = 0;
+        let events = device.events;
+        let heart = device.heart;
+        let measurement = heart.measure();
+        let start = Instant::now();
+        let mut ticker = Ticker::every(Duration::from_secs(1));
+        let mut recorder = Recorder::new(device.clock.timestamp().unwrap_or(0));
+
         let workout = async {
             loop {
-                let hr = hrs.read_hrs().unwrap();
-                WorkoutView::new(hr, time::Duration::new(seconds, 0))
-                    .draw(screen.display())
+                let elapsed = time::Duration::seconds((Instant::now() - start).as_secs() as i64);
+                WorkoutView::new(heart.get(), elapsed)
+                    .draw(screen.display().await)
                     .unwrap();
-                screen.on();
-                ticker.next().await;
-                seconds += 2;
+                screen.on().await;
+                if let Either::First(_) = select(ticker.next(), heart.wait_updated()).await {
+                    let bpm = heart.get().filter(|e| e.confidence >= MIN_CONFIDENCE).map(|e| e.bpm);
+                    recorder.update(bpm);
+                }
             }
         };
+        select(button_pressed(events), workout).await;
+        drop(measurement);
 
-        let next = match select(button.wait(), workout).await {
-            Either::First(_) => WatchState::Menu(MenuState::new(MenuView::main())),
-            Either::Second(state) => state,
-        };
-        hrs.disable_oscillator().unwrap();
-        hrs.disable_hrs().unwrap();
-        next
+        device.workouts.save(device.flash, &mut recorder).await;
+        WatchState::WorkoutHistory(WorkoutHistoryState::new(device))
+    }
+}
+
+#[derive(PartialEq)]
+pub struct WorkoutHistoryState {
+    sessions: heapless::Vec<Summary, { crate::workouts::HISTORY }>,
+    timeout: Timeout,
+}
+
+impl WorkoutHistoryState {
+    pub fn new(device: &mut Device<'_>) -> Self {
+        Self {
+            sessions: device.workouts.history(),
+            timeout: Timeout::new(IDLE_TIMEOUT),
+        }
+    }
+
+    pub async fn draw(&mut self, device: &mut Device<'_>) {
+        WorkoutHistoryView::new(&self.sessions, device.clock.zone())
+            .draw(device.screen.display().await)
+            .unwrap();
+        device.screen.on().await;
+    }
+
+    pub async fn next(&mut self, device: &mut Device<'_>) -> WatchState {
+        match select(self.timeout.wait(device.backlight), button_pressed(device.events)).await {
+            Either::First(_) => WatchState::Idle(IdleState::new(device)),
+            Either::Second(_) => WatchState::Menu(MenuState::new(MenuView::apps())),
+        }
     }
 }
 
@@ -340,18 +738,19 @@ impl TimerState {
     pub async fn draw(&mut self, _device: &mut Device<'_>) {}
     pub async fn next(&mut self, device: &mut Device<'_>) -> WatchState {
         let screen = &mut device.screen;
-        let button = &mut device.button;
- 		let mut ticker = Ticker::every(Duration::from_secs(1));
-  		let vibrator = &mut device.vibrator;
+        let events = device.events;
+        let mut ticker = Ticker::every(Duration::from_secs(1));
+        let vibrator = device.vibrator;
+        let backlight = device.backlight;
 
-		// Hardcoded for now
-		let mut seconds = 5;
+        // Hardcoded for now
+        let mut seconds = 5;
         let timer = async {
-  			loop {
+            loop {
                 TimerView::new(time::Duration::new(seconds, 0), true)
-                    .draw(screen.display())
+                    .draw(screen.display().await)
                     .unwrap();
-                screen.on();
+                screen.on().await;
                 ticker.next().await;
                 seconds -= 1;
 
@@ -361,45 +760,95 @@ impl TimerState {
             }
 
             TimerView::new(time::Duration::ZERO, false)
-                    .draw(screen.display())
-                    .unwrap();
-                screen.on();
-                vibrator.on_for(1500).await;
+                .draw(screen.display().await)
+                .unwrap();
+            screen.on().await;
+            vibrator.play(Pattern::TIMER_DONE);
+            // Stay on the finished timer until it is dismissed, or for a while
+            Timeout::new(TIMER_DONE_TIMEOUT).wait(backlight).await;
         };
 
-        let next = match select(button.wait(), timer).await {
-            Either::First(_) => {
-            	vibrator.off();
-            	WatchState::Menu(MenuState::new(MenuView::main()))
-            },
-            Either::Second(_) => WatchState::Timer(TimerState {}),
-        };
-        next
+        let dismissed = select(button_pressed(events), timer).await;
+        vibrator.stop();
+        match dismissed {
+            Either::First(_) => WatchState::Menu(MenuState::new(MenuView::main())),
+            Either::Second(_) => WatchState::Idle(IdleState::new(device)),
+        }
     }
 }
 
-async fn firmware_details(battery: &crate::device::Battery<'_>, validated: bool) -> FirmwareDetails {
-    const CARGO_NAME: &str = env!("CARGO_PKG_NAME");
-    const CARGO_VERSION: &str = env!("CARGO_PKG_VERSION");
-    const COMMIT: &str = env!("VERGEN_GIT_SHA");
-    const BUILD_TIMESTAMP: &str = env!("VERGEN_BUILD_TIMESTAMP");
-
-    let battery_level = battery.measure().await;
+async fn firmware_details(battery: &crate::battery::BatteryMonitor, validated: bool) -> FirmwareDetails {
+    let battery_level = battery.percent() as u32;
     let battery_charging = battery.is_charging();
 
     FirmwareDetails::new(
-        CARGO_NAME,
-        CARGO_VERSION,
-        COMMIT,
-        BUILD_TIMESTAMP,
+        version::NAME,
+        version::VERSION,
+        version::COMMIT,
+        version::BUILD_TIMESTAMP,
         battery_level,
         battery_charging,
         validated,
     )
 }
 
-async fn time_details(device: &mut Device<'_>) -> TimeDetails {
-    TimeDetails::new(
-        device.clock.get(),
-    )
-}
\ No newline at end of file
+async fn time_details(device: &mut Device<'_>, page: TimePage) -> TimeDetails {
+    TimeDetails::new(device.clock.get(), page)
+}
+
+/// Step a field of the local date and time, starting from 2000 while the time is not known.
+fn adjust_time(clock: &Clock, field: Field, delta: i32) {
+    let now = clock.get();
+    let edited = now
+        .and_then(|now| DateTime::new(now.year(), now.month() as u8, now.day(), now.hour(), now.minute()))
+        .unwrap_or_default()
+        .step(field, delta);
+    let month = time::Month::try_from(edited.month());
+    let date = month.and_then(|month| time::Date::from_calendar_date(edited.year(), month, edited.day()));
+    let time = time::Time::from_hms(edited.hour(), edited.minute(), now.map_or(0, |now| now.second()));
+    match (date, time) {
+        (Ok(date), Ok(time)) => clock.set(time::PrimitiveDateTime::new(date, time)),
+        _ => warn!("unable to set time to {:?}", edited),
+    }
+}
+
+#[derive(PartialEq)]
+pub struct BrightnessState {
+    view: BrightnessView,
+    timeout: Timeout,
+}
+
+impl BrightnessState {
+    pub fn new(device: &mut Device<'_>) -> Self {
+        Self {
+            view: BrightnessView::new(device.settings.get().brightness),
+            timeout: Timeout::new(IDLE_TIMEOUT),
+        }
+    }
+
+    pub async fn draw(&mut self, device: &mut Device<'_>) {
+        self.view.draw(device.screen.display().await).unwrap();
+        device.screen.on().await;
+    }
+
+    pub async fn next(&mut self, device: &mut Device<'_>) -> WatchState {
+        loop {
+            match select(self.timeout.wait(device.backlight), device.events.next()).await {
+                Either::First(_) => return WatchState::Idle(IdleState::new(device)),
+                Either::Second(Event::Button) => return WatchState::Menu(MenuState::new(MenuView::settings())),
+                Either::Second(Event::Touch(gesture)) => {
+                    if let Some(level) = self.view.on_event(InputEvent::Touch(gesture)) {
+                        device.settings.update(|s| s.brightness = level);
+                        // Fades to the new level right away, and back up if it had dimmed
+                        device.backlight.on();
+                        return WatchState::Brightness(Self {
+                            view: BrightnessView::new(level),
+                            timeout: Timeout::new(IDLE_TIMEOUT),
+                        });
+                    }
+                }
+                Either::Second(_) => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use core::cell::RefCell;
+
+use defmt::{info, warn};
+use embassy_sync::blocking_mutex::raw::{NoopRawMutex, ThreadModeRawMutex};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::mutex::Mutex as AsyncMutex;
+use embassy_time::{Duration, Timer};
+use watchful_core::records::RecordLog;
+
+use crate::clock::Clock;
+use crate::power::{Peripheral, Power};
+use crate::{storage, ExternalFlash};
+
+pub const DEFAULT_GOAL: u32 = 10_000;
+
+/// How often the count is written to flash.
+const SAVE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Julian day followed by the step count, both little endian.
+const RECORD_SIZE: usize = 8;
+
+#[derive(Clone, Copy, PartialEq)]
+struct Daily {
+    /// The day the count belongs to, unknown until the clock is set.
+    day: Option<time::Date>,
+    count: u32,
+}
+
+/// Steps taken since local midnight.
+pub struct Steps {
+    daily: Mutex<ThreadModeRawMutex, RefCell<Daily>>,
+}
+
+impl Steps {
+    pub const fn new() -> Self {
+        Self {
+            daily: Mutex::new(RefCell::new(Daily { day: None, count: 0 })),
+        }
+    }
+
+    /// Add steps taken on `today`, starting over if the day has changed.
+    pub fn add(&self, today: Option<time::Date>, steps: u32) {
+        self.daily.lock(|d| {
+            let mut d = d.borrow_mut();
+            roll_over(&mut d, today);
+            d.count += steps;
+        })
+    }
+
+    /// Steps taken on `today`.
+    pub fn get(&self, today: Option<time::Date>) -> u32 {
+        self.daily.lock(|d| {
+            let d = d.borrow();
+            match (d.day, today) {
+                (Some(day), Some(today)) if day != today => 0,
+                _ => d.count,
+            }
+        })
+    }
+
+    fn snapshot(&self) -> Daily {
+        self.daily.lock(|d| *d.borrow())
+    }
+
+    fn restore(&self, restored: Daily) {
+        self.daily.lock(|d| {
+            let mut d = d.borrow_mut();
+            d.day = restored.day;
+            d.count += restored.count;
+        })
+    }
+}
+
+fn roll_over(daily: &mut Daily, today: Option<time::Date>) {
+    match (daily.day, today) {
+        (Some(day), Some(today)) if day != today => {
+            daily.day = Some(today);
+            daily.count = 0;
+        }
+        // Steps counted before the clock was set are assumed to be from today
+        (None, Some(today)) => daily.day = Some(today),
+        _ => {}
+    }
+}
+
+/// Restores today's count at boot and keeps it saved in external flash.
+#[embassy_executor::task]
+pub async fn steps_task(
+    steps: &'static Steps,
+    clock: &'static Clock,
+    flash: &'static AsyncMutex<NoopRawMutex, ExternalFlash>,
+    power: &'static Power,
+) {
+    let mut log = RecordLog::new(storage::STEPS.start, storage::STEPS.end, RECORD_SIZE);
+    {
+        let _flash_power = power.acquire(Peripheral::Flash);
+        let mut flash = flash.lock().await;
+        if log.mount(&mut *flash).await.is_err() {
+            warn!("[steps] unable to mount log");
+        }
+        let mut record = [0; RECORD_SIZE];
+        if let Ok(Some(RECORD_SIZE)) = log.read(&mut *flash, 0, &mut record).await {
+            if let Some(daily) = decode(&record) {
+                info!("[steps] restored {} steps", daily.count);
+                steps.restore(daily);
+            }
+        }
+    }
+
+    let mut saved = steps.snapshot();
+    loop {
+        // Also wake up at midnight, so the count starts over without any new steps
+        Timer::after(until_midnight(clock).min(SAVE_INTERVAL)).await;
+        steps.add(clock.today(), 0);
+
+        let current = steps.snapshot();
+        if current != saved && current.day.is_some() {
+            let record = encode(&current);
+            let _flash_power = power.acquire(Peripheral::Flash);
+            let mut flash = flash.lock().await;
+            match log.append(&mut *flash, &record).await {
+                Ok(()) => saved = current,
+                Err(_) => warn!("[steps] unable to save step count"),
+            }
+        }
+    }
+}
+
+fn until_midnight(clock: &Clock) -> Duration {
+    let Some(now) = clock.get() else {
+        return SAVE_INTERVAL;
+    };
+    let elapsed = now.time() - time::Time::MIDNIGHT;
+    let remaining = time::Duration::DAY - elapsed;
+    Duration::from_secs(remaining.whole_seconds().max(0) as u64 + 1)
+}
+
+fn encode(daily: &Daily) -> [u8; RECORD_SIZE] {
+    let mut record = [0; RECORD_SIZE];
+    let day = daily.day.map(|d| d.to_julian_day()).unwrap_or(0);
+    record[..4].copy_from_slice(&day.to_le_bytes());
+    record[4..].copy_from_slice(&daily.count.to_le_bytes());
+    record
+}
+
+fn decode(record: &[u8; RECORD_SIZE]) -> Option<Daily> {
+    let day = i32::from_le_bytes([record[0], record[1], record[2], record[3]]);
+    let count = u32::from_le_bytes([record[4], record[5], record[6], record[7]]);
+    let day = time::Date::from_julian_day(day).ok()?;
+    Some(Daily { day: Some(day), count })
+}
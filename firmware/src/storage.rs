@@ -0,0 +1,27 @@
+//! Layout of the 4 MB external SPI flash.
+//!
+//! | range               | content                                   |
+//! |---------------------|-------------------------------------------|
+//! | 0x000000 - 0x040000 | bootloader assets and recovery firmware   |
+//! | 0x040000 - 0x0B4000 | DFU image slot                            |
+//! | 0x0B4000 - 0x0B6000 | daily step counts                         |
+//! | 0x0B6000 - 0x0B8000 | user settings                             |
+//! | 0x0B8000 - 0x0BA000 | sleep summaries, one per night            |
+//! | 0x0BA000 - 0x0CA000 | workout sessions, two per sector          |
+//! | 0x0CA000 - 0x0CE000 | battery samples, every 5 minutes          |
+//! | 0x0CE000 - 0x0D6000 | battery samples while calibrating         |
+//! | 0x0D6000 - 0x0D8000 | calibrated battery curve                  |
+//!
+//! Everything after the DFU slot belongs to the application. Each region is a
+//! [`RecordLog`](watchful_core::records::RecordLog) of its own.
+
+use core::ops::Range;
+
+pub const DFU: Range<u32> = 0x40000..0xB4000;
+pub const STEPS: Range<u32> = 0xB4000..0xB6000;
+pub const SETTINGS: Range<u32> = 0xB6000..0xB8000;
+pub const SLEEP: Range<u32> = 0xB8000..0xBA000;
+pub const WORKOUTS: Range<u32> = 0xBA000..0xCA000;
+pub const BATTERY: Range<u32> = 0xCA000..0xCE000;
+pub const BATTERY_CALIBRATION: Range<u32> = 0xCE000..0xD6000;
+pub const BATTERY_CURVE: Range<u32> = 0xD6000..0xD8000;
@@ -0,0 +1,98 @@
+use core::cell::Cell;
+
+use defmt::warn;
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Delay, Duration, Timer};
+use embedded_graphics::prelude::Point;
+use watchful_ui::TouchGesture;
+
+use crate::device::Touchpad;
+use crate::events::{Event, EventBus};
+use crate::power::{Bus, Power};
+
+/// Seconds without touches before the controller slows down its scanning.
+pub const STANDBY_SECONDS: u8 = 5;
+
+/// Whether the touch controller should be scanning, it sleeps while nobody holds
+/// [`Peripheral::Touch`](crate::power::Peripheral::Touch).
+pub struct Touch {
+    active: Mutex<ThreadModeRawMutex, Cell<bool>>,
+    changed: Signal<ThreadModeRawMutex, bool>,
+}
+
+impl Touch {
+    pub const fn new() -> Self {
+        Self {
+            active: Mutex::new(Cell::new(true)),
+            changed: Signal::new(),
+        }
+    }
+
+    pub fn set_active(&self, active: bool) {
+        if self.active.lock(|a| a.replace(active)) != active {
+            self.changed.signal(active);
+        }
+    }
+}
+
+/// Publishes the gestures recognized by the touch controller, and puts it to sleep while
+/// it is not needed.
+#[embassy_executor::task]
+pub async fn touch_task(
+    mut touchpad: Touchpad<'static>,
+    touch: &'static Touch,
+    events: &'static EventBus,
+    power: &'static Power,
+) {
+    loop {
+        match select(touchpad.wait_for_report(), touch.changed.wait()).await {
+            Either::First(()) => {
+                // Waiting is done on the interrupt line, the bus is only needed for the read
+                let i2c = power.hold(Bus::I2c);
+                let result = touchpad.read_event();
+                drop(i2c);
+                match result {
+                    Ok(event) => {
+                        if let Some(gesture) = event.as_ref().and_then(gesture) {
+                            events.publish(Event::Touch(gesture));
+                        }
+                    }
+                    Err(_) => {
+                        warn!("[touch] error reading touchpad");
+                        Timer::after(Duration::from_millis(100)).await;
+                    }
+                }
+            }
+            Either::Second(true) => {}
+            Either::Second(false) => {
+                let i2c = power.hold(Bus::I2c);
+                if touchpad.sleep().is_err() {
+                    warn!("[touch] unable to put touchpad to sleep");
+                }
+                drop(i2c);
+                while !touch.changed.wait().await {}
+                // Only a reset wakes the controller from deep sleep
+                let _i2c = power.hold(Bus::I2c);
+                if touchpad.init(&mut Delay, STANDBY_SECONDS).await.is_err() {
+                    warn!("[touch] unable to wake touchpad");
+                }
+            }
+        }
+    }
+}
+
+fn gesture(event: &cst816s::TouchEvent) -> Option<TouchGesture> {
+    let pos = Point::new(event.x as i32, event.y as i32);
+    Some(match event.gesture {
+        cst816s::Gesture::SingleClick => TouchGesture::SingleTap(pos),
+        cst816s::Gesture::DoubleClick => TouchGesture::DoubleTap(pos),
+        cst816s::Gesture::SlideUp => TouchGesture::SwipeUp(pos),
+        cst816s::Gesture::SlideDown => TouchGesture::SwipeDown(pos),
+        cst816s::Gesture::SlideLeft => TouchGesture::SwipeLeft(pos),
+        cst816s::Gesture::SlideRight => TouchGesture::SwipeRight(pos),
+        _ => return None,
+    })
+}
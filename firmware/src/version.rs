@@ -0,0 +1,9 @@
+//! What firmware is running, for the firmware settings, the device information service and DFU.
+
+pub const NAME: &str = env!("CARGO_PKG_NAME");
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const COMMIT: &str = env!("VERGEN_GIT_SHA");
+pub const BUILD_TIMESTAMP: &str = env!("VERGEN_BUILD_TIMESTAMP");
+
+/// [`VERSION`] as the number DFU reports.
+pub const NUMBER: u32 = watchful_core::version::number(VERSION);
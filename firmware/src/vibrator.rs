@@ -0,0 +1,57 @@
+use embassy_futures::select::{select, Either};
+use embassy_nrf::gpio::Output;
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+use watchful_core::vibration::Pattern;
+
+/// Plays vibration patterns in the background, see [`vibrator_task`].
+pub struct Vibrator {
+    /// The pattern to play next, `None` to stop.
+    request: Signal<ThreadModeRawMutex, Option<Pattern>>,
+}
+
+impl Vibrator {
+    pub const fn new() -> Self {
+        Self { request: Signal::new() }
+    }
+
+    /// Start `pattern`, replacing whatever is playing.
+    pub fn play(&self, pattern: Pattern) {
+        self.request.signal(Some(pattern));
+    }
+
+    pub fn stop(&self) {
+        self.request.signal(None);
+    }
+}
+
+/// Drives the motor, which is on while its pin is low, through the requested patterns.
+#[embassy_executor::task]
+pub async fn vibrator_task(mut motor: Output<'static>, vibrator: &'static Vibrator) {
+    let mut next = None;
+    loop {
+        let pattern = match next.take() {
+            Some(pattern) => pattern,
+            None => match vibrator.request.wait().await {
+                Some(pattern) => pattern,
+                None => continue,
+            },
+        };
+
+        let play = async {
+            for (on, ms) in pattern.steps() {
+                if on {
+                    motor.set_low();
+                } else {
+                    motor.set_high();
+                }
+                Timer::after(Duration::from_millis(ms as u64)).await;
+            }
+        };
+        if let Either::Second(request) = select(play, vibrator.request.wait()).await {
+            next = request;
+        }
+        motor.set_high();
+    }
+}
@@ -0,0 +1,51 @@
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use watchful_core::weather::{CurrentWeather, Forecast, WeatherMessage};
+
+/// A weather report together with the wall clock time it was received at.
+#[derive(Clone)]
+pub struct Report<T> {
+    pub data: T,
+    pub received: time::PrimitiveDateTime,
+}
+
+struct Reports {
+    current: Option<Report<CurrentWeather>>,
+    forecast: Option<Report<Forecast>>,
+}
+
+/// Latest weather pushed by the phone through the SimpleWeather service.
+pub struct Weather {
+    reports: Mutex<ThreadModeRawMutex, RefCell<Reports>>,
+}
+
+impl Weather {
+    pub const fn new() -> Self {
+        Self {
+            reports: Mutex::new(RefCell::new(Reports {
+                current: None,
+                forecast: None,
+            })),
+        }
+    }
+
+    pub fn update(&self, message: WeatherMessage, received: time::PrimitiveDateTime) {
+        self.reports.lock(|r| {
+            let mut r = r.borrow_mut();
+            match message {
+                WeatherMessage::Current(data) => r.current = Some(Report { data, received }),
+                WeatherMessage::Forecast(data) => r.forecast = Some(Report { data, received }),
+            }
+        })
+    }
+
+    pub fn current(&self) -> Option<Report<CurrentWeather>> {
+        self.reports.lock(|r| r.borrow().current.clone())
+    }
+
+    pub fn forecast(&self) -> Option<Report<Forecast>> {
+        self.reports.lock(|r| r.borrow().forecast.clone())
+    }
+}
@@ -0,0 +1,100 @@
+use core::cell::RefCell;
+
+use defmt::{info, warn};
+use embassy_sync::blocking_mutex::raw::{NoopRawMutex, ThreadModeRawMutex};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::mutex::Mutex as AsyncMutex;
+use watchful_core::records::RecordLog;
+use watchful_core::workout::{Recorder, Summary, MAX_ENCODED_SIZE};
+
+use crate::power::Peripheral;
+use crate::{storage, ExternalFlash};
+
+/// Sessions kept in memory for the history view.
+pub const HISTORY: usize = 8;
+
+/// Sessions shorter than this (in seconds) were most likely started by accident.
+const MIN_DURATION: u32 = 60;
+
+/// Finished workout sessions in external flash.
+pub struct Workouts {
+    log: AsyncMutex<ThreadModeRawMutex, RecordLog>,
+    /// Summaries of the last sessions, newest first.
+    history: Mutex<ThreadModeRawMutex, RefCell<heapless::Vec<Summary, HISTORY>>>,
+}
+
+impl Workouts {
+    pub const fn new() -> Self {
+        Self {
+            log: AsyncMutex::new(RecordLog::new(
+                storage::WORKOUTS.start,
+                storage::WORKOUTS.end,
+                MAX_ENCODED_SIZE,
+            )),
+            history: Mutex::new(RefCell::new(heapless::Vec::new())),
+        }
+    }
+
+    /// Find the sessions saved before the last reset.
+    pub async fn load(&self, flash: &AsyncMutex<NoopRawMutex, ExternalFlash>) {
+        let _flash_power = crate::POWER.acquire(Peripheral::Flash);
+        let mut log = self.log.lock().await;
+        let mut flash = flash.lock().await;
+        if log.mount(&mut *flash).await.is_err() {
+            warn!("[workouts] unable to mount log");
+        }
+        // Only the header of each record is needed
+        let mut record = [0; MAX_ENCODED_SIZE];
+        let mut history = heapless::Vec::new();
+        for n in 0..HISTORY {
+            match log.read(&mut *flash, n, &mut record).await {
+                Ok(Some(len)) => {
+                    if let Some(summary) = Summary::decode(&record[..len]) {
+                        let _ = history.push(summary);
+                    }
+                }
+                _ => break,
+            }
+        }
+        self.history.lock(|h| *h.borrow_mut() = history);
+    }
+
+    pub async fn save(&self, flash: &AsyncMutex<NoopRawMutex, ExternalFlash>, recorder: &mut Recorder) {
+        let duration = recorder.summary().duration;
+        if duration < MIN_DURATION {
+            return;
+        }
+        info!("[workouts] saving {} seconds", duration);
+        let _flash_power = crate::POWER.acquire(Peripheral::Flash);
+        let mut log = self.log.lock().await;
+        let mut flash = flash.lock().await;
+        if log.append(&mut *flash, recorder.encode()).await.is_err() {
+            warn!("[workouts] unable to save session");
+            return;
+        }
+        let summary = recorder.summary();
+        self.history.lock(|h| {
+            let mut h = h.borrow_mut();
+            h.truncate(HISTORY - 1);
+            let _ = h.insert(0, summary);
+        })
+    }
+
+    /// Summaries of the last sessions, newest first.
+    pub fn history(&self) -> heapless::Vec<Summary, HISTORY> {
+        self.history.lock(|h| h.borrow().clone())
+    }
+
+    /// Read the `n`th newest session into `buf`, returning its encoded length.
+    pub async fn read(
+        &self,
+        flash: &AsyncMutex<NoopRawMutex, ExternalFlash>,
+        n: usize,
+        buf: &mut [u8; MAX_ENCODED_SIZE],
+    ) -> Option<usize> {
+        let _flash_power = crate::POWER.acquire(Peripheral::Flash);
+        let log = self.log.lock().await;
+        let mut flash = flash.lock().await;
+        log.read(&mut *flash, n, buf).await.ok().flatten()
+    }
+}
@@ -40,6 +40,7 @@ bitflags! {
 
 pub struct XtFlash<SPI: SpiDevice> {
     spi: SPI,
+    powered_down: bool,
 }
 
 #[derive(Debug)]
@@ -78,11 +79,39 @@ impl<SPI: SpiDevice> XtFlash<SPI> {
 
         spi.write(&[0x50])?;
 
-        Ok(Self { spi })
+        Ok(Self {
+            spi,
+            powered_down: false,
+        })
+    }
+
+    /// Enter deep power-down. The flash ignores everything but the wakeup command until
+    /// then, the next operation wakes it up again.
+    pub fn power_down(&mut self) -> Result<(), Error<SPI::Error>> {
+        if !self.powered_down {
+            self.spi.write(&[OpCode::PowerDown as u8])?;
+            self.powered_down = true;
+        }
+        Ok(())
+    }
+
+    pub fn wake_up(&mut self) -> Result<(), Error<SPI::Error>> {
+        if self.powered_down {
+            // Release from power-down, the device id read back gives it the time it needs
+            let mut value: [u8; 5] = [OpCode::Wakeup as u8, 0, 0, 0, 0];
+            self.spi.transfer_in_place(&mut value[..])?;
+            self.powered_down = false;
+        }
+        Ok(())
+    }
+
+    pub fn is_powered_down(&self) -> bool {
+        self.powered_down
     }
 
     pub async fn erase(&mut self, from: u32, to: u32) -> Result<(), Error<SPI::Error>> {
         check_erase(self, from, to).map_err(Error::Flash)?;
+        self.wake_up()?;
 
         // info!("Erase 0x{:x} - 0x{:x}", from, to);
         for page in (from..to).step_by(ERASE_SIZE) {
@@ -104,6 +133,7 @@ impl<SPI: SpiDevice> XtFlash<SPI> {
     }
 
     pub fn read_status(&mut self) -> Result<StatusRegister, Error<SPI::Error>> {
+        self.wake_up()?;
         let mut value = [OpCode::ReadStatus as u8, 0x00];
         self.spi
             .transaction(&mut [Operation::TransferInPlace(&mut value[..])])?;
@@ -133,6 +163,7 @@ impl<SPI: SpiDevice> XtFlash<SPI> {
 
     pub async fn write(&mut self, mut write_offset: u32, data: &[u8]) -> Result<(), Error<SPI::Error>> {
         check_write(self, write_offset, data.len()).map_err(Error::Flash)?;
+        self.wake_up()?;
         for chunk in data.chunks(PAGE_SIZE / 2) {
             self.write_enable()?;
 
@@ -151,6 +182,7 @@ impl<SPI: SpiDevice> XtFlash<SPI> {
     }
 
     pub async fn read(&mut self, mut offset: u32, data: &mut [u8]) -> Result<(), Error<SPI::Error>> {
+        self.wake_up()?;
         for chunk in data.chunks_mut(PAGE_SIZE / 2) {
             let off = offset.to_be_bytes();
             let cmd = [OpCode::Read as u8, off[1], off[2], off[3]];
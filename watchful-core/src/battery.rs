@@ -0,0 +1,195 @@
+//! Battery level from the cell voltage.
+//!
+//! Single ADC samples are noisy and sag while the screen or the heart rate sensor draw
+//! current, so samples are smoothed, corrected for the load at the time and mapped to a
+//! percentage that only moves in the direction the battery is going.
+
+/// Cell voltage in millivolts at rest against percent, from empty to full.
+pub const DEFAULT_CURVE: [(u16, u8); 6] = [(3500, 0), (3616, 3), (3723, 22), (3776, 48), (3979, 79), (4180, 100)];
+
+/// Internal resistance of the cell and its protection circuit, the voltage drops by this
+/// much per ampere drawn.
+const INTERNAL_RESISTANCE_MILLIOHM: u32 = 250;
+
+/// Weight of a new sample in the smoothed voltage, as a shift: 1/8.
+const FILTER_SHIFT: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ChargeState {
+    Discharging,
+    Charging,
+    /// On the charger with charging finished.
+    Charged,
+}
+
+impl ChargeState {
+    pub fn new(power_present: bool, charging: bool) -> Self {
+        match (power_present, charging) {
+            (false, _) => Self::Discharging,
+            (true, true) => Self::Charging,
+            (true, false) => Self::Charged,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Status {
+    /// Smoothed voltage, corrected for the load at the time of each sample.
+    pub millivolts: u16,
+    pub percent: u8,
+    pub state: ChargeState,
+}
+
+/// Interpolate `millivolts` in `curve`, a list of voltages and percentages sorted by
+/// voltage.
+pub fn percent(curve: &[(u16, u8)], millivolts: u16) -> u8 {
+    let Some(&(first_mv, first_percent)) = curve.first() else {
+        return 0;
+    };
+    if millivolts <= first_mv {
+        return first_percent;
+    }
+    for pair in curve.windows(2) {
+        let ((low_mv, low), (high_mv, high)) = (pair[0], pair[1]);
+        if millivolts < high_mv {
+            let span = (high_mv - low_mv).max(1) as u32;
+            let offset = (millivolts - low_mv) as u32;
+            return (low as u32 + offset * (high.saturating_sub(low)) as u32 / span) as u8;
+        }
+    }
+    curve[curve.len() - 1].1
+}
+
+/// The voltage the cell would show at rest while `load_ma` is being drawn from it.
+pub fn resting_voltage(millivolts: u16, load_ma: u16) -> u16 {
+    let drop = load_ma as u32 * INTERNAL_RESISTANCE_MILLIOHM / 1000;
+    (millivolts as u32 + drop).min(u16::MAX as u32) as u16
+}
+
+/// Turns raw samples into a [`Status`].
+pub struct Gauge {
+    /// Smoothed voltage with 4 fractional bits.
+    filtered: Option<u32>,
+    status: Option<Status>,
+}
+
+impl Default for Gauge {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Gauge {
+    pub const fn new() -> Self {
+        Self {
+            filtered: None,
+            status: None,
+        }
+    }
+
+    pub fn status(&self) -> Option<Status> {
+        self.status
+    }
+
+    /// Add a sample of `millivolts` taken while about `load_ma` was drawn.
+    pub fn update(&mut self, curve: &[(u16, u8)], millivolts: u16, load_ma: u16, state: ChargeState) -> Status {
+        let previous = self.status.filter(|s| s.state == state);
+        // Plugging in or out makes the voltage jump, start over instead of dragging along
+        if previous.is_none() {
+            self.filtered = None;
+        }
+
+        let sample = (resting_voltage(millivolts, load_ma) as u32) << 4;
+        let filtered = match self.filtered {
+            Some(f) => f - (f >> FILTER_SHIFT) + (sample >> FILTER_SHIFT),
+            None => sample,
+        };
+        self.filtered = Some(filtered);
+        let resting = (filtered >> 4) as u16;
+
+        let level = percent(curve, resting);
+        // Noise is not allowed to move the level against the charge direction
+        let level = match (state, previous) {
+            (ChargeState::Charged, _) => 100,
+            (ChargeState::Discharging, Some(previous)) => level.min(previous.percent),
+            (ChargeState::Charging, Some(previous)) => level.max(previous.percent),
+            (_, None) => level,
+        };
+
+        let status = Status {
+            millivolts: resting,
+            percent: level,
+            state,
+        };
+        self.status = Some(status);
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolates_curve() {
+        assert_eq!(percent(&DEFAULT_CURVE, 3000), 0);
+        assert_eq!(percent(&DEFAULT_CURVE, 3500), 0);
+        assert_eq!(percent(&DEFAULT_CURVE, 3776), 48);
+        assert_eq!(percent(&DEFAULT_CURVE, 3877), 63);
+        assert_eq!(percent(&DEFAULT_CURVE, 4180), 100);
+        assert_eq!(percent(&DEFAULT_CURVE, 4300), 100);
+        assert_eq!(percent(&[], 4000), 0);
+    }
+
+    #[test]
+    fn compensates_load() {
+        assert_eq!(resting_voltage(3800, 0), 3800);
+        assert_eq!(resting_voltage(3800, 40), 3810);
+    }
+
+    #[test]
+    fn charge_state_from_pins() {
+        assert_eq!(ChargeState::new(false, false), ChargeState::Discharging);
+        assert_eq!(ChargeState::new(false, true), ChargeState::Discharging);
+        assert_eq!(ChargeState::new(true, true), ChargeState::Charging);
+        assert_eq!(ChargeState::new(true, false), ChargeState::Charged);
+    }
+
+    #[test]
+    fn smooths_noise() {
+        let mut gauge = Gauge::new();
+        let first = gauge.update(&DEFAULT_CURVE, 3877, 0, ChargeState::Discharging);
+        assert_eq!(first.percent, 63);
+        // A single low sample only moves the level a little
+        let dip = gauge.update(&DEFAULT_CURVE, 3700, 0, ChargeState::Discharging);
+        assert!(dip.percent >= 58, "{dip:?}");
+        // And the level does not bounce back up while discharging
+        for _ in 0..20 {
+            let status = gauge.update(&DEFAULT_CURVE, 3877, 0, ChargeState::Discharging);
+            assert_eq!(status.percent, dip.percent);
+        }
+    }
+
+    #[test]
+    fn restarts_on_state_change() {
+        let mut gauge = Gauge::new();
+        gauge.update(&DEFAULT_CURVE, 3700, 0, ChargeState::Discharging);
+        let charging = gauge.update(&DEFAULT_CURVE, 4000, 0, ChargeState::Charging);
+        assert_eq!(charging.percent, percent(&DEFAULT_CURVE, 4000));
+        let charged = gauge.update(&DEFAULT_CURVE, 4150, 0, ChargeState::Charged);
+        assert_eq!(charged.percent, 100);
+        assert_eq!(gauge.status(), Some(charged));
+    }
+
+    #[test]
+    fn load_raises_level() {
+        let mut idle = Gauge::new();
+        let mut busy = Gauge::new();
+        let idle = idle.update(&DEFAULT_CURVE, 3750, 0, ChargeState::Discharging);
+        let busy = busy.update(&DEFAULT_CURVE, 3750, 100, ChargeState::Discharging);
+        assert!(busy.percent > idle.percent);
+        assert_eq!(busy.millivolts, 3775);
+    }
+}
@@ -0,0 +1,234 @@
+//! Per-device battery curve from a recorded discharge.
+//!
+//! The cells in our units do not follow [`DEFAULT_CURVE`](crate::battery::DEFAULT_CURVE)
+//! closely. During calibration the battery is logged from a full charge until it is
+//! nearly empty, and the level at each point in time is taken to be the share of the
+//! discharge time still left. That assumes an even drain, which holds well enough over
+//! days of normal use.
+//!
+//! A fitted curve is stored as [`CURVE_POINTS`] pairs of voltage in millivolts, little
+//! endian `u16`, and percent, `u8`, from empty to full.
+
+/// Points in a fitted curve, one every 10%.
+pub const CURVE_POINTS: usize = 11;
+pub const CURVE_ENCODED_SIZE: usize = CURVE_POINTS * 3;
+
+/// A discharge only counts as complete once the battery got this low.
+pub const EMPTY_MILLIVOLTS: u16 = 3600;
+
+/// Readings kept of a discharge, longer discharges are thinned out.
+const MAX_READINGS: usize = 128;
+/// A discharge needs at least this many readings to fit a curve.
+const MIN_READINGS: usize = 12;
+
+pub type Curve = [(u16, u8); CURVE_POINTS];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Reading {
+    /// Seconds since 1970.
+    pub time: i64,
+    pub millivolts: u16,
+}
+
+/// Fit a curve to the `readings` of a discharge, newest first. `None` if the discharge
+/// is too short or did not get close to empty.
+pub fn fit(readings: &[Reading]) -> Option<Curve> {
+    let (newest, oldest) = (readings.first()?, readings.last()?);
+    let duration = newest.time - oldest.time;
+    if readings.len() < MIN_READINGS || duration <= 0 || newest.millivolts > EMPTY_MILLIVOLTS {
+        return None;
+    }
+
+    let mut curve = [(0, 0); CURVE_POINTS];
+    let mut previous = 0;
+    for (i, point) in curve.iter_mut().enumerate() {
+        let percent = (i * 100 / (CURVE_POINTS - 1)) as u8;
+        let time = newest.time - duration * percent as i64 / 100;
+        // Noise is not allowed to make the curve go down
+        let millivolts = voltage_at(readings, time).max(previous);
+        previous = millivolts;
+        *point = (millivolts, percent);
+    }
+    Some(curve)
+}
+
+/// Interpolate the voltage at `time` in `readings`, newest first.
+fn voltage_at(readings: &[Reading], time: i64) -> u16 {
+    for pair in readings.windows(2) {
+        let (newer, older) = (pair[0], pair[1]);
+        if time >= older.time {
+            let span = (newer.time - older.time).max(1);
+            let offset = (time - older.time).min(span);
+            let delta = newer.millivolts as i64 - older.millivolts as i64;
+            return (older.millivolts as i64 + delta * offset / span) as u16;
+        }
+    }
+    readings.last().map_or(0, |r| r.millivolts)
+}
+
+pub fn encode_curve(curve: &Curve) -> [u8; CURVE_ENCODED_SIZE] {
+    let mut buf = [0; CURVE_ENCODED_SIZE];
+    for (chunk, (millivolts, percent)) in buf.chunks_exact_mut(3).zip(curve) {
+        chunk[..2].copy_from_slice(&millivolts.to_le_bytes());
+        chunk[2] = *percent;
+    }
+    buf
+}
+
+/// `None` unless the points are in order from empty to full.
+pub fn decode_curve(data: &[u8]) -> Option<Curve> {
+    let data = data.get(..CURVE_ENCODED_SIZE)?;
+    let mut curve = [(0, 0); CURVE_POINTS];
+    for (point, chunk) in curve.iter_mut().zip(data.chunks_exact(3)) {
+        *point = (u16::from_le_bytes([chunk[0], chunk[1]]), chunk[2]);
+    }
+    let ordered = curve.windows(2).all(|w| w[0].0 <= w[1].0 && w[0].1 < w[1].1);
+    (ordered && curve[CURVE_POINTS - 1].1 <= 100).then_some(curve)
+}
+
+/// Collects the readings of a discharge read back newest first, thinning them out to fit
+/// in memory however long the discharge took.
+pub struct Discharge {
+    readings: [Reading; MAX_READINGS],
+    len: usize,
+    /// Only every `stride`th reading is kept.
+    stride: usize,
+    /// Readings to skip before the next one is kept.
+    skip: usize,
+    /// The oldest reading so far, kept even when thinned out so the start is exact.
+    oldest: Option<Reading>,
+}
+
+impl Default for Discharge {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Discharge {
+    pub const fn new() -> Self {
+        Self {
+            readings: [Reading { time: 0, millivolts: 0 }; MAX_READINGS],
+            len: 0,
+            stride: 1,
+            skip: 0,
+            oldest: None,
+        }
+    }
+
+    /// Add the next older reading.
+    pub fn push(&mut self, reading: Reading) {
+        self.oldest = Some(reading);
+        if self.skip > 0 {
+            self.skip -= 1;
+            return;
+        }
+        if self.len == MAX_READINGS {
+            // Keep every other reading from now on, this one lands on the new stride
+            for i in 0..MAX_READINGS / 2 {
+                self.readings[i] = self.readings[i * 2];
+            }
+            self.len = MAX_READINGS / 2;
+            self.stride *= 2;
+        }
+        self.readings[self.len] = reading;
+        self.len += 1;
+        self.skip = self.stride - 1;
+    }
+
+    pub fn fit(&mut self) -> Option<Curve> {
+        if let Some(oldest) = self.oldest {
+            if self.readings[..self.len].last() != Some(&oldest) {
+                if self.len == MAX_READINGS {
+                    self.len -= 1;
+                }
+                self.readings[self.len] = oldest;
+                self.len += 1;
+            }
+        }
+        fit(&self.readings[..self.len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battery::{percent, DEFAULT_CURVE};
+
+    const START: i64 = 1_760_000_000;
+
+    /// A discharge over `hours` following `curve`, every 10 minutes, newest first.
+    fn discharge(curve: &[(u16, u8)], hours: i64) -> Vec<Reading> {
+        let steps = hours * 6;
+        (0..=steps)
+            .rev()
+            .map(|i| {
+                let level = 100 - (i * 100 / steps) as u8;
+                // Invert the curve to find the voltage for the level
+                let millivolts = (3500..=4300).find(|&mv| percent(curve, mv) >= level).unwrap();
+                Reading {
+                    time: START + i * 600,
+                    millivolts,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fits_known_curve() {
+        let readings = discharge(&DEFAULT_CURVE, 100);
+        let curve = fit(&readings).unwrap();
+        assert_eq!(curve[0], (3500, 0));
+        assert_eq!(curve[10].1, 100);
+        // Every point lies on the curve the discharge followed
+        for (mv, level) in curve {
+            let diff = percent(&DEFAULT_CURVE, mv) as i32 - level as i32;
+            assert!(diff.abs() <= 1, "{level}% at {mv} mV off by {diff}");
+        }
+    }
+
+    #[test]
+    fn curve_never_goes_down() {
+        let mut readings = discharge(&DEFAULT_CURVE, 50);
+        // A spike half way through
+        let middle = readings.len() / 2;
+        readings[middle].millivolts = 4200;
+        let curve = fit(&readings).unwrap();
+        assert!(curve.windows(2).all(|w| w[0].0 <= w[1].0));
+    }
+
+    #[test]
+    fn needs_complete_discharge() {
+        assert_eq!(fit(&[]), None);
+        let readings = discharge(&DEFAULT_CURVE, 50);
+        assert_eq!(fit(&readings[..5]), None);
+        // Stopped half way
+        let half = readings.len() / 2;
+        assert_eq!(fit(&readings[half..]), None);
+    }
+
+    #[test]
+    fn thins_out_long_discharges() {
+        let readings = discharge(&DEFAULT_CURVE, 200);
+        assert!(readings.len() > MAX_READINGS * 4);
+        let mut collected = Discharge::new();
+        for r in &readings {
+            collected.push(*r);
+        }
+        let thinned = collected.fit().unwrap();
+        let full = fit(&readings).unwrap();
+        for (a, b) in thinned.iter().zip(&full) {
+            assert_eq!(a.1, b.1);
+            assert!(a.0.abs_diff(b.0) <= 10, "{a:?} against {b:?}");
+        }
+    }
+
+    #[test]
+    fn encoding() {
+        let curve = fit(&discharge(&DEFAULT_CURVE, 50)).unwrap();
+        assert_eq!(decode_curve(&encode_curve(&curve)), Some(curve));
+        assert_eq!(decode_curve(&[0; 4]), None);
+        assert_eq!(decode_curve(&[0xFF; CURVE_ENCODED_SIZE]), None);
+    }
+}
@@ -0,0 +1,272 @@
+//! Battery level over the last two days and an estimate of the time left.
+//!
+//! A [`Sample`] is logged to flash every [`LOG_MINUTES`]. Encoded it is 8 bytes:
+//!
+//! | offset | size | field                                          |
+//! |--------|------|------------------------------------------------|
+//! | 0      | 4    | time, seconds since 1970, little endian        |
+//! | 4      | 2    | voltage in millivolts, little endian           |
+//! | 6      | 1    | level in percent                               |
+//! | 7      | 1    | 0 discharging, 1 charging, 2 charged           |
+
+use crate::battery::{ChargeState, Status};
+
+/// Minutes between logged samples.
+pub const LOG_MINUTES: i64 = 5;
+/// Minutes covered by each slot of the history.
+pub const SLOT_MINUTES: i64 = 30;
+pub const SLOTS: usize = (48 * 60 / SLOT_MINUTES) as usize;
+pub const ENCODED_SIZE: usize = 8;
+
+/// How far back the discharge rate is taken from.
+const ESTIMATE_SLOTS: usize = (12 * 60 / SLOT_MINUTES) as usize;
+/// The discharge rate needs at least this many slots on battery.
+const ESTIMATE_MIN_SLOTS: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Sample {
+    /// Seconds since 1970.
+    pub time: i64,
+    pub millivolts: u16,
+    pub percent: u8,
+    pub state: ChargeState,
+}
+
+impl Sample {
+    pub fn new(time: i64, status: Status) -> Self {
+        Self {
+            time,
+            millivolts: status.millivolts,
+            percent: status.percent,
+            state: status.state,
+        }
+    }
+
+    pub fn encode(&self) -> [u8; ENCODED_SIZE] {
+        let mut buf = [0; ENCODED_SIZE];
+        buf[0..4].copy_from_slice(&(self.time as u32).to_le_bytes());
+        buf[4..6].copy_from_slice(&self.millivolts.to_le_bytes());
+        buf[6] = self.percent;
+        buf[7] = match self.state {
+            ChargeState::Discharging => 0,
+            ChargeState::Charging => 1,
+            ChargeState::Charged => 2,
+        };
+        buf
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let data: &[u8; ENCODED_SIZE] = data.get(..ENCODED_SIZE)?.try_into().ok()?;
+        Some(Self {
+            time: u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as i64,
+            millivolts: u16::from_le_bytes([data[4], data[5]]),
+            percent: data[6],
+            state: match data[7] {
+                0 => ChargeState::Discharging,
+                1 => ChargeState::Charging,
+                2 => ChargeState::Charged,
+                _ => return None,
+            },
+        })
+    }
+}
+
+/// What the history keeps of the newest sample in a slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Point {
+    pub percent: u8,
+    /// On the charger, charging or charged.
+    pub charging: bool,
+}
+
+/// Battery levels of the last 48 hours.
+#[derive(Clone, PartialEq)]
+pub struct History {
+    slots: [Option<Point>; SLOTS],
+    /// Number of the newest slot recorded, counted from 1970.
+    newest: Option<i64>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl History {
+    pub const fn new() -> Self {
+        Self {
+            slots: [None; SLOTS],
+            newest: None,
+        }
+    }
+
+    /// Record a new sample, replacing the one in its slot.
+    pub fn record(&mut self, sample: &Sample) {
+        self.insert(sample, true);
+    }
+
+    /// Record a sample read back from the log. The log is read newest first, so samples
+    /// never replace what is in their slot already.
+    pub fn load(&mut self, sample: &Sample) {
+        self.insert(sample, false);
+    }
+
+    fn insert(&mut self, sample: &Sample, replace: bool) {
+        let slot = slot(sample.time);
+        match self.newest {
+            Some(newest) if slot > newest => {
+                // Forget the slots skipped since
+                for s in (newest + 1).max(slot - SLOTS as i64 + 1)..slot {
+                    self.slots[index(s)] = None;
+                }
+                self.newest = Some(slot);
+            }
+            // Older than anything kept
+            Some(newest) if slot <= newest - SLOTS as i64 => return,
+            Some(_) if !replace && self.slots[index(slot)].is_some() => return,
+            Some(_) => {}
+            None => {
+                self.slots = [None; SLOTS];
+                self.newest = Some(slot);
+            }
+        }
+        self.slots[index(slot)] = Some(Point {
+            percent: sample.percent,
+            charging: sample.state != ChargeState::Discharging,
+        });
+    }
+
+    /// The levels of the 48 hours up to `now`, oldest first.
+    pub fn slots(&self, now: i64) -> impl DoubleEndedIterator<Item = Option<Point>> + '_ {
+        let now = slot(now);
+        (now - SLOTS as i64 + 1..=now).map(move |s| match self.newest {
+            Some(newest) if s <= newest && s > newest - SLOTS as i64 => self.slots[index(s)],
+            _ => None,
+        })
+    }
+
+    /// Hours until empty at the rate the level dropped since the watch was last charged,
+    /// looking back at most 12 hours. `None` while charging or without enough history.
+    pub fn hours_remaining(&self, now: i64) -> Option<u32> {
+        // The slot of `now` stays empty until the first sample in it is logged
+        let mut recent = self.slots(now).rev().take(ESTIMATE_SLOTS).skip_while(|p| p.is_none());
+        let newest = recent.next().flatten().filter(|p| !p.charging)?;
+        let (slots, oldest) = recent
+            .map_while(|p| p.filter(|p| !p.charging))
+            .enumerate()
+            .last()
+            .map(|(i, p)| (i + 1, p))?;
+        let dropped = oldest.percent.saturating_sub(newest.percent) as u32;
+        if slots < ESTIMATE_MIN_SLOTS || dropped == 0 {
+            return None;
+        }
+        let minutes = slots as u32 * SLOT_MINUTES as u32;
+        Some(newest.percent as u32 * minutes / dropped / 60)
+    }
+}
+
+fn slot(time: i64) -> i64 {
+    time.div_euclid(SLOT_MINUTES * 60)
+}
+
+fn index(slot: i64) -> usize {
+    slot.rem_euclid(SLOTS as i64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: i64 = 3600;
+    const START: i64 = 1_760_000_000 / HOUR * HOUR;
+
+    fn sample(time: i64, percent: u8, state: ChargeState) -> Sample {
+        Sample {
+            time,
+            millivolts: 3500 + percent as u16 * 6,
+            percent,
+            state,
+        }
+    }
+
+    #[test]
+    fn encoding() {
+        let s = sample(START, 73, ChargeState::Charging);
+        assert_eq!(Sample::decode(&s.encode()), Some(s));
+        assert_eq!(Sample::decode(&[0; 4]), None);
+        let mut invalid = s.encode();
+        invalid[7] = 9;
+        assert_eq!(Sample::decode(&invalid), None);
+    }
+
+    #[test]
+    fn history() {
+        let mut history = History::new();
+        assert!(history.slots(START).all(|p| p.is_none()));
+        // Every 5 minutes for 3 hours, dropping 1% every half hour
+        for i in 0..36 {
+            history.record(&sample(START + i * 300, 90 - (i / 6) as u8, ChargeState::Discharging));
+        }
+        let now = START + 3 * HOUR - 1;
+        let slots: Vec<_> = history.slots(now).collect();
+        assert_eq!(slots.len(), SLOTS);
+        assert_eq!(slots.iter().flatten().count(), 6);
+        assert_eq!(slots[SLOTS - 1].map(|p| p.percent), Some(85));
+        assert_eq!(slots[SLOTS - 6].map(|p| p.percent), Some(90));
+        // Two days later it has all scrolled out
+        assert!(history.slots(now + 48 * HOUR).all(|p| p.is_none()));
+    }
+
+    #[test]
+    fn loading_keeps_newest() {
+        let mut history = History::new();
+        history.load(&sample(START + 600, 50, ChargeState::Discharging));
+        history.load(&sample(START + 300, 51, ChargeState::Discharging));
+        history.load(&sample(START - 600, 52, ChargeState::Discharging));
+        let slots: Vec<_> = history.slots(START + 600).collect();
+        assert_eq!(slots[SLOTS - 1].map(|p| p.percent), Some(50));
+        assert_eq!(slots[SLOTS - 2].map(|p| p.percent), Some(52));
+    }
+
+    #[test]
+    fn estimate() {
+        let mut history = History::new();
+        assert_eq!(history.hours_remaining(START), None);
+        // Charged up to 90%, then 2% an hour
+        history.record(&sample(START - HOUR, 90, ChargeState::Charging));
+        for i in 0..=20 {
+            history.record(&sample(START + i * 30 * 60, 90 - i as u8, ChargeState::Discharging));
+        }
+        let now = START + 10 * HOUR;
+        assert_eq!(history.hours_remaining(now), Some(35));
+
+        // Not while charging
+        history.record(&sample(now + 60, 71, ChargeState::Charging));
+        assert_eq!(history.hours_remaining(now + 60), None);
+    }
+
+    #[test]
+    fn estimate_at_slot_start() {
+        let mut history = History::new();
+        // Every 5 minutes for 6 hours, dropping 1% every half hour
+        for i in 0..72 {
+            history.record(&sample(START + i * 300, 90 - (i / 6) as u8, ChargeState::Discharging));
+        }
+        let before = history.hours_remaining(START + 6 * HOUR - 1);
+        assert_eq!(before, Some(39));
+        // A minute into the next slot, before anything is logged in it
+        assert_eq!(history.hours_remaining(START + 6 * HOUR + 60), before);
+    }
+
+    #[test]
+    fn estimate_needs_history() {
+        let mut history = History::new();
+        history.record(&sample(START, 80, ChargeState::Discharging));
+        assert_eq!(history.hours_remaining(START), None);
+        // No drop yet
+        history.record(&sample(START + HOUR, 80, ChargeState::Discharging));
+        assert_eq!(history.hours_remaining(START + HOUR), None);
+    }
+}
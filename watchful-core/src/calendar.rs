@@ -0,0 +1,187 @@
+//! Setting the date and time by hand.
+//!
+//! Each field is stepped on its own and wraps around within its range instead of carrying
+//! into the next, the way digital watches are set. The day is kept within the month, so
+//! moving from January 31 to February lands on the 28th or 29th.
+
+/// Years that can be set, phones take over from there.
+pub const MIN_YEAR: i32 = 2000;
+pub const MAX_YEAR: i32 = 2099;
+
+pub fn is_leap_year(year: i32) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+/// Days in `month`, from 1 for January, of `year`.
+pub fn days_in_month(year: i32, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Field {
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+}
+
+/// A date and time to the minute, always valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DateTime {
+    year: i32,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+}
+
+impl Default for DateTime {
+    /// The first date and time that can be set, where setting starts while the time is not
+    /// known.
+    fn default() -> Self {
+        Self {
+            year: MIN_YEAR,
+            month: 1,
+            day: 1,
+            hour: 0,
+            minute: 0,
+        }
+    }
+}
+
+impl DateTime {
+    /// `None` unless the fields make up a date and time in the years that can be set.
+    pub fn new(year: i32, month: u8, day: u8, hour: u8, minute: u8) -> Option<Self> {
+        let valid = (MIN_YEAR..=MAX_YEAR).contains(&year)
+            && (1..=12).contains(&month)
+            && (1..=days_in_month(year, month)).contains(&day)
+            && hour < 24
+            && minute < 60;
+        valid.then_some(Self {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+        })
+    }
+
+    pub fn year(&self) -> i32 {
+        self.year
+    }
+
+    pub fn month(&self) -> u8 {
+        self.month
+    }
+
+    pub fn day(&self) -> u8 {
+        self.day
+    }
+
+    pub fn hour(&self) -> u8 {
+        self.hour
+    }
+
+    pub fn minute(&self) -> u8 {
+        self.minute
+    }
+
+    /// Move `field` by `delta`. The year stops at the ends of the years that can be set,
+    /// every other field wraps around.
+    pub fn step(self, field: Field, delta: i32) -> Self {
+        let wrap = |value: u8, first: u8, count: u8| {
+            (first as i32 + (value as i32 - first as i32 + delta).rem_euclid(count as i32)) as u8
+        };
+        let mut next = self;
+        match field {
+            Field::Year => next.year = (self.year + delta).clamp(MIN_YEAR, MAX_YEAR),
+            Field::Month => next.month = wrap(self.month, 1, 12),
+            Field::Day => next.day = wrap(self.day, 1, days_in_month(self.year, self.month)),
+            Field::Hour => next.hour = wrap(self.hour, 0, 24),
+            Field::Minute => next.minute = wrap(self.minute, 0, 60),
+        }
+        next.day = next.day.min(days_in_month(next.year, next.month));
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leap_years() {
+        assert!(is_leap_year(2024));
+        assert!(is_leap_year(2000));
+        assert!(!is_leap_year(2100));
+        assert!(!is_leap_year(2026));
+        assert_eq!(days_in_month(2024, 2), 29);
+        assert_eq!(days_in_month(2025, 2), 28);
+        assert_eq!(days_in_month(2025, 4), 30);
+        assert_eq!(days_in_month(2025, 12), 31);
+    }
+
+    #[test]
+    fn validates() {
+        assert!(DateTime::new(2024, 2, 29, 23, 59).is_some());
+        assert_eq!(DateTime::new(2025, 2, 29, 12, 0), None);
+        assert_eq!(DateTime::new(2025, 4, 31, 12, 0), None);
+        assert_eq!(DateTime::new(2025, 13, 1, 12, 0), None);
+        assert_eq!(DateTime::new(2025, 1, 0, 12, 0), None);
+        assert_eq!(DateTime::new(2025, 1, 1, 24, 0), None);
+        assert_eq!(DateTime::new(2025, 1, 1, 0, 60), None);
+        assert_eq!(DateTime::new(1999, 12, 31, 0, 0), None);
+    }
+
+    #[test]
+    fn fields_wrap_without_carrying() {
+        let time = DateTime::new(2025, 12, 31, 23, 59).unwrap();
+        assert_eq!(time.step(Field::Minute, 1), DateTime::new(2025, 12, 31, 23, 0).unwrap());
+        assert_eq!(time.step(Field::Hour, 1), DateTime::new(2025, 12, 31, 0, 59).unwrap());
+        assert_eq!(time.step(Field::Day, 1), DateTime::new(2025, 12, 1, 23, 59).unwrap());
+        assert_eq!(time.step(Field::Month, 1), DateTime::new(2025, 1, 31, 23, 59).unwrap());
+        let time = DateTime::new(2025, 1, 1, 0, 0).unwrap();
+        assert_eq!(time.step(Field::Minute, -1).minute(), 59);
+        assert_eq!(time.step(Field::Hour, -1).hour(), 23);
+        assert_eq!(time.step(Field::Day, -1).day(), 31);
+        assert_eq!(time.step(Field::Month, -1).month(), 12);
+    }
+
+    #[test]
+    fn keeps_day_in_month() {
+        let time = DateTime::new(2024, 1, 31, 8, 0).unwrap();
+        assert_eq!(time.step(Field::Month, 1).day(), 29);
+        assert_eq!(time.step(Field::Month, 3).day(), 30);
+        let leap_day = DateTime::new(2024, 2, 29, 8, 0).unwrap();
+        assert_eq!(leap_day.step(Field::Year, 1), DateTime::new(2025, 2, 28, 8, 0).unwrap());
+        assert_eq!(leap_day.step(Field::Year, 4).day(), 29);
+        // The day wraps within the month it is in
+        assert_eq!(DateTime::new(2025, 2, 28, 8, 0).unwrap().step(Field::Day, 1).day(), 1);
+    }
+
+    #[test]
+    fn starts_at_first_year() {
+        let first = DateTime::default();
+        assert_eq!(Some(first), DateTime::new(MIN_YEAR, 1, 1, 0, 0));
+        assert_eq!(first.step(Field::Year, -1), first);
+        assert_eq!(first.step(Field::Minute, -1).minute(), 59);
+        // Times that can not be set start over from the first
+        assert_eq!(DateTime::new(1999, 12, 31, 23, 59).unwrap_or_default(), first);
+        assert_eq!(DateTime::new(2100, 1, 1, 0, 0).unwrap_or_default(), first);
+    }
+
+    #[test]
+    fn year_stops_at_range() {
+        let time = DateTime::new(2095, 6, 1, 8, 0).unwrap();
+        assert_eq!(time.step(Field::Year, 10).year(), MAX_YEAR);
+        assert_eq!(time.step(Field::Year, -100).year(), MIN_YEAR);
+    }
+}
@@ -0,0 +1,116 @@
+//! The time counted from the RTC.
+//!
+//! The watch only knows the time once a phone or the user has set it. From then on it is
+//! the time it was set to plus the RTC time since, corrected for the [`Drift`]. RTC times
+//! are microseconds since boot, UTC times microseconds since 1970.
+
+use crate::drift::Drift;
+use crate::timezone::TimeZone;
+
+const US_PER_SECOND: i64 = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TimeBase {
+    /// RTC time when the clock was last set.
+    base_us: i64,
+    /// UTC at `base_us`, `None` until the clock is set.
+    time_us: Option<i64>,
+    pub drift: Drift,
+}
+
+impl Default for TimeBase {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimeBase {
+    /// A clock that has not been set.
+    pub const fn new() -> Self {
+        Self {
+            base_us: 0,
+            time_us: None,
+            drift: Drift::new(0),
+        }
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.time_us.is_some()
+    }
+
+    /// Set the clock to `utc_us` at RTC time `now_us`.
+    pub fn set(&mut self, now_us: i64, utc_us: i64) {
+        self.base_us = now_us;
+        self.time_us = Some(utc_us);
+    }
+
+    /// UTC at RTC time `now_us`, `None` while the clock is not set.
+    pub fn utc_us(&self, now_us: i64) -> Option<i64> {
+        let elapsed = now_us.saturating_sub(self.base_us).max(0);
+        self.time_us?.checked_add(self.drift.correct(elapsed))
+    }
+
+    /// The local time of `zone` at RTC time `now_us`, in microseconds since 1970 as if it
+    /// were UTC. `None` while the clock is not set.
+    pub fn local_us(&self, now_us: i64, zone: TimeZone) -> Option<i64> {
+        let utc = self.utc_us(now_us)?;
+        let offset = zone.offset_at(utc.div_euclid(US_PER_SECOND)) as i64;
+        utc.checked_add(offset * US_PER_SECOND)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timezone::DstRule;
+
+    const SECOND: i64 = US_PER_SECOND;
+
+    #[test]
+    fn unset() {
+        let base = TimeBase::new();
+        assert!(!base.is_set());
+        assert_eq!(base.utc_us(5 * SECOND), None);
+        // Behind UTC, which must not reach before the earliest time
+        let zone = TimeZone::new(-5 * 60, DstRule::Us);
+        assert_eq!(base.local_us(5 * SECOND, zone), None);
+        assert_eq!(base.local_us(i64::MAX, zone), None);
+    }
+
+    #[test]
+    fn counts_from_set() {
+        let mut base = TimeBase::new();
+        base.set(10 * SECOND, 1_700_000_000 * SECOND);
+        assert!(base.is_set());
+        assert_eq!(base.utc_us(70 * SECOND), Some(1_700_000_060 * SECOND));
+        // RTC times before the clock was set do not go back
+        assert_eq!(base.utc_us(0), Some(1_700_000_000 * SECOND));
+        let zone = TimeZone::new(-5 * 60, DstRule::None);
+        assert_eq!(
+            base.local_us(70 * SECOND, zone),
+            Some((1_700_000_060 - 5 * 3600) * SECOND)
+        );
+    }
+
+    #[test]
+    fn corrects_drift() {
+        let mut base = TimeBase::new();
+        base.drift = Drift::new(100);
+        base.set(0, 0);
+        assert_eq!(base.utc_us(10 * SECOND), Some(10 * SECOND + 1000));
+    }
+
+    #[test]
+    fn out_of_range() {
+        let mut base = TimeBase::new();
+        base.set(0, i64::MAX - SECOND);
+        assert_eq!(base.utc_us(10 * SECOND), None);
+        // Times before 1970 are fine
+        base.set(0, -SECOND);
+        assert_eq!(
+            base.local_us(0, TimeZone::new(-60, DstRule::None)),
+            Some((-1 - 3600) * SECOND)
+        );
+    }
+}
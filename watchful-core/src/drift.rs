@@ -0,0 +1,128 @@
+//! Estimate of how far the RTC runs off from the phone's clock.
+//!
+//! The PineTime runs its low frequency clock from the internal RC oscillator, which is off
+//! by up to a few hundred parts per million. Every time the phone sets the clock, the time
+//! the watch had drifted since the previous sync tells how fast or slow the RTC runs, and
+//! elapsed RTC time is corrected by that rate from then on.
+
+/// Syncs closer together than this say more about sync latency than about drift.
+const MIN_INTERVAL_US: i64 = 60 * 60 * 1_000_000;
+/// Anything off by more than this is a changed clock on the phone rather than drift.
+const MAX_PPM: i64 = 1000;
+/// Weight of a new measurement in the estimate, as a shift: 1/2.
+const FILTER_SHIFT: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Drift {
+    /// How much faster the real clock runs than the RTC, in parts per million.
+    ppm: i32,
+    /// RTC time of the last sync in microseconds, `None` if the clock was set by hand
+    /// since.
+    last_sync: Option<i64>,
+}
+
+impl Default for Drift {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl Drift {
+    pub const fn new(ppm: i32) -> Self {
+        Self { ppm, last_sync: None }
+    }
+
+    pub fn ppm(&self) -> i32 {
+        self.ppm
+    }
+
+    /// Real time passed in `rtc_us` microseconds of RTC time.
+    pub fn correct(&self, rtc_us: i64) -> i64 {
+        rtc_us + rtc_us * self.ppm as i64 / 1_000_000
+    }
+
+    /// The phone set the clock at RTC time `now_us`, finding it `error_us` microseconds
+    /// behind. Returns whether the estimate changed.
+    pub fn sync(&mut self, now_us: i64, error_us: i64) -> bool {
+        let last = self.last_sync.replace(now_us);
+        let Some(interval) = last.map(|last| now_us - last) else {
+            return false;
+        };
+        if interval < MIN_INTERVAL_US {
+            // Keep measuring from the earlier sync
+            self.last_sync = last;
+            return false;
+        }
+        let measured = error_us * 1_000_000 / interval;
+        if measured.abs() > MAX_PPM {
+            return false;
+        }
+        let ppm = self.ppm as i64 + (measured >> FILTER_SHIFT);
+        self.ppm = ppm.clamp(-MAX_PPM, MAX_PPM) as i32;
+        true
+    }
+
+    /// The clock was set by hand, the next sync can not tell anything about drift.
+    pub fn reset(&mut self) {
+        self.last_sync = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR_US: i64 = 60 * 60 * 1_000_000;
+
+    #[test]
+    fn corrects_elapsed_time() {
+        assert_eq!(Drift::new(0).correct(1_000_000), 1_000_000);
+        assert_eq!(Drift::new(100).correct(10_000_000), 10_001_000);
+        assert_eq!(Drift::new(-50).correct(1_000_000_000), 999_950_000);
+    }
+
+    #[test]
+    fn converges_on_drift() {
+        // The RTC loses 200 ppm
+        let mut drift = Drift::default();
+        let mut now = 0;
+        assert!(!drift.sync(now, 0));
+        for _ in 0..10 {
+            let interval = 6 * HOUR_US;
+            now += interval;
+            let behind = interval * 200 / 1_000_000 - (drift.correct(interval) - interval);
+            assert!(drift.sync(now, behind));
+        }
+        assert!((drift.ppm() - 200).abs() <= 1, "{}", drift.ppm());
+    }
+
+    #[test]
+    fn ignores_close_syncs() {
+        let mut drift = Drift::default();
+        drift.sync(0, 0);
+        // Latency on a reconnect a minute later would look like huge drift
+        assert!(!drift.sync(60_000_000, 500_000));
+        // Measured from the first sync instead
+        assert!(drift.sync(2 * HOUR_US, 720_000));
+        assert_eq!(drift.ppm(), 50);
+    }
+
+    #[test]
+    fn ignores_changed_clock() {
+        let mut drift = Drift::default();
+        drift.sync(0, 0);
+        // Off by an hour after two hours
+        assert!(!drift.sync(2 * HOUR_US, HOUR_US));
+        assert_eq!(drift.ppm(), 0);
+    }
+
+    #[test]
+    fn set_by_hand() {
+        let mut drift = Drift::new(30);
+        drift.sync(0, 0);
+        drift.reset();
+        assert!(!drift.sync(10 * HOUR_US, 5_000_000));
+        assert_eq!(drift.ppm(), 30);
+    }
+}
@@ -0,0 +1,179 @@
+//! Background heart rate readings over the last day, and alerts for resting rates out of
+//! range.
+
+/// Minutes covered by each slot of the history.
+pub const SLOT_MINUTES: i64 = 10;
+pub const SLOTS: usize = (24 * 60 / SLOT_MINUTES) as usize;
+
+/// Heart rates of the last 24 hours, the latest reading of every slot.
+#[derive(Clone, PartialEq)]
+pub struct History {
+    slots: [u8; SLOTS],
+    /// Number of the newest slot recorded, counted from 1970.
+    newest: Option<i64>,
+}
+
+impl History {
+    pub const fn new() -> Self {
+        Self {
+            slots: [0; SLOTS],
+            newest: None,
+        }
+    }
+
+    /// Record a reading taken at `time`, seconds since 1970.
+    pub fn record(&mut self, time: i64, bpm: u8) {
+        let slot = slot(time);
+        match self.newest {
+            Some(newest) if slot > newest => {
+                // Forget the slots skipped since
+                for s in (newest + 1).max(slot - SLOTS as i64 + 1)..slot {
+                    self.slots[index(s)] = 0;
+                }
+                self.newest = Some(slot);
+            }
+            // The clock was set back, older than anything kept
+            Some(newest) if slot <= newest - SLOTS as i64 => return,
+            Some(_) => {}
+            None => {
+                self.slots = [0; SLOTS];
+                self.newest = Some(slot);
+            }
+        }
+        self.slots[index(slot)] = bpm;
+    }
+
+    /// The readings of the 24 hours up to `now`, oldest first.
+    pub fn slots(&self, now: i64) -> impl Iterator<Item = Option<u8>> + '_ {
+        let now = slot(now);
+        (now - SLOTS as i64 + 1..=now).map(move |s| match self.newest {
+            Some(newest) if s <= newest && s > newest - SLOTS as i64 => Some(self.slots[index(s)]).filter(|b| *b > 0),
+            _ => None,
+        })
+    }
+
+    /// The newest reading, if taken within the last 24 hours of `now`.
+    pub fn latest(&self, now: i64) -> Option<u8> {
+        self.slots(now).flatten().last()
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn slot(time: i64) -> i64 {
+    time.div_euclid(SLOT_MINUTES * 60)
+}
+
+fn index(slot: i64) -> usize {
+    slot.rem_euclid(SLOTS as i64) as usize
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Alert {
+    High(u8),
+    Low(u8),
+}
+
+/// Raises an alert when resting readings leave the configured range, once until they are
+/// back in range.
+pub struct Alerts {
+    /// Whether the last reading was too high (`Some(true)`) or too low.
+    out_of_range: Option<bool>,
+}
+
+impl Alerts {
+    pub const fn new() -> Self {
+        Self { out_of_range: None }
+    }
+
+    pub fn check(&mut self, bpm: u8, low: u8, high: u8) -> Option<Alert> {
+        let alert = if bpm > high {
+            Some(Alert::High(bpm))
+        } else if bpm < low {
+            Some(Alert::Low(bpm))
+        } else {
+            None
+        };
+        let high = alert.map(|a| matches!(a, Alert::High(_)));
+        let raised = alert.filter(|_| high != self.out_of_range);
+        self.out_of_range = high;
+        raised
+    }
+}
+
+impl Default for Alerts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: i64 = 3600;
+    const START: i64 = 1_760_000_000 / HOUR * HOUR;
+
+    #[test]
+    fn history() {
+        let mut history = History::new();
+        assert_eq!(history.latest(START), None);
+        assert!(history.slots(START).all(|s| s.is_none()));
+
+        // Every 30 minutes for 6 hours
+        for i in 0..12 {
+            history.record(START + i * 30 * 60, 60 + i as u8);
+        }
+        let now = START + 6 * HOUR;
+        let slots: Vec<_> = history.slots(now).collect();
+        assert_eq!(slots.len(), SLOTS);
+        assert_eq!(slots.iter().flatten().count(), 12);
+        assert_eq!(slots[SLOTS - 1], None);
+        assert_eq!(slots[SLOTS - 4], Some(71));
+        assert_eq!(slots[SLOTS - 37], Some(60));
+        assert_eq!(history.latest(now), Some(71));
+
+        // A day later the old readings are gone
+        let later = START + 30 * HOUR;
+        history.record(later, 80);
+        let slots: Vec<_> = history.slots(later).collect();
+        assert_eq!(slots.iter().flatten().copied().collect::<Vec<_>>(), [80]);
+        assert_eq!(history.latest(later + 25 * HOUR), None);
+    }
+
+    #[test]
+    fn clock_set_back() {
+        let mut history = History::new();
+        history.record(START + 2 * HOUR, 70);
+        history.record(START + HOUR, 65);
+        history.record(START - 2 * 24 * HOUR, 50);
+        let slots: Vec<_> = history.slots(START + 2 * HOUR).flatten().collect();
+        assert_eq!(slots, [65, 70]);
+    }
+
+    #[test]
+    fn alerts() {
+        let mut alerts = Alerts::new();
+        let readings = [70, 105, 110, 90, 38, 36, 60, 101, 39];
+        let raised: Vec<_> = readings.iter().map(|&bpm| alerts.check(bpm, 40, 100)).collect();
+        assert_eq!(
+            raised,
+            [
+                None,
+                Some(Alert::High(105)),
+                None,
+                None,
+                Some(Alert::Low(38)),
+                None,
+                None,
+                Some(Alert::High(101)),
+                Some(Alert::Low(39)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,27 @@
+//! Hardware independent logic for Watchful.
+//!
+//! Everything in here is `no_std` and free of any peripheral access so that it can be
+//! tested on the host with `cargo test`.
+#![cfg_attr(not(test), no_std)]
+
+pub mod battery;
+pub mod battery_calibration;
+pub mod battery_history;
+pub mod calendar;
+pub mod clock;
+pub mod drift;
+pub mod heart_history;
+pub mod motion;
+pub mod navigation;
+pub mod ppg;
+pub mod records;
+pub mod settings;
+pub mod shell;
+pub mod sleep;
+pub mod steps;
+pub mod timezone;
+pub mod version;
+pub mod vibration;
+pub mod weather;
+pub mod workout;
+pub mod wrist;
@@ -0,0 +1,71 @@
+//! Accelerometer samples shared by the motion based features.
+
+/// Rate the accelerometer is sampled at.
+pub const SAMPLE_RATE: u32 = 25;
+
+/// One acceleration reading in milli-g.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Sample {
+    pub x: i16,
+    pub y: i16,
+    pub z: i16,
+}
+
+impl Sample {
+    pub const fn new(x: i16, y: i16, z: i16) -> Self {
+        Self { x, y, z }
+    }
+
+    /// Length of the acceleration vector in milli-g.
+    pub fn magnitude(&self) -> u32 {
+        let (x, y, z) = (self.x as i32, self.y as i32, self.z as i32);
+        isqrt((x * x + y * y + z * z) as u32)
+    }
+}
+
+fn isqrt(n: u32) -> u32 {
+    if n < 2 {
+        return n;
+    }
+    // Newton's method, starting from a power of two above the root
+    let mut x = 1 << (32 - n.leading_zeros()).div_ceil(2);
+    loop {
+        let y = (x + n / x) / 2;
+        if y >= x {
+            return x;
+        }
+        x = y;
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Parse a recorded trace with one `x,y,z` sample per line. Lines starting with `#` are
+    /// comments.
+    pub fn trace(csv: &str) -> Vec<Sample> {
+        csv.lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(|l| {
+                let mut values = l.split(',').map(|v| v.trim().parse::<i16>().unwrap());
+                let mut next = || values.next().unwrap();
+                Sample::new(next(), next(), next())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn magnitude() {
+        assert_eq!(Sample::new(0, 0, 0).magnitude(), 0);
+        assert_eq!(Sample::new(0, 0, -1000).magnitude(), 1000);
+        assert_eq!(Sample::new(300, 400, 0).magnitude(), 500);
+        assert_eq!(Sample::new(-4096, -4096, -4096).magnitude(), 7094);
+        for n in [2, 3, 4, 15, 16, 17, 99, 100, 101, u32::MAX] {
+            let r = isqrt(n) as u64;
+            assert!(r * r <= n as u64 && (r + 1) * (r + 1) > n as u64, "isqrt({})", n);
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Types for the InfiniTime navigation protocol.
+//!
+//! The phone writes an icon name such as `turn-slight-left` or `roundabout-right`, the
+//! instruction text, the distance to the manoeuvre and the progress towards it (0-100).
+
+/// Which way to go at the next manoeuvre, reduced from the icon names used by the
+/// InfiniTime navigation service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Direction {
+    Straight,
+    SlightLeft,
+    Left,
+    SharpLeft,
+    SlightRight,
+    Right,
+    SharpRight,
+    UTurn,
+    Roundabout,
+    Arrive,
+    Unknown,
+}
+
+impl Direction {
+    pub fn from_icon_name(name: &str) -> Self {
+        let name = name.trim();
+        if name.contains("uturn") {
+            Self::UTurn
+        } else if name.starts_with("arrive") || name == "flag" {
+            Self::Arrive
+        } else if name.starts_with("roundabout") || name.starts_with("rotary") {
+            Self::Roundabout
+        } else if name.ends_with("sharp-left") {
+            Self::SharpLeft
+        } else if name.ends_with("sharp-right") {
+            Self::SharpRight
+        } else if name.ends_with("slight-left") {
+            Self::SlightLeft
+        } else if name.ends_with("slight-right") {
+            Self::SlightRight
+        } else if name.ends_with("left") {
+            Self::Left
+        } else if name.ends_with("right") {
+            Self::Right
+        } else if name.ends_with("straight")
+            || matches!(name, "continue" | "depart" | "merge" | "fork" | "updown" | "ferry")
+        {
+            Self::Straight
+        } else {
+            Self::Unknown
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn icon_names() {
+        assert_eq!(Direction::from_icon_name("turn-left"), Direction::Left);
+        assert_eq!(Direction::from_icon_name("end-of-road-right"), Direction::Right);
+        assert_eq!(Direction::from_icon_name("turn-sharp-left"), Direction::SharpLeft);
+        assert_eq!(Direction::from_icon_name("fork-slight-right"), Direction::SlightRight);
+        assert_eq!(Direction::from_icon_name("continue"), Direction::Straight);
+        assert_eq!(Direction::from_icon_name("merge-straight"), Direction::Straight);
+        assert_eq!(Direction::from_icon_name("continue-uturn"), Direction::UTurn);
+        assert_eq!(Direction::from_icon_name("roundabout-left"), Direction::Roundabout);
+        assert_eq!(Direction::from_icon_name("arrive-right"), Direction::Arrive);
+        assert_eq!(Direction::from_icon_name("flag"), Direction::Arrive);
+        assert_eq!(Direction::from_icon_name("close"), Direction::Unknown);
+    }
+}
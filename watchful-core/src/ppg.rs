@@ -0,0 +1,318 @@
+//! Heart rate from the photoplethysmogram (PPG) of the HRS3300.
+//!
+//! The sensor reports how much of the LED light is reflected back; every heart beat pushes
+//! more blood into the skin, which absorbs more light. The raw values are band-pass
+//! filtered to the range of heart rates, which removes the slowly changing level, breathing
+//! and noise, and each beat is timed where the inverted pulse rises through zero. The rate
+//! is taken from the median of the last intervals between beats, and how much the
+//! intervals agree gives the confidence.
+
+/// Rate the sensor must be sampled at.
+pub const SAMPLE_RATE: u32 = 25;
+
+/// Raw level below which the sensor is not against skin: off the wrist hardly any light
+/// comes back to the photodiode.
+pub const MIN_SKIN_LEVEL: u32 = 1000;
+/// Raw level at which the ADC saturates.
+const SATURATED: u32 = 0x3FFF0;
+/// The pulse changes the level by a few percent at most, swings larger than this fraction
+/// of the level are movement.
+const MOTION_FRACTION: i32 = 25;
+
+/// Samples to ignore while the filters settle, after turning on and after movement.
+const SETTLE: u32 = 2 * SAMPLE_RATE;
+const MOTION_HOLD_OFF: u32 = SAMPLE_RATE;
+
+/// Fraction bits of the beat times.
+const FRACTION: u32 = 4;
+const MIN_BPM: u32 = 40;
+const MAX_BPM: u32 = 200;
+/// One minute in the units of the beat times.
+const MINUTE: u32 = (SAMPLE_RATE * 60) << FRACTION;
+const MIN_INTERVAL: u32 = MINUTE / MAX_BPM;
+const MAX_INTERVAL: u32 = MINUTE / MIN_BPM;
+const INTERVALS: usize = 8;
+/// Intervals needed before reporting a rate.
+const MIN_INTERVALS: usize = 4;
+/// Intervals this much longer than the median are missed beats, unless they keep coming.
+const MAX_REJECTED: u8 = 4;
+
+/// Fraction bits of the filter coefficients.
+const COEFFICIENT_BITS: u32 = 14;
+/// Butterworth high-pass at 0.6 Hz, from the Audio EQ Cookbook.
+const HIGH_PASS: Biquad = Biquad::new([14727, -29453, 14727], [-29285, 13237]);
+/// Butterworth low-pass at 3.5 Hz.
+const LOW_PASS: Biquad = Biquad::new([1923, 3845, 1923], [-13521, 4827]);
+
+/// Estimates with less confidence are only good for a rough idea.
+pub const MIN_CONFIDENCE: u8 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Estimate {
+    pub bpm: u8,
+    /// 0 to 100
+    pub confidence: u8,
+}
+
+/// Second order IIR filter in fixed point.
+#[derive(Clone, Copy)]
+struct Biquad {
+    b: [i64; 3],
+    a: [i64; 2],
+    x: [i64; 2],
+    y: [i64; 2],
+}
+
+impl Biquad {
+    const fn new(b: [i64; 3], a: [i64; 2]) -> Self {
+        Self {
+            b,
+            a,
+            x: [0; 2],
+            y: [0; 2],
+        }
+    }
+
+    /// Start from a steady input instead of zero.
+    fn prime(&mut self, x: i32, y: i32) {
+        self.x = [x as i64; 2];
+        self.y = [y as i64; 2];
+    }
+
+    fn update(&mut self, x: i32) -> i32 {
+        let x = x as i64;
+        let y = (self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1])
+            >> COEFFICIENT_BITS;
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y as i32
+    }
+}
+
+pub struct HeartRateEstimator {
+    high_pass: Biquad,
+    low_pass: Biquad,
+    /// Average raw level, scaled by 16.
+    level: Option<u32>,
+    last: i32,
+    sample: u32,
+    hold_off: u32,
+    /// Time of the last beat, in samples scaled by `1 << FRACTION`.
+    last_beat: Option<u32>,
+    intervals: [u32; INTERVALS],
+    count: usize,
+    next: usize,
+    rejected: u8,
+}
+
+impl HeartRateEstimator {
+    pub const fn new() -> Self {
+        Self {
+            high_pass: HIGH_PASS,
+            low_pass: LOW_PASS,
+            level: None,
+            last: 0,
+            sample: 0,
+            hold_off: SETTLE,
+            last_beat: None,
+            intervals: [0; INTERVALS],
+            count: 0,
+            next: 0,
+            rejected: 0,
+        }
+    }
+
+    /// Feed one raw sample taken at [`SAMPLE_RATE`]. Returns a new estimate on every beat
+    /// once there are enough of them.
+    pub fn update(&mut self, raw: u32) -> Option<Estimate> {
+        if !(MIN_SKIN_LEVEL..SATURATED).contains(&raw) {
+            if self.level.is_some() {
+                self.reset();
+            }
+            return None;
+        }
+        self.sample = self.sample.wrapping_add(1);
+
+        let level = match self.level.as_mut() {
+            Some(level) => {
+                *level = *level - (*level >> 4) + raw;
+                *level >> 4
+            }
+            None => {
+                self.level = Some(raw << 4);
+                self.high_pass.prime(raw as i32, 0);
+                raw
+            }
+        };
+        // More blood absorbs more light, so beats are dips in the reflection
+        let pulse = -self.low_pass.update(self.high_pass.update(raw as i32));
+        let last = core::mem::replace(&mut self.last, pulse);
+
+        if self.hold_off > 0 {
+            self.hold_off -= 1;
+            return None;
+        }
+        if pulse.abs() * MOTION_FRACTION > level as i32 {
+            self.hold_off = MOTION_HOLD_OFF;
+            self.last_beat = None;
+            self.count = 0;
+            self.next = 0;
+            return None;
+        }
+
+        if last <= 0 && pulse > 0 {
+            // Interpolate where the pulse crossed zero
+            let fraction = ((pulse as i64) << FRACTION) / (pulse - last) as i64;
+            let at = (self.sample << FRACTION).wrapping_sub(fraction as u32);
+            self.beat(at)
+        } else {
+            None
+        }
+    }
+
+    /// Forget everything, e.g. when the sensor was turned off.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    fn beat(&mut self, at: u32) -> Option<Estimate> {
+        let Some(last) = self.last_beat else {
+            self.last_beat = Some(at);
+            return None;
+        };
+        let interval = at.wrapping_sub(last);
+        if interval < MIN_INTERVAL {
+            // Noise shortly after the beat
+            return None;
+        }
+        self.last_beat = Some(at);
+        if interval > MAX_INTERVAL {
+            // The intervals so far tell nothing about the next one
+            self.count = 0;
+            self.next = 0;
+            return None;
+        }
+        if self.count >= MIN_INTERVALS && interval * 2 > self.median() * 3 && self.rejected < MAX_REJECTED {
+            self.rejected += 1;
+            return None;
+        }
+        self.rejected = 0;
+
+        self.intervals[self.next] = interval;
+        self.next = (self.next + 1) % INTERVALS;
+        self.count = (self.count + 1).min(INTERVALS);
+        if self.count < MIN_INTERVALS {
+            return None;
+        }
+
+        let median = self.median();
+        let intervals = &self.intervals[..self.count];
+        let mut deviations = [0; INTERVALS];
+        let deviations = &mut deviations[..self.count];
+        for (d, i) in deviations.iter_mut().zip(intervals) {
+            *d = i.abs_diff(median);
+        }
+        deviations.sort_unstable();
+        let spread = deviations[self.count / 2] * 100 / median;
+
+        // Average the intervals close to the median for a finer rate than the median alone
+        let (sum, n) = intervals
+            .iter()
+            .filter(|i| i.abs_diff(median) * 8 <= median)
+            .fold((0, 0), |(sum, n), i| (sum + i, n + 1));
+        let bpm = MINUTE * n / sum;
+
+        // Agreeing intervals give full confidence, 25% spread gives none
+        let confidence = 100u32.saturating_sub(spread * 4) * self.count as u32 / INTERVALS as u32;
+        Some(Estimate {
+            bpm: bpm.clamp(MIN_BPM, MAX_BPM) as u8,
+            confidence: confidence as u8,
+        })
+    }
+
+    fn median(&self) -> u32 {
+        let mut sorted = [0; INTERVALS];
+        let sorted = &mut sorted[..self.count];
+        sorted.copy_from_slice(&self.intervals[..self.count]);
+        sorted.sort_unstable();
+        sorted[self.count / 2]
+    }
+}
+
+impl Default for HeartRateEstimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(csv: &str) -> Vec<u32> {
+        csv.lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(|l| l.parse().unwrap())
+            .collect()
+    }
+
+    /// The estimates over the whole file.
+    fn estimate(csv: &str) -> Vec<Estimate> {
+        let mut estimator = HeartRateEstimator::new();
+        samples(csv).into_iter().filter_map(|s| estimator.update(s)).collect()
+    }
+
+    fn assert_rate(estimates: &[Estimate], bpm: u8, tolerance: u8, confidence: u8) {
+        let last = estimates.last().expect("no estimate");
+        assert!(
+            last.bpm.abs_diff(bpm) <= tolerance,
+            "estimated {:?}, expected {}",
+            last,
+            bpm
+        );
+        assert!(last.confidence >= confidence, "low confidence {:?}", last);
+    }
+
+    #[test]
+    fn at_rest() {
+        let estimates = estimate(include_str!("../fixtures/ppg/rest_72.csv"));
+        assert_rate(&estimates, 72, 2, 80);
+        // Settles within the first ten seconds
+        assert!(estimates.len() >= 20);
+    }
+
+    #[test]
+    fn exercise() {
+        assert_rate(&estimate(include_str!("../fixtures/ppg/exercise_130.csv")), 130, 5, 60);
+    }
+
+    #[test]
+    fn follows_changes() {
+        assert_rate(&estimate(include_str!("../fixtures/ppg/rising.csv")), 100, 3, 80);
+    }
+
+    #[test]
+    fn off_wrist() {
+        assert_eq!(estimate(include_str!("../fixtures/ppg/off_wrist.csv")), vec![]);
+    }
+
+    #[test]
+    fn motion_is_not_trusted() {
+        let estimates = estimate(include_str!("../fixtures/ppg/motion.csv"));
+        assert!(
+            estimates.iter().all(|e| e.confidence < MIN_CONFIDENCE),
+            "confident during motion: {:?}",
+            estimates
+        );
+    }
+
+    #[test]
+    fn saturated() {
+        let mut estimator = HeartRateEstimator::new();
+        assert!((0..100).all(|_| estimator.update(0x3FFFF).is_none()));
+    }
+}
@@ -0,0 +1,451 @@
+//! Append-only log of small records in a region of NOR flash.
+//!
+//! The region is divided into fixed size slots that never cross an erase sector. Every
+//! slot starts with an 8 byte header:
+//!
+//! | offset | size | field                                         |
+//! |--------|------|-----------------------------------------------|
+//! | 0      | 4    | sequence number, `0xFFFFFFFF` when unused     |
+//! | 4      | 2    | payload length                                |
+//! | 6      | 2    | CRC-16/CCITT of the payload                   |
+//!
+//! The sequence number is written last, so a record interrupted by a reset is never
+//! picked up. When the log reaches a new sector, that sector is erased first, which drops
+//! the oldest records. The region should therefore span at least two sectors.
+
+use embedded_storage_async::nor_flash::NorFlash;
+
+const HEADER_SIZE: u32 = 8;
+const UNUSED: u32 = u32::MAX;
+
+/// Erase sector size of the external flash, the only flash the log is used with.
+const SECTOR_SIZE: u32 = 4096;
+
+pub struct RecordLog {
+    start: u32,
+    end: u32,
+    slot_size: u32,
+    next: u32,
+    seq: u32,
+}
+
+impl RecordLog {
+    /// A log storing records of up to `payload` bytes between `start` and `end`. Both
+    /// must be aligned to the erase size of the flash.
+    pub const fn new(start: u32, end: u32, payload: usize) -> Self {
+        let slot_size = (HEADER_SIZE + payload as u32 + 3) & !3;
+        Self {
+            start,
+            end,
+            slot_size,
+            next: start,
+            seq: 0,
+        }
+    }
+
+    /// Largest payload a record can hold.
+    pub fn payload_size(&self) -> usize {
+        (self.slot_size - HEADER_SIZE) as usize
+    }
+
+    /// Scan the region to find where the log continues.
+    pub async fn mount<F: NorFlash>(&mut self, flash: &mut F) -> Result<(), F::Error> {
+        let mut newest: Option<(u32, u32)> = None;
+        let mut slot = Some(self.start);
+        while let Some(pos) = slot {
+            let header = read_header(flash, pos).await?;
+            if header.seq != UNUSED && newest.map(|(seq, _)| header.seq > seq).unwrap_or(true) {
+                newest = Some((header.seq, pos));
+            }
+            slot = self.following(pos);
+        }
+
+        match newest {
+            Some((seq, pos)) => {
+                self.seq = seq.wrapping_add(1);
+                self.next = self.following(pos).unwrap_or(self.start);
+                // Skip over slots that were partially written before a reset.
+                for _ in 0..self.slot_count() {
+                    if self.is_sector_start(self.next) || read_header(flash, self.next).await?.is_erased() {
+                        break;
+                    }
+                    self.next = self.following(self.next).unwrap_or(self.start);
+                }
+            }
+            None => {
+                self.seq = 0;
+                self.next = self.start;
+            }
+        }
+        Ok(())
+    }
+
+    /// Append a record, erasing the oldest sector when needed.
+    pub async fn append<F: NorFlash>(&mut self, flash: &mut F, payload: &[u8]) -> Result<(), F::Error> {
+        assert!(payload.len() <= self.payload_size());
+        let pos = self.next;
+        if self.is_sector_start(pos) {
+            flash.erase(pos, pos + SECTOR_SIZE).await?;
+        }
+
+        let len = (payload.len() as u16).to_le_bytes();
+        let crc = crc16(payload).to_le_bytes();
+        flash.write(pos + 4, &[len[0], len[1], crc[0], crc[1]]).await?;
+
+        let aligned = payload.len() & !3;
+        if aligned > 0 {
+            flash.write(pos + HEADER_SIZE, &payload[..aligned]).await?;
+        }
+        if aligned < payload.len() {
+            let mut tail = [0xFF; 4];
+            tail[..payload.len() - aligned].copy_from_slice(&payload[aligned..]);
+            flash.write(pos + HEADER_SIZE + aligned as u32, &tail).await?;
+        }
+
+        flash.write(pos, &self.seq.to_le_bytes()).await?;
+
+        self.seq = self.seq.wrapping_add(1);
+        self.next = self.following(pos).unwrap_or(self.start);
+        Ok(())
+    }
+
+    /// Read the `n`th newest record into `buf`, returning the payload length.
+    ///
+    /// `buf` must be able to hold [`payload_size`](Self::payload_size) bytes. Walking the
+    /// whole log this way reads it over and over, use [`newest`](Self::newest) for that.
+    pub async fn read<F: NorFlash>(&self, flash: &mut F, n: usize, buf: &mut [u8]) -> Result<Option<usize>, F::Error> {
+        let mut cursor = self.newest();
+        for _ in 0..n {
+            if self.read_next(flash, &mut cursor, buf).await?.is_none() {
+                return Ok(None);
+            }
+        }
+        self.read_next(flash, &mut cursor, buf).await
+    }
+
+    /// A cursor before the newest record, for reading the records newest first with
+    /// [`read_next`](Self::read_next).
+    pub fn newest(&self) -> Cursor {
+        Cursor {
+            pos: self.next,
+            expected: self.seq,
+            remaining: self.slot_count(),
+        }
+    }
+
+    /// Read the record after `cursor`, the next older one, into `buf` and move the cursor
+    /// past it. Returns the payload length, or `None` after the oldest record.
+    ///
+    /// `buf` must be able to hold [`payload_size`](Self::payload_size) bytes.
+    pub async fn read_next<F: NorFlash>(
+        &self,
+        flash: &mut F,
+        cursor: &mut Cursor,
+        buf: &mut [u8],
+    ) -> Result<Option<usize>, F::Error> {
+        while cursor.remaining > 0 {
+            cursor.remaining -= 1;
+            cursor.pos = self.preceding(cursor.pos);
+            let header = read_header(flash, cursor.pos).await?;
+            if header.seq == UNUSED {
+                continue;
+            }
+            if header.seq >= cursor.expected {
+                // Wrapped around to records older than the oldest sector
+                break;
+            }
+            cursor.expected = header.seq;
+
+            let len = header.len as usize;
+            if len > self.payload_size() {
+                continue;
+            }
+            flash.read(cursor.pos + HEADER_SIZE, &mut buf[..len]).await?;
+            if crc16(&buf[..len]) != header.crc {
+                continue;
+            }
+            return Ok(Some(len));
+        }
+        cursor.remaining = 0;
+        Ok(None)
+    }
+
+    /// Erase the whole region.
+    pub async fn clear<F: NorFlash>(&mut self, flash: &mut F) -> Result<(), F::Error> {
+        flash.erase(self.start, self.end).await?;
+        self.next = self.start;
+        self.seq = 0;
+        Ok(())
+    }
+
+    fn slots_per_sector(&self) -> u32 {
+        SECTOR_SIZE / self.slot_size
+    }
+
+    fn slot_count(&self) -> u32 {
+        (self.end - self.start) / SECTOR_SIZE * self.slots_per_sector()
+    }
+
+    fn is_sector_start(&self, pos: u32) -> bool {
+        (pos - self.start) & (SECTOR_SIZE - 1) == 0
+    }
+
+    /// The slot after `pos`, or `None` when `pos` is the last slot of the region.
+    fn following(&self, pos: u32) -> Option<u32> {
+        let sector = (pos - self.start) / SECTOR_SIZE;
+        let index = (pos - self.start) % SECTOR_SIZE / self.slot_size;
+        if index + 1 < self.slots_per_sector() {
+            Some(pos + self.slot_size)
+        } else if self.start + (sector + 1) * SECTOR_SIZE < self.end {
+            Some(self.start + (sector + 1) * SECTOR_SIZE)
+        } else {
+            None
+        }
+    }
+
+    fn preceding(&self, pos: u32) -> u32 {
+        if pos == self.start {
+            let sectors = (self.end - self.start) / SECTOR_SIZE;
+            self.start + (sectors - 1) * SECTOR_SIZE + (self.slots_per_sector() - 1) * self.slot_size
+        } else if self.is_sector_start(pos) {
+            pos - SECTOR_SIZE + (self.slots_per_sector() - 1) * self.slot_size
+        } else {
+            pos - self.slot_size
+        }
+    }
+}
+
+/// Position in a [`RecordLog`] while reading it newest first.
+#[derive(Debug, Clone, Copy)]
+pub struct Cursor {
+    pos: u32,
+    /// Records must have a sequence number below this, anything else is from before the
+    /// log wrapped around.
+    expected: u32,
+    /// Slots left to look at.
+    remaining: u32,
+}
+
+struct Header {
+    seq: u32,
+    len: u16,
+    crc: u16,
+}
+
+impl Header {
+    fn is_erased(&self) -> bool {
+        self.seq == UNUSED && self.len == u16::MAX && self.crc == u16::MAX
+    }
+}
+
+async fn read_header<F: NorFlash>(flash: &mut F, pos: u32) -> Result<Header, F::Error> {
+    let mut raw = [0; HEADER_SIZE as usize];
+    flash.read(pos, &mut raw).await?;
+    Ok(Header {
+        seq: u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]),
+        len: u16::from_le_bytes([raw[4], raw[5]]),
+        crc: u16::from_le_bytes([raw[6], raw[7]]),
+    })
+}
+
+/// CRC-16/CCITT-FALSE
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use embedded_storage_async::nor_flash::{ErrorType, NorFlashError, NorFlashErrorKind, ReadNorFlash};
+
+    use super::*;
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+    }
+
+    #[futures_test::test]
+    async fn append_and_read() {
+        let mut flash = MemFlash::<{ 3 * 4096 }>::new();
+        let mut log = RecordLog::new(0, 2 * 4096, 8);
+        log.mount(&mut flash).await.unwrap();
+
+        let mut buf = [0; 8];
+        assert_eq!(log.read(&mut flash, 0, &mut buf).await.unwrap(), None);
+
+        for i in 0..10u32 {
+            log.append(&mut flash, &i.to_le_bytes()).await.unwrap();
+        }
+        assert_eq!(log.read(&mut flash, 0, &mut buf).await.unwrap(), Some(4));
+        assert_eq!(&buf[..4], &9u32.to_le_bytes());
+        assert_eq!(log.read(&mut flash, 9, &mut buf).await.unwrap(), Some(4));
+        assert_eq!(&buf[..4], &0u32.to_le_bytes());
+        assert_eq!(log.read(&mut flash, 10, &mut buf).await.unwrap(), None);
+
+        // Nothing is written outside of the region
+        assert!(flash.mem[2 * 4096..].iter().all(|b| *b == 0xFF));
+    }
+
+    #[futures_test::test]
+    async fn wraps_around() {
+        let mut flash = MemFlash::<{ 2 * 4096 }>::new();
+        let mut log = RecordLog::new(0, 2 * 4096, 12);
+        log.mount(&mut flash).await.unwrap();
+
+        // 204 slots per sector, write enough to wrap twice
+        for i in 0..1000u32 {
+            log.append(&mut flash, &i.to_le_bytes()).await.unwrap();
+        }
+
+        let mut buf = [0; 12];
+        log.read(&mut flash, 0, &mut buf).await.unwrap();
+        assert_eq!(&buf[..4], &999u32.to_le_bytes());
+
+        // After erasing the oldest sector, at least one full sector remains
+        let mut count = 0;
+        while log.read(&mut flash, count, &mut buf).await.unwrap().is_some() {
+            assert_eq!(&buf[..4], &(999 - count as u32).to_le_bytes());
+            count += 1;
+        }
+        assert!(count >= 204, "only {} records left", count);
+
+        // A remount continues where the log left off
+        let mut log = RecordLog::new(0, 2 * 4096, 12);
+        log.mount(&mut flash).await.unwrap();
+        log.append(&mut flash, &1000u32.to_le_bytes()).await.unwrap();
+        log.read(&mut flash, 0, &mut buf).await.unwrap();
+        assert_eq!(&buf[..4], &1000u32.to_le_bytes());
+        log.read(&mut flash, 1, &mut buf).await.unwrap();
+        assert_eq!(&buf[..4], &999u32.to_le_bytes());
+    }
+
+    #[futures_test::test]
+    async fn cursor() {
+        let mut flash = MemFlash::<{ 2 * 4096 }>::new();
+        let mut log = RecordLog::new(0, 2 * 4096, 12);
+        log.mount(&mut flash).await.unwrap();
+
+        let mut buf = [0; 12];
+        let mut cursor = log.newest();
+        assert_eq!(log.read_next(&mut flash, &mut cursor, &mut buf).await.unwrap(), None);
+
+        // Wrapped, so the oldest records are gone
+        for i in 0..300u32 {
+            log.append(&mut flash, &i.to_le_bytes()).await.unwrap();
+        }
+        let mut cursor = log.newest();
+        let mut count = 0u32;
+        while let Some(len) = log.read_next(&mut flash, &mut cursor, &mut buf).await.unwrap() {
+            assert_eq!(len, 4);
+            assert_eq!(&buf[..4], &(299 - count).to_le_bytes());
+            count += 1;
+        }
+        assert_eq!(log.read(&mut flash, count as usize, &mut buf).await.unwrap(), None);
+        assert_eq!(
+            log.read(&mut flash, count as usize - 1, &mut buf).await.unwrap(),
+            Some(4)
+        );
+        assert_eq!(&buf[..4], &(300 - count).to_le_bytes());
+        // Stays at the end
+        assert_eq!(log.read_next(&mut flash, &mut cursor, &mut buf).await.unwrap(), None);
+    }
+
+    #[futures_test::test]
+    async fn ignores_torn_records() {
+        let mut flash = MemFlash::<{ 2 * 4096 }>::new();
+        let mut log = RecordLog::new(0, 2 * 4096, 4);
+        log.mount(&mut flash).await.unwrap();
+        log.append(&mut flash, &[1, 2, 3]).await.unwrap();
+        log.append(&mut flash, &[4, 5, 6]).await.unwrap();
+
+        // Reset after writing the payload but before the sequence number
+        let torn = 2 * 12 + 4;
+        flash.mem[torn..torn + 4].copy_from_slice(&[3, 0, 0x12, 0x34]);
+
+        let mut log = RecordLog::new(0, 2 * 4096, 4);
+        log.mount(&mut flash).await.unwrap();
+        log.append(&mut flash, &[7, 8, 9]).await.unwrap();
+
+        let mut buf = [0; 4];
+        assert_eq!(log.read(&mut flash, 0, &mut buf).await.unwrap(), Some(3));
+        assert_eq!(&buf[..3], &[7, 8, 9]);
+        log.read(&mut flash, 1, &mut buf).await.unwrap();
+        assert_eq!(&buf[..3], &[4, 5, 6]);
+
+        // Corrupted payload is skipped
+        flash.mem[3 * 12 + 8] = 0;
+        log.read(&mut flash, 0, &mut buf).await.unwrap();
+        assert_eq!(&buf[..3], &[4, 5, 6]);
+    }
+
+    /// NOR flash in RAM that, like the real thing, can only clear bits when writing.
+    pub struct MemFlash<const SIZE: usize> {
+        pub mem: Vec<u8>,
+    }
+
+    impl<const SIZE: usize> MemFlash<SIZE> {
+        pub fn new() -> Self {
+            Self { mem: vec![0xFF; SIZE] }
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct MemFlashError;
+
+    impl NorFlashError for MemFlashError {
+        fn kind(&self) -> NorFlashErrorKind {
+            NorFlashErrorKind::Other
+        }
+    }
+
+    impl<const SIZE: usize> ErrorType for MemFlash<SIZE> {
+        type Error = MemFlashError;
+    }
+
+    impl<const SIZE: usize> ReadNorFlash for MemFlash<SIZE> {
+        const READ_SIZE: usize = 1;
+
+        async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            let offset = offset as usize;
+            bytes.copy_from_slice(&self.mem[offset..offset + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            SIZE
+        }
+    }
+
+    impl<const SIZE: usize> NorFlash for MemFlash<SIZE> {
+        const WRITE_SIZE: usize = 4;
+        const ERASE_SIZE: usize = 4096;
+
+        async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            assert_eq!(from as usize & (Self::ERASE_SIZE - 1), 0);
+            assert_eq!(to as usize & (Self::ERASE_SIZE - 1), 0);
+            self.mem[from as usize..to as usize].fill(0xFF);
+            Ok(())
+        }
+
+        async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            let offset = offset as usize;
+            assert_eq!(offset & (Self::WRITE_SIZE - 1), 0);
+            assert_eq!(bytes.len() & (Self::WRITE_SIZE - 1), 0);
+            for (mem, byte) in self.mem[offset..offset + bytes.len()].iter_mut().zip(bytes) {
+                *mem &= *byte;
+            }
+            Ok(())
+        }
+    }
+}
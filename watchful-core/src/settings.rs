@@ -0,0 +1,180 @@
+//! User settings and their encoding in flash.
+//!
+//! Settings are stored as a version byte followed by the fields at fixed offsets. New
+//! fields are only ever appended, so a record written by an older firmware decodes with
+//! defaults for the fields it did not know about.
+//!
+//! | offset | size | field                          |
+//! |--------|------|--------------------------------|
+//! | 0      | 1    | version, currently 1           |
+//! | 1      | 1    | flags, bit 0: raise to wake    |
+//! | 2      | 1    | background heart rate interval |
+//! | 3      | 1    | high resting heart rate alert  |
+//! | 4      | 1    | low resting heart rate alert   |
+//! | 5      | 1    | backlight brightness, percent  |
+//! | 6      | 3    | time zone, see [`TimeZone`]    |
+//! | 9      | 1    | world clock, 0 when off        |
+
+use crate::timezone::{TimeZone, WORLD_ZONES};
+
+pub const VERSION: u8 = 1;
+
+/// Room reserved for a stored record, leaving space for new fields.
+pub const MAX_ENCODED_SIZE: usize = 32;
+
+const FLAG_RAISE_TO_WAKE: u8 = 1 << 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    Empty,
+    UnsupportedVersion(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Settings {
+    /// Turn the screen on when the wrist is raised.
+    pub raise_to_wake: bool,
+    /// Minutes between background heart rate readings, 0 when turned off.
+    pub heart_interval: u8,
+    /// Alert when a background reading is above this many beats per minute.
+    pub heart_high: u8,
+    /// Alert when a background reading is below this many beats per minute.
+    pub heart_low: u8,
+    /// Backlight level while the screen is on, in percent.
+    pub brightness: u8,
+    pub time_zone: TimeZone,
+    /// Zone shown on the watch face as a second clock, 0 when off, otherwise the index in
+    /// [`WORLD_ZONES`] plus one.
+    pub world_clock: u8,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl Settings {
+    pub const DEFAULT: Self = Self {
+        raise_to_wake: true,
+        heart_interval: 0,
+        heart_high: 100,
+        heart_low: 40,
+        brightness: 50,
+        time_zone: TimeZone::UTC,
+        world_clock: 0,
+    };
+
+    /// Name and zone of the world clock, if it is on.
+    pub fn world_zone(&self) -> Option<(&'static str, TimeZone)> {
+        WORLD_ZONES.get((self.world_clock as usize).checked_sub(1)?).copied()
+    }
+
+    /// Encode into `buf`, returning the number of bytes used.
+    pub fn encode(&self, buf: &mut [u8; MAX_ENCODED_SIZE]) -> usize {
+        let mut flags = 0;
+        if self.raise_to_wake {
+            flags |= FLAG_RAISE_TO_WAKE;
+        }
+        buf[0] = VERSION;
+        buf[1] = flags;
+        buf[2] = self.heart_interval;
+        buf[3] = self.heart_high;
+        buf[4] = self.heart_low;
+        buf[5] = self.brightness;
+        buf[6..9].copy_from_slice(&self.time_zone.encode());
+        buf[9] = self.world_clock;
+        10
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, Error> {
+        let (&version, fields) = data.split_first().ok_or(Error::Empty)?;
+        if version == 0 || version > VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
+        let mut settings = Self::default();
+        if let Some(&flags) = fields.first() {
+            settings.raise_to_wake = flags & FLAG_RAISE_TO_WAKE != 0;
+        }
+        if let Some(&interval) = fields.get(1) {
+            settings.heart_interval = interval;
+        }
+        if let Some(&high) = fields.get(2) {
+            settings.heart_high = high;
+        }
+        if let Some(&low) = fields.get(3) {
+            settings.heart_low = low;
+        }
+        if let Some(&brightness) = fields.get(4) {
+            settings.brightness = brightness.min(100);
+        }
+        if let Some(zone) = fields.get(5..8).and_then(TimeZone::decode) {
+            settings.time_zone = zone;
+        }
+        if let Some(&world_clock) = fields.get(8) {
+            settings.world_clock = if world_clock as usize <= WORLD_ZONES.len() {
+                world_clock
+            } else {
+                0
+            };
+        }
+        Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timezone::DstRule;
+
+    #[test]
+    fn roundtrip() {
+        let mut buf = [0; MAX_ENCODED_SIZE];
+        let changed = Settings {
+            raise_to_wake: false,
+            heart_interval: 30,
+            heart_high: 110,
+            heart_low: 45,
+            brightness: 80,
+            time_zone: TimeZone::new(60, DstRule::Eu),
+            world_clock: 3,
+        };
+        for settings in [Settings::default(), changed] {
+            let len = settings.encode(&mut buf);
+            assert_eq!(Settings::decode(&buf[..len]), Ok(settings));
+        }
+    }
+
+    #[test]
+    fn missing_fields_use_defaults() {
+        assert_eq!(Settings::decode(&[VERSION]), Ok(Settings::default()));
+        // Written before the heart rate settings existed
+        let settings = Settings::decode(&[VERSION, 0]).unwrap();
+        assert!(!settings.raise_to_wake);
+        assert_eq!(settings.heart_interval, Settings::DEFAULT.heart_interval);
+        assert_eq!(settings.brightness, Settings::DEFAULT.brightness);
+        assert_eq!(settings.time_zone, TimeZone::UTC);
+    }
+
+    #[test]
+    fn world_zone() {
+        let mut settings = Settings::default();
+        assert_eq!(settings.world_zone(), None);
+        settings.world_clock = 1;
+        assert_eq!(settings.world_zone(), Some(WORLD_ZONES[0]));
+        // Out of range on decode
+        let mut buf = [0; MAX_ENCODED_SIZE];
+        let len = settings.encode(&mut buf);
+        buf[9] = 200;
+        assert_eq!(Settings::decode(&buf[..len]).unwrap().world_clock, 0);
+    }
+
+    #[test]
+    fn reject_invalid() {
+        assert_eq!(Settings::decode(&[]), Err(Error::Empty));
+        assert_eq!(Settings::decode(&[0xFF, 0x00]), Err(Error::UnsupportedVersion(0xFF)));
+    }
+}
@@ -0,0 +1,456 @@
+//! Line based command shell, run over the Nordic UART service.
+//!
+//! Commands are written one per line, ending in `\n` or `\r`, and answered with lines of
+//! text. Answers longer than a notification are split at the negotiated ATT MTU, see
+//! [`chunk_size`].
+//!
+//! ```text
+//! help
+//! status
+//! battery
+//! time get
+//! time set 2025-06-01 12:30[:00]
+//! settings get
+//! settings set <key> <value>
+//! reboot
+//! validate
+//! flash info
+//! log level [off|error|warn|info|debug|trace]
+//! ```
+
+use crate::calendar::DateTime;
+use crate::settings::Settings;
+use crate::timezone::{DstRule, WORLD_ZONES};
+
+/// Longest command line, longer lines are dropped.
+pub const MAX_LINE: usize = 64;
+
+/// Bytes of an ATT notification that are not payload.
+const NOTIFICATION_HEADER: usize = 3;
+
+pub const HELP: &str = "commands: status, battery, time get|set, settings get|set, reboot, validate, flash info, \
+                        log level\n";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    UnknownCommand,
+    MissingArgument,
+    InvalidArgument,
+    LineTooLong,
+}
+
+impl Error {
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::UnknownCommand => "unknown command, try help",
+            Self::MissingArgument => "missing argument",
+            Self::InvalidArgument => "invalid argument",
+            Self::LineTooLong => "line too long",
+        }
+    }
+}
+
+/// Which log lines are sent to the shell, each level includes the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Level {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub const ALL: [Self; 6] = [Self::Off, Self::Error, Self::Warn, Self::Info, Self::Debug, Self::Trace];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Info => "info",
+            Self::Debug => "debug",
+            Self::Trace => "trace",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|level| level.name() == name)
+    }
+
+    pub fn from_u8(value: u8) -> Option<Self> {
+        Self::ALL.get(value as usize).copied()
+    }
+
+    pub fn to_u8(self) -> u8 {
+        self as u8
+    }
+}
+
+/// A setting changed from the shell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Setting {
+    RaiseToWake(bool),
+    HeartInterval(u8),
+    HeartHigh(u8),
+    HeartLow(u8),
+    Brightness(u8),
+    /// Standard time offset from UTC in minutes.
+    Offset(i16),
+    Dst(DstRule),
+    /// 0 for off, otherwise the index in [`WORLD_ZONES`] plus one.
+    WorldClock(u8),
+}
+
+impl Setting {
+    fn parse(key: &str, value: &str) -> Result<Self, Error> {
+        let setting = match key {
+            "raise" => Self::RaiseToWake(parse_bool(value)?),
+            "heart_interval" => Self::HeartInterval(parse_number(value)?),
+            "heart_high" => Self::HeartHigh(parse_number(value)?),
+            "heart_low" => Self::HeartLow(parse_number(value)?),
+            "brightness" => Self::Brightness(
+                parse_number(value)
+                    .ok()
+                    .filter(|b| *b <= 100)
+                    .ok_or(Error::InvalidArgument)?,
+            ),
+            "offset" => {
+                let offset: i16 = parse_number(value)?;
+                if offset.abs() > 14 * 60 {
+                    return Err(Error::InvalidArgument);
+                }
+                Self::Offset(offset)
+            }
+            "dst" => Self::Dst(match value {
+                "off" => DstRule::None,
+                "eu" => DstRule::Eu,
+                "us" => DstRule::Us,
+                _ => return Err(Error::InvalidArgument),
+            }),
+            "world" => {
+                let zone: u8 = parse_number(value)?;
+                if zone as usize > WORLD_ZONES.len() {
+                    return Err(Error::InvalidArgument);
+                }
+                Self::WorldClock(zone)
+            }
+            _ => return Err(Error::InvalidArgument),
+        };
+        Ok(setting)
+    }
+
+    pub fn apply(&self, settings: &mut Settings) {
+        match *self {
+            Self::RaiseToWake(on) => settings.raise_to_wake = on,
+            Self::HeartInterval(minutes) => settings.heart_interval = minutes,
+            Self::HeartHigh(bpm) => settings.heart_high = bpm,
+            Self::HeartLow(bpm) => settings.heart_low = bpm,
+            Self::Brightness(percent) => settings.brightness = percent,
+            Self::Offset(minutes) => settings.time_zone.offset_minutes = minutes,
+            Self::Dst(rule) => settings.time_zone.dst = rule,
+            Self::WorldClock(zone) => settings.world_clock = zone,
+        }
+    }
+}
+
+/// Write `settings` one `key value` per line, the way `settings set` takes them.
+pub fn write_settings<W: core::fmt::Write>(settings: &Settings, w: &mut W) -> core::fmt::Result {
+    let dst = match settings.time_zone.dst {
+        DstRule::None => "off",
+        DstRule::Eu => "eu",
+        DstRule::Us => "us",
+    };
+    writeln!(w, "raise {}", if settings.raise_to_wake { "on" } else { "off" })?;
+    writeln!(w, "heart_interval {}", settings.heart_interval)?;
+    writeln!(w, "heart_high {}", settings.heart_high)?;
+    writeln!(w, "heart_low {}", settings.heart_low)?;
+    writeln!(w, "brightness {}", settings.brightness)?;
+    writeln!(w, "offset {}", settings.time_zone.offset_minutes)?;
+    writeln!(w, "dst {}", dst)?;
+    writeln!(w, "world {}", settings.world_clock)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Command {
+    Help,
+    Status,
+    Battery,
+    TimeGet,
+    /// Set the local time.
+    TimeSet {
+        time: DateTime,
+        second: u8,
+    },
+    SettingsGet,
+    SettingsSet(Setting),
+    Reboot,
+    Validate,
+    FlashInfo,
+    /// Show the log level, or change it.
+    LogLevel(Option<Level>),
+}
+
+/// Parse a command line, without its line ending.
+pub fn parse(line: &str) -> Result<Command, Error> {
+    let mut words = line.split_whitespace();
+    let command = match (words.next(), words.next()) {
+        (Some("help"), None) => Command::Help,
+        (Some("status"), None) => Command::Status,
+        (Some("battery"), None) => Command::Battery,
+        (Some("time"), Some("get")) => Command::TimeGet,
+        (Some("time"), Some("set")) => {
+            let date = words.next().ok_or(Error::MissingArgument)?;
+            let time = words.next().ok_or(Error::MissingArgument)?;
+            let (time, second) = parse_date_time(date, time).ok_or(Error::InvalidArgument)?;
+            Command::TimeSet { time, second }
+        }
+        (Some("settings"), Some("get")) => Command::SettingsGet,
+        (Some("settings"), Some("set")) => {
+            let key = words.next().ok_or(Error::MissingArgument)?;
+            let value = words.next().ok_or(Error::MissingArgument)?;
+            Command::SettingsSet(Setting::parse(key, value)?)
+        }
+        (Some("reboot"), None) => Command::Reboot,
+        (Some("validate"), None) => Command::Validate,
+        (Some("flash"), Some("info")) => Command::FlashInfo,
+        (Some("log"), Some("level")) => match words.next() {
+            Some(name) => Command::LogLevel(Some(Level::parse(name).ok_or(Error::InvalidArgument)?)),
+            None => Command::LogLevel(None),
+        },
+        _ => return Err(Error::UnknownCommand),
+    };
+    match words.next() {
+        Some(_) => Err(Error::InvalidArgument),
+        None => Ok(command),
+    }
+}
+
+/// `2025-06-01` and `12:30` or `12:30:15`.
+fn parse_date_time(date: &str, time: &str) -> Option<(DateTime, u8)> {
+    let mut date = date.split('-');
+    let year = date.next()?.parse().ok()?;
+    let month = date.next()?.parse().ok()?;
+    let day = date.next()?.parse().ok()?;
+    let mut time = time.split(':');
+    let hour = time.next()?.parse().ok()?;
+    let minute = time.next()?.parse().ok()?;
+    let second = time.next().map_or(Some(0), |s| s.parse().ok())?;
+    if date.next().is_some() || time.next().is_some() || second >= 60 {
+        return None;
+    }
+    Some((DateTime::new(year, month, day, hour, minute)?, second))
+}
+
+fn parse_bool(value: &str) -> Result<bool, Error> {
+    match value {
+        "on" | "1" | "true" => Ok(true),
+        "off" | "0" | "false" => Ok(false),
+        _ => Err(Error::InvalidArgument),
+    }
+}
+
+fn parse_number<T: core::str::FromStr>(value: &str) -> Result<T, Error> {
+    value.parse().map_err(|_| Error::InvalidArgument)
+}
+
+/// Collects written bytes into command lines.
+pub struct LineBuffer {
+    buf: [u8; MAX_LINE],
+    len: usize,
+    /// The line got longer than [`MAX_LINE`], it is dropped at its end.
+    overflow: bool,
+    /// A line was returned, the next byte starts a new one.
+    complete: bool,
+}
+
+impl Default for LineBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LineBuffer {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; MAX_LINE],
+            len: 0,
+            overflow: false,
+            complete: false,
+        }
+    }
+
+    /// Add a written byte, returning the line it completes. Empty lines are skipped, so
+    /// `\r\n` ends a line once.
+    pub fn push(&mut self, byte: u8) -> Option<Result<&str, Error>> {
+        if self.complete {
+            self.len = 0;
+            self.overflow = false;
+            self.complete = false;
+        }
+        match byte {
+            b'\n' | b'\r' if self.overflow => {
+                self.complete = true;
+                Some(Err(Error::LineTooLong))
+            }
+            b'\n' | b'\r' if self.len == 0 => None,
+            b'\n' | b'\r' => {
+                self.complete = true;
+                Some(core::str::from_utf8(&self.buf[..self.len]).map_err(|_| Error::InvalidArgument))
+            }
+            _ if self.len == MAX_LINE => {
+                self.overflow = true;
+                None
+            }
+            _ => {
+                self.buf[self.len] = byte;
+                self.len += 1;
+                None
+            }
+        }
+    }
+}
+
+/// Payload bytes of each notification at `att_mtu`, at most `max`.
+pub fn chunk_size(att_mtu: u16, max: usize) -> usize {
+    (att_mtu as usize).saturating_sub(NOTIFICATION_HEADER).clamp(1, max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(buffer: &mut LineBuffer, data: &[u8]) -> Vec<Result<String, Error>> {
+        data.iter()
+            .filter_map(|b| buffer.push(*b).map(|line| line.map(String::from)))
+            .collect()
+    }
+
+    #[test]
+    fn commands() {
+        assert_eq!(parse("help"), Ok(Command::Help));
+        assert_eq!(parse("  status "), Ok(Command::Status));
+        assert_eq!(parse("battery"), Ok(Command::Battery));
+        assert_eq!(parse("time get"), Ok(Command::TimeGet));
+        assert_eq!(parse("settings get"), Ok(Command::SettingsGet));
+        assert_eq!(parse("reboot"), Ok(Command::Reboot));
+        assert_eq!(parse("validate"), Ok(Command::Validate));
+        assert_eq!(parse("flash info"), Ok(Command::FlashInfo));
+        assert_eq!(parse("log level"), Ok(Command::LogLevel(None)));
+        assert_eq!(parse("log level debug"), Ok(Command::LogLevel(Some(Level::Debug))));
+    }
+
+    #[test]
+    fn invalid_commands() {
+        assert_eq!(parse(""), Err(Error::UnknownCommand));
+        assert_eq!(parse("status now"), Err(Error::UnknownCommand));
+        assert_eq!(parse("time"), Err(Error::UnknownCommand));
+        assert_eq!(parse("flash info all"), Err(Error::InvalidArgument));
+        assert_eq!(parse("log level loud"), Err(Error::InvalidArgument));
+        assert_eq!(parse("settings set brightness"), Err(Error::MissingArgument));
+    }
+
+    #[test]
+    fn time_set() {
+        let time = DateTime::new(2024, 2, 29, 23, 59).unwrap();
+        assert_eq!(
+            parse("time set 2024-02-29 23:59:30"),
+            Ok(Command::TimeSet { time, second: 30 })
+        );
+        assert_eq!(
+            parse("time set 2024-02-29 23:59"),
+            Ok(Command::TimeSet { time, second: 0 })
+        );
+        assert_eq!(parse("time set 2025-02-29 12:00"), Err(Error::InvalidArgument));
+        assert_eq!(parse("time set 2025-01-01 12:00:60"), Err(Error::InvalidArgument));
+        assert_eq!(parse("time set 2025-01-01 12"), Err(Error::InvalidArgument));
+        assert_eq!(parse("time set 2025-01-01"), Err(Error::MissingArgument));
+    }
+
+    #[test]
+    fn settings_set() {
+        assert_eq!(
+            parse("settings set raise off"),
+            Ok(Command::SettingsSet(Setting::RaiseToWake(false)))
+        );
+        assert_eq!(
+            parse("settings set brightness 80"),
+            Ok(Command::SettingsSet(Setting::Brightness(80)))
+        );
+        assert_eq!(parse("settings set brightness 101"), Err(Error::InvalidArgument));
+        assert_eq!(
+            parse("settings set offset -300"),
+            Ok(Command::SettingsSet(Setting::Offset(-300)))
+        );
+        assert_eq!(parse("settings set offset 900"), Err(Error::InvalidArgument));
+        assert_eq!(
+            parse("settings set dst eu"),
+            Ok(Command::SettingsSet(Setting::Dst(DstRule::Eu)))
+        );
+        assert_eq!(parse("settings set world 9"), Err(Error::InvalidArgument));
+        assert_eq!(parse("settings set colour red"), Err(Error::InvalidArgument));
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let mut settings = Settings::DEFAULT;
+        Setting::Dst(DstRule::Us).apply(&mut settings);
+        Setting::Offset(-300).apply(&mut settings);
+        Setting::HeartInterval(30).apply(&mut settings);
+        let mut out = String::new();
+        write_settings(&settings, &mut out).unwrap();
+        let mut read_back = Settings::DEFAULT;
+        for line in out.lines() {
+            let mut words = line.split(' ');
+            let (key, value) = (words.next().unwrap(), words.next().unwrap());
+            Setting::parse(key, value).unwrap().apply(&mut read_back);
+        }
+        assert_eq!(read_back, settings);
+    }
+
+    #[test]
+    fn levels() {
+        for level in Level::ALL {
+            assert_eq!(Level::parse(level.name()), Some(level));
+            assert_eq!(Level::from_u8(level.to_u8()), Some(level));
+        }
+        assert!(Level::Warn < Level::Info);
+        assert_eq!(Level::from_u8(6), None);
+    }
+
+    #[test]
+    fn splits_lines() {
+        let mut buffer = LineBuffer::new();
+        assert_eq!(
+            lines(&mut buffer, b"status\r\nbattery\ntime "),
+            vec![Ok("status".into()), Ok("battery".into())]
+        );
+        // Continues over writes
+        assert_eq!(lines(&mut buffer, b"get\n"), vec![Ok("time get".into())]);
+        assert_eq!(lines(&mut buffer, b"\n\r\n"), vec![]);
+    }
+
+    #[test]
+    fn drops_long_lines() {
+        let mut buffer = LineBuffer::new();
+        let mut data = vec![b'x'; MAX_LINE + 10];
+        data.extend_from_slice(b"\nstatus\n");
+        assert_eq!(
+            lines(&mut buffer, &data),
+            vec![Err(Error::LineTooLong), Ok("status".into())]
+        );
+        assert_eq!(lines(&mut buffer, &[0xff, b'\n']), vec![Err(Error::InvalidArgument)]);
+    }
+
+    #[test]
+    fn chunks() {
+        assert_eq!(chunk_size(23, 57), 20);
+        assert_eq!(chunk_size(247, 57), 57);
+        assert_eq!(chunk_size(0, 57), 1);
+    }
+}
@@ -0,0 +1,368 @@
+//! Sleep tracking from wrist movement.
+//!
+//! Accelerometer samples are reduced to an activity count per minute, and every minute is
+//! scored asleep or awake from a weighted window of the counts around it, in the manner of
+//! the Cole-Kripke actigraphy algorithm. The minutes of a night are summarised in a
+//! [`NightSummary`] small enough to keep many nights in flash.
+
+use crate::motion::{Sample, SAMPLE_RATE};
+
+/// Changes in magnitude below this (in milli-g) are sensor noise.
+const NOISE: u32 = 50;
+const SAMPLES_PER_EPOCH: u32 = 60 * SAMPLE_RATE;
+
+/// Weights of the minutes from four before to two after the scored one.
+const WEIGHTS: [u32; 7] = [106, 54, 58, 76, 230, 74, 67];
+/// Scored asleep when the weighted average activity is below this.
+const THRESHOLD: u32 = 350;
+/// Awake periods shorter than this (in minutes) are not counted as awakenings.
+const MIN_AWAKENING: u16 = 5;
+
+/// Minutes per hypnogram slot.
+pub const SLOT_MINUTES: u16 = 5;
+pub const HYPNOGRAM_SLOTS: usize = 160;
+pub const ENCODED_SIZE: usize = 15 + HYPNOGRAM_SLOTS / 8;
+
+/// Sums up movement over one minute epochs.
+pub struct ActivityCounter {
+    last: Option<u32>,
+    sum: u32,
+    samples: u32,
+}
+
+impl ActivityCounter {
+    pub const fn new() -> Self {
+        Self {
+            last: None,
+            sum: 0,
+            samples: 0,
+        }
+    }
+
+    /// Feed one sample taken at [`SAMPLE_RATE`], returning the activity count when a minute
+    /// has passed.
+    pub fn update(&mut self, sample: Sample) -> Option<u16> {
+        let magnitude = sample.magnitude();
+        if let Some(last) = self.last.replace(magnitude) {
+            self.sum = self.sum.saturating_add(magnitude.abs_diff(last).saturating_sub(NOISE));
+        }
+        self.samples += 1;
+        if self.samples == SAMPLES_PER_EPOCH {
+            let count = self.sum.min(u16::MAX as u32) as u16;
+            self.sum = 0;
+            self.samples = 0;
+            Some(count)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for ActivityCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Epoch {
+    Asleep,
+    Awake,
+    /// The watch was not worn.
+    OffWrist,
+}
+
+/// Scores minutes as asleep or awake. The score of a minute is known two minutes later.
+pub struct Classifier {
+    window: [u16; 7],
+    filled: usize,
+}
+
+impl Classifier {
+    pub const fn new() -> Self {
+        Self {
+            window: [0; 7],
+            filled: 0,
+        }
+    }
+
+    /// Add the activity count of the latest minute, returning the score of the minute two
+    /// before it.
+    pub fn push(&mut self, activity: u16) -> Option<Epoch> {
+        self.window.copy_within(1.., 0);
+        self.window[6] = activity;
+        self.filled = (self.filled + 1).min(7);
+        // The first minutes are scored with the missing history taken as still
+        (self.filled >= 3).then(|| self.score())
+    }
+
+    /// Score the remaining minutes at the end of a night.
+    pub fn finish(&mut self) -> impl Iterator<Item = Epoch> + '_ {
+        let pending = self.filled.min(2);
+        (0..pending).map(move |_| {
+            self.window.copy_within(1.., 0);
+            self.window[6] = 0;
+            self.score()
+        })
+    }
+
+    fn score(&self) -> Epoch {
+        let weighted: u32 = self.window.iter().zip(WEIGHTS).map(|(a, w)| *a as u32 * w).sum();
+        if weighted < THRESHOLD * WEIGHTS.iter().sum::<u32>() {
+            Epoch::Asleep
+        } else {
+            Epoch::Awake
+        }
+    }
+}
+
+impl Default for Classifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Summary of one night.
+///
+/// Encoded little endian as:
+///
+/// | offset | size | field                                                |
+/// |--------|------|------------------------------------------------------|
+/// | 0      | 4    | julian day of the evening the night started          |
+/// | 4      | 2    | start, minutes after midnight                        |
+/// | 6      | 2    | minutes tracked                                      |
+/// | 8      | 2    | minutes asleep                                       |
+/// | 10     | 2    | fell asleep, minutes after start                     |
+/// | 12     | 2    | woke up, minutes after start                         |
+/// | 14     | 1    | awakenings                                           |
+/// | 15     | 20   | hypnogram, one bit per 5 minutes, set when asleep    |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NightSummary {
+    pub day: i32,
+    pub start: u16,
+    pub minutes: u16,
+    pub asleep: u16,
+    pub onset: u16,
+    pub wake: u16,
+    pub awakenings: u8,
+    pub hypnogram: [u8; HYPNOGRAM_SLOTS / 8],
+}
+
+impl NightSummary {
+    /// Whether the person was mostly asleep during the given slot.
+    pub fn asleep_in_slot(&self, slot: usize) -> bool {
+        slot < HYPNOGRAM_SLOTS && self.hypnogram[slot / 8] & (1 << (slot % 8)) != 0
+    }
+
+    /// Number of hypnogram slots covering the night.
+    pub fn slots(&self) -> usize {
+        (self.minutes.div_ceil(SLOT_MINUTES) as usize).min(HYPNOGRAM_SLOTS)
+    }
+
+    pub fn encode(&self) -> [u8; ENCODED_SIZE] {
+        let mut buf = [0; ENCODED_SIZE];
+        buf[0..4].copy_from_slice(&self.day.to_le_bytes());
+        buf[4..6].copy_from_slice(&self.start.to_le_bytes());
+        buf[6..8].copy_from_slice(&self.minutes.to_le_bytes());
+        buf[8..10].copy_from_slice(&self.asleep.to_le_bytes());
+        buf[10..12].copy_from_slice(&self.onset.to_le_bytes());
+        buf[12..14].copy_from_slice(&self.wake.to_le_bytes());
+        buf[14] = self.awakenings;
+        buf[15..].copy_from_slice(&self.hypnogram);
+        buf
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() != ENCODED_SIZE {
+            return None;
+        }
+        let u16_at = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]);
+        let mut hypnogram = [0; HYPNOGRAM_SLOTS / 8];
+        hypnogram.copy_from_slice(&data[15..]);
+        Some(Self {
+            day: i32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+            start: u16_at(4),
+            minutes: u16_at(6),
+            asleep: u16_at(8),
+            onset: u16_at(10),
+            wake: u16_at(12),
+            awakenings: data[14],
+            hypnogram,
+        })
+    }
+}
+
+/// Collects the scored minutes of a night.
+pub struct Night {
+    day: i32,
+    start: u16,
+    minutes: u16,
+    asleep: u16,
+    onset: Option<u16>,
+    wake: u16,
+    awakenings: u8,
+    /// Length of the current awake period after falling asleep.
+    awake_run: u16,
+    slot_asleep: u16,
+    hypnogram: [u8; HYPNOGRAM_SLOTS / 8],
+}
+
+impl Night {
+    /// A night starting at `start` minutes after midnight of the given julian day.
+    pub const fn new(day: i32, start: u16) -> Self {
+        Self {
+            day,
+            start,
+            minutes: 0,
+            asleep: 0,
+            onset: None,
+            wake: 0,
+            awakenings: 0,
+            awake_run: 0,
+            slot_asleep: 0,
+            hypnogram: [0; HYPNOGRAM_SLOTS / 8],
+        }
+    }
+
+    pub fn record(&mut self, epoch: Epoch) {
+        let minute = self.minutes;
+        self.minutes = self.minutes.saturating_add(1);
+
+        if epoch == Epoch::Asleep {
+            self.asleep += 1;
+            self.onset.get_or_insert(minute);
+            self.wake = minute + 1;
+            if self.awake_run >= MIN_AWAKENING {
+                self.awakenings = self.awakenings.saturating_add(1);
+            }
+            self.awake_run = 0;
+            self.slot_asleep += 1;
+        } else if self.onset.is_some() {
+            self.awake_run += 1;
+        }
+
+        if minute % SLOT_MINUTES == SLOT_MINUTES - 1 {
+            let slot = (minute / SLOT_MINUTES) as usize;
+            if slot < HYPNOGRAM_SLOTS && self.slot_asleep * 2 > SLOT_MINUTES {
+                self.hypnogram[slot / 8] |= 1 << (slot % 8);
+            }
+            self.slot_asleep = 0;
+        }
+    }
+
+    /// Minutes recorded so far.
+    pub fn minutes(&self) -> u16 {
+        self.minutes
+    }
+
+    pub fn summary(&self) -> NightSummary {
+        NightSummary {
+            day: self.day,
+            start: self.start,
+            minutes: self.minutes,
+            asleep: self.asleep,
+            onset: self.onset.unwrap_or(0),
+            wake: self.wake,
+            awakenings: self.awakenings,
+            hypnogram: self.hypnogram,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motion::tests::trace;
+
+    fn activity(csv: &str) -> Vec<u16> {
+        csv.lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(|l| l.parse().unwrap())
+            .collect()
+    }
+
+    fn track(epochs: impl IntoIterator<Item = Option<u16>>) -> NightSummary {
+        let mut classifier = Classifier::new();
+        let mut night = Night::new(0, 22 * 60);
+        for epoch in epochs {
+            match epoch {
+                Some(a) => {
+                    if let Some(e) = classifier.push(a) {
+                        night.record(e);
+                    }
+                }
+                None => {
+                    for e in classifier.finish() {
+                        night.record(e);
+                    }
+                    classifier = Classifier::new();
+                    night.record(Epoch::OffWrist);
+                }
+            }
+        }
+        for e in classifier.finish() {
+            night.record(e);
+        }
+        night.summary()
+    }
+
+    #[test]
+    fn activity_count() {
+        let minute = |csv: &str| {
+            let mut counter = ActivityCounter::new();
+            trace(csv).into_iter().find_map(|s| counter.update(s)).unwrap()
+        };
+        let walking = minute(include_str!("../fixtures/motion/walk.csv"));
+        let desk = minute(include_str!("../fixtures/motion/desk.csv"));
+        assert!(walking > 10 * THRESHOLD as u16, "walking: {}", walking);
+        assert!(desk > THRESHOLD as u16, "desk: {}", desk);
+
+        let mut counter = ActivityCounter::new();
+        let still = (0..SAMPLES_PER_EPOCH).find_map(|_| counter.update(Sample::new(3, -20, -1000)));
+        assert_eq!(still, Some(0));
+    }
+
+    #[test]
+    fn night() {
+        let night = track(
+            activity(include_str!("../fixtures/sleep/night.csv"))
+                .into_iter()
+                .map(Some),
+        );
+        assert_eq!(night.minutes, 660);
+        assert!(night.asleep.abs_diff(478) <= 24, "asleep {} minutes", night.asleep);
+        assert!(
+            night.onset.abs_diff(40) <= 5,
+            "fell asleep after {} minutes",
+            night.onset
+        );
+        assert!(night.wake.abs_diff(530) <= 5, "woke up after {} minutes", night.wake);
+        assert_eq!(night.awakenings, 1);
+        assert!(!night.asleep_in_slot(2));
+        assert!(night.asleep_in_slot(20));
+        assert!(!night.asleep_in_slot(61));
+        assert!(night.asleep_in_slot(80));
+        assert!(!night.asleep_in_slot(120));
+        assert_eq!(night.slots(), 132);
+    }
+
+    #[test]
+    fn off_wrist_is_not_sleep() {
+        let night = track((0..120).map(|m| if m < 60 { Some(0) } else { None }));
+        assert_eq!(night.minutes, 120);
+        assert_eq!(night.asleep, 60);
+    }
+
+    #[test]
+    fn summary_encoding() {
+        let night = track(
+            activity(include_str!("../fixtures/sleep/night.csv"))
+                .into_iter()
+                .map(Some),
+        );
+        assert_eq!(NightSummary::decode(&night.encode()), Some(night));
+        assert_eq!(NightSummary::decode(&[0; 3]), None);
+    }
+}
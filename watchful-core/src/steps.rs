@@ -0,0 +1,167 @@
+//! Step detection on raw accelerometer samples.
+//!
+//! The magnitude of the acceleration is high-pass filtered to remove gravity and smoothed,
+//! then every swing above an adaptive threshold and back through zero is a step candidate.
+//! Candidates only count once a run of them arrives at a walking cadence, which keeps
+//! typing, gestures and the odd bump from adding up over the day.
+//!
+//! The thresholds below are tuned against the synthetic traces in `fixtures/motion` only,
+//! they have not been checked against recordings from a wrist yet.
+
+use core::cmp::Ordering;
+
+use crate::motion::{Sample, SAMPLE_RATE};
+
+/// Peaks below this (in milli-g) are never steps.
+const MIN_THRESHOLD: i32 = 100;
+/// Shortest and longest time between two steps, in samples.
+const MIN_INTERVAL: u32 = SAMPLE_RATE / 4;
+const MAX_INTERVAL: u32 = 2 * SAMPLE_RATE;
+/// Candidates in a row needed before they are counted as walking.
+const REQUIRED_RUN: u32 = 5;
+/// Time constant of the gravity estimate, as a power of two in samples.
+const BASELINE_SHIFT: u32 = 6;
+const SMOOTHING: usize = 3;
+
+pub struct StepCounter {
+    /// Low-pass filtered magnitude, scaled by `1 << BASELINE_SHIFT`.
+    baseline: Option<i32>,
+    window: [i32; SMOOTHING],
+    pos: usize,
+    threshold: i32,
+    /// Highest point of the current swing above the threshold.
+    peak: Option<(i32, u32)>,
+    sample: u32,
+    last_step: Option<u32>,
+    run: u32,
+}
+
+impl StepCounter {
+    pub const fn new() -> Self {
+        Self {
+            baseline: None,
+            window: [0; SMOOTHING],
+            pos: 0,
+            threshold: MIN_THRESHOLD,
+            peak: None,
+            sample: 0,
+            last_step: None,
+            run: 0,
+        }
+    }
+
+    /// Feed one sample taken at [`SAMPLE_RATE`], returning the number of new steps.
+    ///
+    /// The first steps of a walk are reported together once the walk is recognised.
+    pub fn update(&mut self, sample: Sample) -> u32 {
+        self.sample = self.sample.wrapping_add(1);
+        let magnitude = sample.magnitude() as i32;
+        let baseline = self.baseline.get_or_insert(magnitude << BASELINE_SHIFT);
+        *baseline += magnitude - (*baseline >> BASELINE_SHIFT);
+        let filtered = magnitude - (*baseline >> BASELINE_SHIFT);
+
+        self.window[self.pos] = filtered;
+        self.pos = (self.pos + 1) % SMOOTHING;
+        let value = self.window.iter().sum::<i32>() / SMOOTHING as i32;
+
+        if let Some(last) = self.last_step {
+            if self.sample.wrapping_sub(last) > MAX_INTERVAL {
+                // Stopped walking
+                self.last_step = None;
+                self.run = 0;
+                self.threshold = MIN_THRESHOLD;
+            }
+        }
+
+        if value > self.threshold {
+            if self.peak.map(|(peak, _)| value > peak).unwrap_or(true) {
+                self.peak = Some((value, self.sample));
+            }
+            0
+        } else if value < 0 {
+            match self.peak.take() {
+                Some((peak, at)) => self.step(peak, at),
+                None => 0,
+            }
+        } else {
+            0
+        }
+    }
+
+    /// Forget any walk in progress.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    fn step(&mut self, peak: i32, at: u32) -> u32 {
+        if let Some(last) = self.last_step {
+            if at.wrapping_sub(last) < MIN_INTERVAL {
+                // Ringing of the previous step
+                return 0;
+            }
+        }
+        self.last_step = Some(at);
+        self.threshold = ((3 * self.threshold + peak / 2) / 4).max(MIN_THRESHOLD);
+
+        self.run += 1;
+        match self.run.cmp(&REQUIRED_RUN) {
+            Ordering::Less => 0,
+            Ordering::Equal => REQUIRED_RUN,
+            Ordering::Greater => 1,
+        }
+    }
+}
+
+impl Default for StepCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motion::tests::trace;
+
+    fn count(csv: &str) -> u32 {
+        let mut counter = StepCounter::new();
+        trace(csv).into_iter().map(|s| counter.update(s)).sum()
+    }
+
+    fn assert_close(counted: u32, expected: u32) {
+        let error = counted.abs_diff(expected);
+        assert!(
+            error * 20 <= expected,
+            "counted {} steps, expected {}",
+            counted,
+            expected
+        );
+    }
+
+    #[test]
+    fn walking() {
+        assert_close(count(include_str!("../fixtures/motion/walk.csv")), 108);
+    }
+
+    #[test]
+    fn walking_with_a_break() {
+        assert_close(count(include_str!("../fixtures/motion/walk_pause_walk.csv")), 80);
+    }
+
+    #[test]
+    fn sitting_at_a_desk() {
+        assert_eq!(count(include_str!("../fixtures/motion/desk.csv")), 0);
+    }
+
+    #[test]
+    fn isolated_bumps() {
+        let mut counter = StepCounter::new();
+        let mut steps = 0;
+        for i in 0..(30 * SAMPLE_RATE) {
+            // A sharp bump every three seconds
+            let z = if i % (3 * SAMPLE_RATE) < 2 { -1600 } else { -1000 };
+            steps += counter.update(Sample::new(0, 0, z));
+        }
+        assert_eq!(steps, 0);
+    }
+}
@@ -0,0 +1,320 @@
+//! Time zones with daylight saving time.
+//!
+//! The clock runs in UTC, local time is derived from a standard offset and the rule that
+//! decides when daylight saving time applies. Times are seconds since 1970, UTC unless
+//! named local.
+
+const HOUR: i64 = 3600;
+const DAY: i64 = 24 * HOUR;
+
+/// When daylight saving time applies, it always moves the clock forward by an hour.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DstRule {
+    None,
+    /// From 01:00 UTC on the last Sunday of March to 01:00 UTC on the last Sunday of
+    /// October.
+    Eu,
+    /// From 02:00 local on the second Sunday of March to 02:00 local on the first Sunday of
+    /// November.
+    Us,
+}
+
+impl DstRule {
+    pub const ALL: [Self; 3] = [Self::None, Self::Eu, Self::Us];
+
+    fn from_u8(value: u8) -> Option<Self> {
+        Self::ALL.get(value as usize).copied()
+    }
+
+    fn to_u8(self) -> u8 {
+        self as u8
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TimeZone {
+    /// Standard time offset from UTC in minutes.
+    pub offset_minutes: i16,
+    pub dst: DstRule,
+}
+
+impl TimeZone {
+    pub const UTC: Self = Self::new(0, DstRule::None);
+
+    pub const fn new(offset_minutes: i16, dst: DstRule) -> Self {
+        Self { offset_minutes, dst }
+    }
+
+    /// Whether daylight saving time applies at `time`.
+    pub fn is_dst(&self, time: i64) -> bool {
+        let standard = self.offset_minutes as i64 * 60;
+        let (year, _, _) = civil_from_days((time + standard).div_euclid(DAY));
+        let (start, end) = match self.dst {
+            DstRule::None => return false,
+            DstRule::Eu => (last_sunday(year, 3) * DAY + HOUR, last_sunday(year, 10) * DAY + HOUR),
+            DstRule::Us => (
+                (nth_sunday(year, 3, 2) * DAY + 2 * HOUR) - standard,
+                (nth_sunday(year, 11, 1) * DAY + 2 * HOUR) - standard - HOUR,
+            ),
+        };
+        time >= start && time < end
+    }
+
+    /// Offset from UTC in seconds at `time`, daylight saving time included.
+    pub fn offset_at(&self, time: i64) -> i32 {
+        let dst = if self.is_dst(time) { HOUR } else { 0 };
+        (self.offset_minutes as i64 * 60 + dst) as i32
+    }
+
+    pub fn to_local(&self, time: i64) -> i64 {
+        time + self.offset_at(time) as i64
+    }
+
+    /// The UTC time of a `local` time. Local times skipped or repeated when daylight saving
+    /// time starts or ends resolve to the standard time offset.
+    pub fn to_utc(&self, local: i64) -> i64 {
+        let standard = local - self.offset_minutes as i64 * 60;
+        let dst = standard - HOUR;
+        if self.is_dst(dst) && self.is_dst(standard) {
+            dst
+        } else {
+            standard
+        }
+    }
+
+    /// Write the offset as `UTC+01:00`.
+    pub fn write_offset<W: core::fmt::Write>(&self, time: i64, w: &mut W) -> core::fmt::Result {
+        let offset = self.offset_at(time) / 60;
+        let sign = if offset < 0 { '-' } else { '+' };
+        let offset = offset.abs();
+        write!(w, "UTC{}{:02}:{:02}", sign, offset / 60, offset % 60)
+    }
+
+    /// Encode as the offset, little endian `i16`, and the rule.
+    pub fn encode(&self) -> [u8; 3] {
+        let [a, b] = self.offset_minutes.to_le_bytes();
+        [a, b, self.dst.to_u8()]
+    }
+
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let &[a, b, dst] = data.get(..3)? else {
+            return None;
+        };
+        let offset_minutes = i16::from_le_bytes([a, b]);
+        if offset_minutes.abs() > 14 * 60 {
+            return None;
+        }
+        Some(Self::new(offset_minutes, DstRule::from_u8(dst)?))
+    }
+}
+
+/// The Local Time Information characteristic (0x2A0F) of the Current Time Service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LocalTimeInformation {
+    /// Standard time offset from UTC in minutes.
+    pub offset_minutes: i16,
+    /// Daylight saving time currently in effect, in minutes.
+    pub dst_minutes: u8,
+}
+
+impl LocalTimeInformation {
+    /// What the watch tells about `zone` at `time`.
+    pub fn new(zone: TimeZone, time: i64) -> Self {
+        Self {
+            offset_minutes: zone.offset_minutes,
+            dst_minutes: if zone.is_dst(time) { 60 } else { 0 },
+        }
+    }
+
+    pub fn encode(&self) -> [u8; 2] {
+        [(self.offset_minutes / 15) as i8 as u8, self.dst_minutes / 15]
+    }
+
+    /// `None` if the phone does not know its time zone.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let &[zone, dst] = data.get(..2)? else {
+            return None;
+        };
+        // Offset in 15 minute steps, -128 is unknown
+        let zone = zone as i8;
+        if !(-48..=56).contains(&zone) {
+            return None;
+        }
+        // Daylight saving time in 15 minute steps, 255 is unknown
+        let dst_minutes = match dst {
+            0 | 2 | 4 | 8 => dst * 15,
+            _ => 0,
+        };
+        Some(Self {
+            offset_minutes: zone as i16 * 15,
+            dst_minutes,
+        })
+    }
+
+    /// Apply to `zone`. With a rule the phone's standard offset is used as is, otherwise
+    /// the current daylight saving time is folded into a fixed offset until the next sync.
+    pub fn apply(&self, zone: TimeZone) -> TimeZone {
+        match zone.dst {
+            DstRule::None => TimeZone::new(self.offset_minutes + self.dst_minutes as i16, DstRule::None),
+            dst => TimeZone::new(self.offset_minutes, dst),
+        }
+    }
+}
+
+/// Zones that can be picked for the world clock.
+pub const WORLD_ZONES: [(&str, TimeZone); 8] = [
+    ("UTC", TimeZone::UTC),
+    ("London", TimeZone::new(0, DstRule::Eu)),
+    ("Berlin", TimeZone::new(60, DstRule::Eu)),
+    ("New York", TimeZone::new(-300, DstRule::Us)),
+    ("Los Angeles", TimeZone::new(-480, DstRule::Us)),
+    ("Kolkata", TimeZone::new(330, DstRule::None)),
+    ("Shanghai", TimeZone::new(480, DstRule::None)),
+    ("Tokyo", TimeZone::new(540, DstRule::None)),
+];
+
+/// Days since 1970 of a date in the proleptic Gregorian calendar.
+pub fn days_from_civil(year: i32, month: u8, day: u8) -> i64 {
+    let year = year as i64 - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Year, month and day of `days` since 1970.
+pub fn civil_from_days(days: i64) -> (i32, u8, u8) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year as i32, month, day)
+}
+
+/// Day of the week of `days` since 1970, 0 for Sunday.
+fn weekday(days: i64) -> i64 {
+    (days + 4).rem_euclid(7)
+}
+
+fn last_sunday(year: i32, month: u8) -> i64 {
+    let last = days_from_civil(year, month + 1, 1) - 1;
+    last - weekday(last)
+}
+
+fn nth_sunday(year: i32, month: u8, n: i64) -> i64 {
+    let first = days_from_civil(year, month, 1);
+    first + (7 - weekday(first)) % 7 + (n - 1) * 7
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(year: i32, month: u8, day: u8, hour: i64, minute: i64) -> i64 {
+        days_from_civil(year, month, day) * DAY + hour * HOUR + minute * 60
+    }
+
+    #[test]
+    fn civil_dates() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11017);
+        assert_eq!(civil_from_days(11017), (2000, 3, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        for days in [0, 59, 365, 10_000, 20_513] {
+            let (y, m, d) = civil_from_days(days);
+            assert_eq!(days_from_civil(y, m, d), days);
+        }
+        // 2025-03-30 was a Sunday
+        assert_eq!(weekday(days_from_civil(2025, 3, 30)), 0);
+    }
+
+    #[test]
+    fn eu_rule() {
+        let berlin = TimeZone::new(60, DstRule::Eu);
+        // Switches at 01:00 UTC on 2025-03-30 and 2025-10-26
+        assert_eq!(berlin.offset_at(at(2025, 3, 30, 0, 59)), 3600);
+        assert_eq!(berlin.offset_at(at(2025, 3, 30, 1, 0)), 7200);
+        assert_eq!(berlin.offset_at(at(2025, 10, 26, 0, 59)), 7200);
+        assert_eq!(berlin.offset_at(at(2025, 10, 26, 1, 0)), 3600);
+        assert_eq!(berlin.offset_at(at(2025, 1, 15, 12, 0)), 3600);
+    }
+
+    #[test]
+    fn us_rule() {
+        let new_york = TimeZone::new(-300, DstRule::Us);
+        // 2025-03-09 02:00 EST is 07:00 UTC, 2025-11-02 02:00 EDT is 06:00 UTC
+        assert_eq!(new_york.offset_at(at(2025, 3, 9, 6, 59)), -5 * 3600);
+        assert_eq!(new_york.offset_at(at(2025, 3, 9, 7, 0)), -4 * 3600);
+        assert_eq!(new_york.offset_at(at(2025, 11, 2, 5, 59)), -4 * 3600);
+        assert_eq!(new_york.offset_at(at(2025, 11, 2, 6, 0)), -5 * 3600);
+    }
+
+    #[test]
+    fn local_roundtrip() {
+        let berlin = TimeZone::new(60, DstRule::Eu);
+        for time in [at(2025, 1, 1, 12, 0), at(2025, 7, 1, 12, 0), at(2025, 3, 30, 1, 0)] {
+            assert_eq!(berlin.to_utc(berlin.to_local(time)), time);
+        }
+        // The repeated hour resolves to standard time
+        let repeated = at(2025, 10, 26, 2, 30);
+        assert_eq!(berlin.to_utc(repeated), at(2025, 10, 26, 1, 30));
+        assert_eq!(TimeZone::UTC.to_local(1234), 1234);
+    }
+
+    #[test]
+    fn local_time_information() {
+        // UTC+1 with an hour of daylight saving time
+        let info = LocalTimeInformation::parse(&[4, 4]).unwrap();
+        assert_eq!(info.offset_minutes, 60);
+        assert_eq!(info.dst_minutes, 60);
+        assert_eq!(info.apply(TimeZone::UTC), TimeZone::new(120, DstRule::None));
+        assert_eq!(
+            info.apply(TimeZone::new(0, DstRule::Eu)),
+            TimeZone::new(60, DstRule::Eu)
+        );
+        // India, unknown daylight saving time
+        let info = LocalTimeInformation::parse(&[22, 255]).unwrap();
+        assert_eq!(info.offset_minutes, 330);
+        assert_eq!(info.dst_minutes, 0);
+        // Unknown zone
+        assert_eq!(LocalTimeInformation::parse(&[0x80, 0]), None);
+        // What the watch serves
+        let berlin = TimeZone::new(60, DstRule::Eu);
+        let summer = LocalTimeInformation::new(berlin, at(2025, 7, 1, 12, 0));
+        assert_eq!(summer.encode(), [4, 4]);
+        let winter = LocalTimeInformation::new(TimeZone::new(-300, DstRule::Us), at(2025, 1, 1, 12, 0));
+        assert_eq!(LocalTimeInformation::parse(&winter.encode()), Some(winter));
+        assert_eq!(LocalTimeInformation::parse(&[4]), None);
+    }
+
+    #[test]
+    fn encoding() {
+        for zone in [
+            TimeZone::UTC,
+            TimeZone::new(-570, DstRule::Us),
+            TimeZone::new(345, DstRule::Eu),
+        ] {
+            assert_eq!(TimeZone::decode(&zone.encode()), Some(zone));
+        }
+        assert_eq!(TimeZone::decode(&[0, 0, 9]), None);
+        assert_eq!(TimeZone::decode(&[0xFF, 0x7F, 0]), None);
+        assert_eq!(TimeZone::decode(&[0]), None);
+    }
+
+    #[test]
+    fn offset_text() {
+        let mut buf = heapless::String::<16>::new();
+        TimeZone::new(-570, DstRule::None).write_offset(0, &mut buf).unwrap();
+        assert_eq!(buf, "UTC-09:30");
+    }
+}
@@ -0,0 +1,42 @@
+//! Firmware version as a number.
+//!
+//! DFU only takes a number for the firmware version, so `major.minor.patch` is reported as
+//! `major * 10000 + minor * 100 + patch`, 0.3.2 becoming 302.
+
+/// The number of a `major.minor.patch` version, anything after the patch such as a
+/// pre-release is ignored. Evaluated at compile time for the crate version.
+pub const fn number(version: &str) -> u32 {
+    let bytes = version.as_bytes();
+    let mut parts = [0u32; 3];
+    let mut part = 0;
+    let mut i = 0;
+    while i < bytes.len() && part < parts.len() {
+        match bytes[i] {
+            b'0'..=b'9' => parts[part] = parts[part] * 10 + (bytes[i] - b'0') as u32,
+            b'.' => part += 1,
+            _ => break,
+        }
+        i += 1;
+    }
+    parts[0] * 10000 + parts[1] * 100 + parts[2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers() {
+        assert_eq!(number("0.3.2"), 302);
+        assert_eq!(number("1.12.0"), 11200);
+        assert_eq!(number("2.0.1-rc.1"), 20001);
+        assert_eq!(number("1.2"), 10200);
+        assert_eq!(number(""), 0);
+    }
+
+    #[test]
+    fn const_evaluated() {
+        const NUMBER: u32 = number("0.4.0");
+        assert_eq!(NUMBER, 400);
+    }
+}
@@ -0,0 +1,87 @@
+//! Vibration patterns for the motor.
+//!
+//! A pattern is a list of durations in milliseconds that alternate between motor on and
+//! motor off, starting with on, played a number of times in a row.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Pattern {
+    /// Alternating on and off durations in milliseconds. Every repetition starts with the
+    /// motor on, so patterns that repeat should end with an off step.
+    pub steps: &'static [u16],
+    /// Times the steps are played, 0 plays them until cancelled.
+    pub repeat: u8,
+}
+
+impl Pattern {
+    /// Two short pulses for a new notification or navigation instruction.
+    pub const NOTIFICATION: Self = Self::new(&[120, 100, 120], 1);
+    /// Long pulses until dismissed.
+    pub const ALARM: Self = Self::new(&[500, 250, 500, 1000], 0);
+    /// A few long pulses when a timer runs out.
+    pub const TIMER_DONE: Self = Self::new(&[300, 200, 300, 800], 3);
+    /// A very short tap to acknowledge a touch.
+    pub const TICK: Self = Self::new(&[20], 1);
+    /// Three quick buzzes when something went wrong.
+    pub const ERROR: Self = Self::new(&[60, 60, 60, 60, 60], 1);
+
+    pub const fn new(steps: &'static [u16], repeat: u8) -> Self {
+        Self { steps, repeat }
+    }
+
+    /// Whether the motor is on and for how many milliseconds, step by step. Endless for
+    /// patterns that repeat until cancelled.
+    pub fn steps(&self) -> impl Iterator<Item = (bool, u16)> {
+        let repeat = match self.repeat {
+            0 => usize::MAX,
+            n => n as usize,
+        };
+        let steps = self.steps;
+        core::iter::repeat_n(steps, repeat)
+            .flat_map(|steps| [true, false].into_iter().cycle().zip(steps.iter().copied()))
+    }
+
+    /// Total playing time, `None` if it repeats until cancelled.
+    pub fn duration_ms(&self) -> Option<u32> {
+        let once: u32 = self.steps.iter().map(|&ms| ms as u32).sum();
+        (self.repeat > 0).then(|| once * self.repeat as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alternates_and_repeats() {
+        let pattern = Pattern::new(&[100, 50], 2);
+        let steps: Vec<_> = pattern.steps().collect();
+        assert_eq!(steps, [(true, 100), (false, 50), (true, 100), (false, 50)]);
+        assert_eq!(pattern.duration_ms(), Some(300));
+    }
+
+    #[test]
+    fn repetitions_start_on() {
+        let steps: Vec<_> = Pattern::new(&[10, 20, 30], 2).steps().collect();
+        assert_eq!(steps[3], (true, 10));
+    }
+
+    #[test]
+    fn repeat_until_cancelled() {
+        assert_eq!(Pattern::ALARM.duration_ms(), None);
+        assert_eq!(Pattern::ALARM.steps().take(100).count(), 100);
+    }
+
+    #[test]
+    fn presets_are_short() {
+        for pattern in [
+            Pattern::NOTIFICATION,
+            Pattern::TIMER_DONE,
+            Pattern::TICK,
+            Pattern::ERROR,
+        ] {
+            let duration = pattern.duration_ms().unwrap();
+            assert!(duration > 0 && duration <= 5000, "{pattern:?}");
+        }
+    }
+}
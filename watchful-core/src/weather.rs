@@ -0,0 +1,340 @@
+//! Parser for the InfiniTime SimpleWeather protocol.
+//!
+//! Companion apps such as GadgetBridge write one message at a time to the weather
+//! characteristic. All integers are little endian.
+//!
+//! Current weather (`type = 0`):
+//!
+//! | offset | size | field                                   |
+//! |--------|------|-----------------------------------------|
+//! | 0      | 1    | message type (0)                        |
+//! | 1      | 1    | version (0 or 1)                        |
+//! | 2      | 8    | timestamp, local seconds since the epoch|
+//! | 10     | 2    | temperature, 1/100 °C                   |
+//! | 12     | 2    | minimum temperature, 1/100 °C           |
+//! | 14     | 2    | maximum temperature, 1/100 °C           |
+//! | 16     | 32   | location, NUL padded UTF-8              |
+//! | 48     | 1    | icon                                    |
+//! | 49     | 2    | sunrise, minutes past midnight (v1)     |
+//! | 51     | 2    | sunset, minutes past midnight (v1)      |
+//!
+//! Forecast (`type = 1`):
+//!
+//! | offset    | size | field                         |
+//! |-----------|------|-------------------------------|
+//! | 0         | 1    | message type (1)              |
+//! | 1         | 1    | version (0)                   |
+//! | 2         | 8    | timestamp                     |
+//! | 10        | 1    | number of days (at most 5)    |
+//! | 11 + 5*n  | 2    | minimum temperature of day n  |
+//! | 13 + 5*n  | 2    | maximum temperature of day n  |
+//! | 15 + 5*n  | 1    | icon of day n                 |
+
+/// Largest message that can be written to the weather characteristic.
+pub const MAX_MESSAGE_SIZE: usize = 53;
+
+/// Number of forecast days the protocol can carry.
+pub const MAX_FORECAST_DAYS: usize = 5;
+
+const CURRENT_V0_SIZE: usize = 49;
+const CURRENT_V1_SIZE: usize = 53;
+const FORECAST_HEADER_SIZE: usize = 11;
+const FORECAST_DAY_SIZE: usize = 5;
+const LOCATION_SIZE: usize = 32;
+
+/// Anything colder or hotter than this is treated as a corrupt payload.
+const TEMPERATURE_LIMIT: i16 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The payload is shorter or longer than its message type requires.
+    InvalidLength,
+    UnknownMessageType(u8),
+    UnsupportedVersion(u8),
+    /// The location is not valid UTF-8.
+    InvalidLocation,
+    /// A temperature is outside of the plausible range.
+    InvalidTemperature,
+    /// The forecast announces more days than the protocol allows.
+    TooManyDays,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WeatherIcon {
+    Sun,
+    CloudsSun,
+    Clouds,
+    BrokenClouds,
+    CloudShowerHeavy,
+    CloudSunRain,
+    Thunderstorm,
+    Snow,
+    Smog,
+    Unknown,
+}
+
+impl From<u8> for WeatherIcon {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::Sun,
+            1 => Self::CloudsSun,
+            2 => Self::Clouds,
+            3 => Self::BrokenClouds,
+            4 => Self::CloudShowerHeavy,
+            5 => Self::CloudSunRain,
+            6 => Self::Thunderstorm,
+            7 => Self::Snow,
+            8 => Self::Smog,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Temperature in hundredths of a degree Celsius.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Temperature(pub i16);
+
+impl Temperature {
+    /// Whole degrees Celsius, rounded to the nearest degree.
+    pub fn celsius(&self) -> i16 {
+        let t = self.0 as i32;
+        let rounded = if t < 0 { (t - 50) / 100 } else { (t + 50) / 100 };
+        rounded as i16
+    }
+
+    fn parse(data: &[u8]) -> Result<Self, Error> {
+        let value = i16::from_le_bytes([data[0], data[1]]);
+        if value.unsigned_abs() > TEMPERATURE_LIMIT as u16 {
+            return Err(Error::InvalidTemperature);
+        }
+        Ok(Self(value))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CurrentWeather {
+    pub timestamp: u64,
+    pub temperature: Temperature,
+    pub min: Temperature,
+    pub max: Temperature,
+    pub location: heapless::String<LOCATION_SIZE>,
+    pub icon: WeatherIcon,
+    /// Sunrise and sunset in minutes past midnight, if the sender provided them.
+    pub sun: Option<(i16, i16)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ForecastDay {
+    pub min: Temperature,
+    pub max: Temperature,
+    pub icon: WeatherIcon,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Forecast {
+    pub timestamp: u64,
+    pub days: heapless::Vec<ForecastDay, MAX_FORECAST_DAYS>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WeatherMessage {
+    Current(CurrentWeather),
+    Forecast(Forecast),
+}
+
+impl WeatherMessage {
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        if data.len() < 2 {
+            return Err(Error::InvalidLength);
+        }
+        match data[0] {
+            0 => parse_current(data).map(Self::Current),
+            1 => parse_forecast(data).map(Self::Forecast),
+            other => Err(Error::UnknownMessageType(other)),
+        }
+    }
+}
+
+fn parse_timestamp(data: &[u8]) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&data[2..10]);
+    u64::from_le_bytes(bytes)
+}
+
+fn parse_current(data: &[u8]) -> Result<CurrentWeather, Error> {
+    let version = data[1];
+    let expected = match version {
+        0 => CURRENT_V0_SIZE,
+        1 => CURRENT_V1_SIZE,
+        other => return Err(Error::UnsupportedVersion(other)),
+    };
+    if data.len() != expected {
+        return Err(Error::InvalidLength);
+    }
+
+    let raw_location = &data[16..16 + LOCATION_SIZE];
+    let len = raw_location.iter().position(|b| *b == 0).unwrap_or(LOCATION_SIZE);
+    let location = core::str::from_utf8(&raw_location[..len]).map_err(|_| Error::InvalidLocation)?;
+    let mut loc = heapless::String::new();
+    // Cannot fail, the source is at most LOCATION_SIZE bytes.
+    let _ = loc.push_str(location);
+
+    let sun = if version == 1 {
+        let sunrise = i16::from_le_bytes([data[49], data[50]]);
+        let sunset = i16::from_le_bytes([data[51], data[52]]);
+        Some((sunrise, sunset))
+    } else {
+        None
+    };
+
+    Ok(CurrentWeather {
+        timestamp: parse_timestamp(data),
+        temperature: Temperature::parse(&data[10..12])?,
+        min: Temperature::parse(&data[12..14])?,
+        max: Temperature::parse(&data[14..16])?,
+        location: loc,
+        icon: data[48].into(),
+        sun,
+    })
+}
+
+fn parse_forecast(data: &[u8]) -> Result<Forecast, Error> {
+    if data[1] != 0 {
+        return Err(Error::UnsupportedVersion(data[1]));
+    }
+    if data.len() < FORECAST_HEADER_SIZE {
+        return Err(Error::InvalidLength);
+    }
+    let count = data[10] as usize;
+    if count > MAX_FORECAST_DAYS {
+        return Err(Error::TooManyDays);
+    }
+    if data.len() < FORECAST_HEADER_SIZE + count * FORECAST_DAY_SIZE {
+        return Err(Error::InvalidLength);
+    }
+
+    let mut days = heapless::Vec::new();
+    for day in data[FORECAST_HEADER_SIZE..].chunks_exact(FORECAST_DAY_SIZE).take(count) {
+        let day = ForecastDay {
+            min: Temperature::parse(&day[0..2])?,
+            max: Temperature::parse(&day[2..4])?,
+            icon: day[4].into(),
+        };
+        // Cannot fail, count is checked against the capacity above.
+        let _ = days.push(day);
+    }
+
+    Ok(Forecast {
+        timestamp: parse_timestamp(data),
+        days,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn current(version: u8, location: &str, temperature: i16) -> Vec<u8> {
+        let mut data = vec![0, version];
+        data.extend_from_slice(&1_700_000_000u64.to_le_bytes());
+        data.extend_from_slice(&temperature.to_le_bytes());
+        data.extend_from_slice(&(-350i16).to_le_bytes());
+        data.extend_from_slice(&2_175i16.to_le_bytes());
+        let mut loc = [0u8; 32];
+        loc[..location.len()].copy_from_slice(location.as_bytes());
+        data.extend_from_slice(&loc);
+        data.push(5);
+        if version == 1 {
+            data.extend_from_slice(&(6 * 60 + 12i16).to_le_bytes());
+            data.extend_from_slice(&(19 * 60 + 47i16).to_le_bytes());
+        }
+        data
+    }
+
+    fn forecast(days: &[(i16, i16, u8)]) -> Vec<u8> {
+        let mut data = vec![1, 0];
+        data.extend_from_slice(&1_700_000_000u64.to_le_bytes());
+        data.push(days.len() as u8);
+        for (min, max, icon) in days {
+            data.extend_from_slice(&min.to_le_bytes());
+            data.extend_from_slice(&max.to_le_bytes());
+            data.push(*icon);
+        }
+        data
+    }
+
+    #[test]
+    fn parse_current_v0() {
+        let msg = WeatherMessage::parse(&current(0, "Trondheim", 1_249)).unwrap();
+        let WeatherMessage::Current(weather) = msg else {
+            panic!("expected current weather");
+        };
+        assert_eq!(weather.timestamp, 1_700_000_000);
+        assert_eq!(weather.temperature.celsius(), 12);
+        assert_eq!(weather.min.celsius(), -4);
+        assert_eq!(weather.max.celsius(), 22);
+        assert_eq!(weather.location.as_str(), "Trondheim");
+        assert_eq!(weather.icon, WeatherIcon::CloudSunRain);
+        assert_eq!(weather.sun, None);
+    }
+
+    #[test]
+    fn parse_current_v1() {
+        let msg = WeatherMessage::parse(&current(1, "Oslo", 0)).unwrap();
+        let WeatherMessage::Current(weather) = msg else {
+            panic!("expected current weather");
+        };
+        assert_eq!(weather.sun, Some((372, 1187)));
+    }
+
+    #[test]
+    fn parse_forecast_days() {
+        let msg = WeatherMessage::parse(&forecast(&[(-200, 300, 7), (100, 1_500, 0), (0, 0, 200)])).unwrap();
+        let WeatherMessage::Forecast(forecast) = msg else {
+            panic!("expected forecast");
+        };
+        assert_eq!(forecast.days.len(), 3);
+        assert_eq!(forecast.days[0].icon, WeatherIcon::Snow);
+        assert_eq!(forecast.days[1].max.celsius(), 15);
+        assert_eq!(forecast.days[2].icon, WeatherIcon::Unknown);
+    }
+
+    #[test]
+    fn reject_invalid() {
+        assert_eq!(WeatherMessage::parse(&[]), Err(Error::InvalidLength));
+        assert_eq!(WeatherMessage::parse(&[3, 0]), Err(Error::UnknownMessageType(3)));
+        assert_eq!(
+            WeatherMessage::parse(&current(2, "Oslo", 0)),
+            Err(Error::UnsupportedVersion(2))
+        );
+
+        let mut truncated = current(0, "Oslo", 0);
+        truncated.pop();
+        assert_eq!(WeatherMessage::parse(&truncated), Err(Error::InvalidLength));
+
+        assert_eq!(
+            WeatherMessage::parse(&current(0, "Oslo", 12_000)),
+            Err(Error::InvalidTemperature)
+        );
+
+        let mut bad_location = current(0, "Oslo", 0);
+        bad_location[16] = 0xC3;
+        bad_location[17] = 0x28;
+        assert_eq!(WeatherMessage::parse(&bad_location), Err(Error::InvalidLocation));
+
+        let mut too_many = forecast(&[(0, 0, 0); 5]);
+        too_many[10] = 6;
+        assert_eq!(WeatherMessage::parse(&too_many), Err(Error::TooManyDays));
+
+        let mut short = forecast(&[(0, 0, 0); 2]);
+        short.pop();
+        assert_eq!(WeatherMessage::parse(&short), Err(Error::InvalidLength));
+    }
+}
@@ -0,0 +1,237 @@
+//! Recorded workout sessions.
+//!
+//! A finished session is stored, and exported over BLE, as one record. All values are
+//! little endian:
+//!
+//! | offset | size | field                                                    |
+//! |--------|------|----------------------------------------------------------|
+//! | 0      | 1    | format version, currently 1                              |
+//! | 1      | 1    | seconds between heart rate samples                       |
+//! | 2      | 8    | start, seconds since 1970-01-01 00:00 in local time      |
+//! | 10     | 4    | duration in seconds                                      |
+//! | 14     | 1    | lowest heart rate, beats per minute                      |
+//! | 15     | 1    | highest heart rate                                       |
+//! | 16     | 1    | average heart rate                                       |
+//! | 17     | 2    | number of samples                                        |
+//! | 19     | 1    | reserved, 0                                              |
+//! | 20     | n    | heart rate samples, one byte each, 0 when unknown        |
+//!
+//! The heart rates in the header are 0 when the rate was never known.
+
+const VERSION: u8 = 1;
+
+/// Seconds of heart rate averaged into each sample.
+pub const SAMPLE_INTERVAL: u8 = 10;
+/// Samples kept per session, four hours. Longer sessions keep counting the duration and
+/// the heart rate summary.
+pub const MAX_SAMPLES: usize = 4 * 3600 / SAMPLE_INTERVAL as usize;
+pub const HEADER_SIZE: usize = 20;
+pub const MAX_ENCODED_SIZE: usize = HEADER_SIZE + MAX_SAMPLES;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Summary {
+    /// Seconds since 1970 in local time.
+    pub start: i64,
+    /// Seconds.
+    pub duration: u32,
+    pub min: u8,
+    pub max: u8,
+    pub average: u8,
+    pub samples: u16,
+}
+
+impl Summary {
+    /// Decode the header of an encoded session.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() < HEADER_SIZE || data[0] != VERSION {
+            return None;
+        }
+        let samples = u16::from_le_bytes([data[17], data[18]]);
+        if data.len() < HEADER_SIZE + samples as usize {
+            return None;
+        }
+        let mut start = [0; 8];
+        start.copy_from_slice(&data[2..10]);
+        Some(Self {
+            start: i64::from_le_bytes(start),
+            duration: u32::from_le_bytes([data[10], data[11], data[12], data[13]]),
+            min: data[14],
+            max: data[15],
+            average: data[16],
+            samples,
+        })
+    }
+}
+
+/// The heart rate samples of an encoded session.
+pub fn samples(data: &[u8]) -> &[u8] {
+    match Summary::decode(data) {
+        Some(summary) => &data[HEADER_SIZE..HEADER_SIZE + summary.samples as usize],
+        None => &[],
+    }
+}
+
+/// Collects a session in its encoded form, ready to be saved.
+pub struct Recorder {
+    start: i64,
+    duration: u32,
+    min: u8,
+    max: u8,
+    sum: u32,
+    count: u32,
+    /// Seconds and known heart rates of the current sample interval.
+    interval_seconds: u8,
+    interval_sum: u32,
+    interval_count: u32,
+    samples: usize,
+    data: [u8; MAX_ENCODED_SIZE],
+}
+
+impl Recorder {
+    /// A session starting at `start`, seconds since 1970 in local time.
+    pub const fn new(start: i64) -> Self {
+        Self {
+            start,
+            duration: 0,
+            min: u8::MAX,
+            max: 0,
+            sum: 0,
+            count: 0,
+            interval_seconds: 0,
+            interval_sum: 0,
+            interval_count: 0,
+            samples: 0,
+            data: [0; MAX_ENCODED_SIZE],
+        }
+    }
+
+    /// Record one second of the session with the heart rate at the time, if known.
+    pub fn update(&mut self, bpm: Option<u8>) {
+        self.duration += 1;
+        if let Some(bpm) = bpm.filter(|b| *b > 0) {
+            self.min = self.min.min(bpm);
+            self.max = self.max.max(bpm);
+            self.sum += bpm as u32;
+            self.count += 1;
+            self.interval_sum += bpm as u32;
+            self.interval_count += 1;
+        }
+        self.interval_seconds += 1;
+        if self.interval_seconds == SAMPLE_INTERVAL {
+            self.push_sample();
+        }
+    }
+
+    pub fn summary(&self) -> Summary {
+        let known = self.count > 0;
+        Summary {
+            start: self.start,
+            duration: self.duration,
+            min: if known { self.min } else { 0 },
+            max: self.max,
+            average: if known { (self.sum / self.count) as u8 } else { 0 },
+            samples: self.samples as u16,
+        }
+    }
+
+    /// The session encoded as described in the [module documentation](self).
+    pub fn encode(&mut self) -> &[u8] {
+        if self.interval_count > 0 {
+            // Keep the heart rate of the last, incomplete interval
+            self.push_sample();
+        }
+        let summary = self.summary();
+        let header = &mut self.data[..HEADER_SIZE];
+        header[0] = VERSION;
+        header[1] = SAMPLE_INTERVAL;
+        header[2..10].copy_from_slice(&summary.start.to_le_bytes());
+        header[10..14].copy_from_slice(&summary.duration.to_le_bytes());
+        header[14] = summary.min;
+        header[15] = summary.max;
+        header[16] = summary.average;
+        header[17..19].copy_from_slice(&summary.samples.to_le_bytes());
+        header[19] = 0;
+        &self.data[..HEADER_SIZE + self.samples]
+    }
+
+    fn push_sample(&mut self) {
+        let sample = match self.interval_count {
+            0 => 0,
+            n => (self.interval_sum / n) as u8,
+        };
+        self.interval_seconds = 0;
+        self.interval_sum = 0;
+        self.interval_count = 0;
+        if self.samples < MAX_SAMPLES {
+            self.data[HEADER_SIZE + self.samples] = sample;
+            self.samples += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session() {
+        let mut recorder = Recorder::new(1_700_000_000);
+        // Half a minute before the heart rate is known, then rising from 100 to 159
+        for _ in 0..30 {
+            recorder.update(None);
+        }
+        for s in 0..60 {
+            recorder.update(Some(100 + s));
+        }
+        for _ in 0..5 {
+            recorder.update(Some(120));
+        }
+
+        let summary = recorder.summary();
+        assert_eq!(summary.duration, 95);
+        assert_eq!((summary.min, summary.max), (100, 159));
+        // (60 * 100 + 59 * 60 / 2 + 5 * 120) / 65
+        assert_eq!(summary.average, 128);
+
+        let data = recorder.encode();
+        assert_eq!(data.len(), HEADER_SIZE + 10);
+        assert_eq!(Summary::decode(data), Some(Summary { samples: 10, ..summary }));
+        assert_eq!(samples(data), &[0, 0, 0, 104, 114, 124, 134, 144, 154, 120]);
+    }
+
+    #[test]
+    fn without_heart_rate() {
+        let mut recorder = Recorder::new(0);
+        for _ in 0..25 {
+            recorder.update(None);
+        }
+        let summary = Summary::decode(recorder.encode()).unwrap();
+        assert_eq!((summary.min, summary.max, summary.average), (0, 0, 0));
+        assert_eq!(summary.samples, 2);
+    }
+
+    #[test]
+    fn long_session() {
+        let mut recorder = Recorder::new(0);
+        for _ in 0..5 * 3600 {
+            recorder.update(Some(90));
+        }
+        let data = recorder.encode();
+        assert_eq!(data.len(), MAX_ENCODED_SIZE);
+        let summary = Summary::decode(data).unwrap();
+        assert_eq!(summary.duration, 5 * 3600);
+        assert_eq!(summary.samples as usize, MAX_SAMPLES);
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(Summary::decode(&[]), None);
+        let mut data = [0; HEADER_SIZE];
+        assert_eq!(Summary::decode(&data), None);
+        data[0] = VERSION;
+        data[17] = 1;
+        assert_eq!(Summary::decode(&data), None);
+        assert_eq!(samples(&data), &[] as &[u8]);
+    }
+}
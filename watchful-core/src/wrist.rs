@@ -0,0 +1,134 @@
+//! Wrist raise detection.
+//!
+//! A raise is the watch turning from a pose where the face points sideways or down, into
+//! the viewing pose with the face up towards the eyes, and then staying still there for a
+//! moment. The axes follow the PineTime: with the watch lying flat on its back, `z` reads
+//! -1 g.
+
+use crate::motion::{Sample, SAMPLE_RATE};
+
+/// How far the face may be tilted sideways in the viewing pose, in milli-g on `x`.
+const MAX_SIDEWAYS: i16 = 450;
+/// The face must point at least this much upwards in the viewing pose.
+const MIN_FACE_UP: i16 = -600;
+/// How much `z` must have dropped during the last second, i.e. how far the wrist turned.
+const MIN_TURN: i16 = 450;
+/// Samples the wrist has to stay still in the viewing pose.
+const HOLD: u32 = 4;
+/// Largest deviation from 1 g that counts as holding still.
+const MAX_MOTION: u32 = 200;
+/// Samples the wrist has to be out of the viewing pose before a raise counts, so the
+/// swinging of an arm while walking does not.
+const MIN_AWAY: u32 = SAMPLE_RATE / 2;
+const WINDOW: usize = SAMPLE_RATE as usize;
+
+pub struct RaiseDetector {
+    /// `z` over the last second.
+    history: [i16; WINDOW],
+    pos: usize,
+    /// Consecutive still samples in the viewing pose.
+    held: u32,
+    /// Consecutive samples out of the viewing pose.
+    away: u32,
+    /// Set when the wrist has been away from the viewing pose, so every raise is only
+    /// reported once.
+    armed: bool,
+}
+
+impl RaiseDetector {
+    pub const fn new() -> Self {
+        Self {
+            history: [i16::MIN; WINDOW],
+            pos: 0,
+            held: 0,
+            away: 0,
+            armed: false,
+        }
+    }
+
+    /// Feed one sample taken at [`SAMPLE_RATE`], returning `true` when the wrist was raised.
+    pub fn update(&mut self, sample: Sample) -> bool {
+        let turned = self.history.iter().max().copied().unwrap_or(i16::MIN) as i32 - sample.z as i32;
+        self.history[self.pos] = sample.z;
+        self.pos = (self.pos + 1) % WINDOW;
+
+        let viewing = sample.x.abs() < MAX_SIDEWAYS && sample.z < MIN_FACE_UP;
+        if !viewing {
+            self.held = 0;
+            self.away = self.away.saturating_add(1);
+            if self.away >= MIN_AWAY {
+                self.armed = true;
+            }
+            return false;
+        }
+        self.away = 0;
+
+        if sample.magnitude().abs_diff(1000) > MAX_MOTION {
+            self.held = 0;
+            return false;
+        }
+
+        // The turn has to happen right before the wrist settles
+        if self.held == 0 && turned < MIN_TURN as i32 {
+            return false;
+        }
+        self.held += 1;
+        if self.armed && self.held >= HOLD {
+            self.armed = false;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Forget the recent motion, e.g. after samples were dropped.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl Default for RaiseDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::motion::tests::trace;
+
+    fn raises(csv: &str) -> usize {
+        let mut detector = RaiseDetector::new();
+        trace(csv).into_iter().filter(|s| detector.update(*s)).count()
+    }
+
+    #[test]
+    fn raising_the_wrist() {
+        assert_eq!(raises(include_str!("../fixtures/motion/raise.csv")), 4);
+    }
+
+    #[test]
+    fn walking_does_not_wake() {
+        assert_eq!(raises(include_str!("../fixtures/motion/walk.csv")), 0);
+        assert_eq!(raises(include_str!("../fixtures/motion/walk_pause_walk.csv")), 0);
+    }
+
+    #[test]
+    fn typing_does_not_wake() {
+        assert_eq!(raises(include_str!("../fixtures/motion/desk.csv")), 0);
+    }
+
+    #[test]
+    fn slow_tilt_does_not_wake() {
+        let mut detector = RaiseDetector::new();
+        let mut woke = false;
+        // Turning the watch face up over ten seconds
+        for i in 0..=(10 * SAMPLE_RATE as i32) {
+            let z = -100 - 900 * i / (10 * SAMPLE_RATE as i32);
+            let y = -((1000 * 1000 - z * z) as f32).sqrt() as i16;
+            woke |= detector.update(Sample::new(0, y, z as i16));
+        }
+        assert!(!woke);
+    }
+}
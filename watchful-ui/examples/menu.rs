@@ -5,6 +5,14 @@ use embedded_graphics::prelude::*;
 use embedded_graphics::primitives::{Circle, Line, PrimitiveStyle, Rectangle};
 use embedded_graphics::text::Text;
 use embedded_graphics_simulator::{BinaryColorTheme, OutputSettingsBuilder, SimulatorDisplay, Window};
+use watchful_core::battery::ChargeState;
+use watchful_core::battery_history;
+use watchful_core::heart_history::{Alert, History};
+use watchful_core::ppg::Estimate;
+use watchful_core::sleep::NightSummary;
+use watchful_core::timezone::TimeZone;
+use watchful_core::weather::{Forecast, ForecastDay, Temperature, WeatherIcon};
+use watchful_core::workout::Summary;
 use watchful_ui::*;
 
 fn main() -> Result<(), core::convert::Infallible> {
@@ -38,8 +46,129 @@ fn main() -> Result<(), core::convert::Infallible> {
 
     let mut display = SimulatorDisplay::<Rgb>::new(Size::new(240, 240));
     let t = time::OffsetDateTime::now_utc();
-    let view = TimeView::new(time::PrimitiveDateTime::new(t.date(), t.time()), 5, false);
+    let view = TimeView::new(
+        Some(time::PrimitiveDateTime::new(t.date(), t.time())),
+        5,
+        false,
+        Some(WeatherComplication::new(12, WeatherIcon::CloudsSun)),
+        Some(WorldClockComplication::new(
+            "Tokyo",
+            time::PrimitiveDateTime::new(t.date(), t.time()) + time::Duration::hours(9),
+        )),
+        Some(4312),
+    );
     view.draw(&mut display)?;
     Window::new("Time", &output_settings).show_static(&display);
+
+    let mut display = SimulatorDisplay::<Rgb>::new(Size::new(240, 240));
+    let mut location = heapless::String::new();
+    location.push_str("Trondheim").unwrap();
+    let mut days = heapless::Vec::new();
+    for (min, max, icon) in [(-2, 4, WeatherIcon::Snow), (1, 7, WeatherIcon::Clouds), (3, 11, WeatherIcon::Sun)] {
+        days.push(ForecastDay {
+            min: Temperature(min * 100),
+            max: Temperature(max * 100),
+            icon,
+        })
+        .unwrap();
+    }
+    let view = WeatherView::new(
+        Some(CurrentConditions {
+            location,
+            temperature: 2,
+            min: -2,
+            max: 4,
+            icon: WeatherIcon::Snow,
+            updated: time::PrimitiveDateTime::new(t.date(), t.time()),
+        }),
+        Some(Forecast {
+            timestamp: t.unix_timestamp() as u64,
+            days,
+        }),
+    );
+    view.draw(&mut display)?;
+    Window::new("Weather", &output_settings).show_static(&display);
+
+    let mut display = SimulatorDisplay::<Rgb>::new(Size::new(240, 240));
+    StepsView::new(6240, 10_000).draw(&mut display)?;
+    Window::new("Steps", &output_settings).show_static(&display);
+
+    let mut display = SimulatorDisplay::<Rgb>::new(Size::new(240, 240));
+    let nights: Vec<NightSummary> = [452u16, 398, 501, 475]
+        .iter()
+        .map(|&asleep| NightSummary {
+            day: 0,
+            start: 22 * 60 + 30,
+            minutes: 540,
+            asleep,
+            onset: 25,
+            wake: 505,
+            awakenings: 1,
+            hypnogram: [0b1111_1110; 20],
+        })
+        .collect();
+    SleepView::new(&nights).draw(&mut display)?;
+    Window::new("Sleep", &output_settings).show_static(&display);
+
+    let mut display = SimulatorDisplay::<Rgb>::new(Size::new(240, 240));
+    let hr = Estimate {
+        bpm: 132,
+        confidence: 90,
+    };
+    WorkoutView::new(Some(hr), time::Duration::seconds(1563)).draw(&mut display)?;
+    Window::new("Workout", &output_settings).show_static(&display);
+
+    let mut display = SimulatorDisplay::<Rgb>::new(Size::new(240, 240));
+    let sessions: Vec<Summary> = [
+        (1_760_900_000, 2710, 141),
+        (1_760_720_000, 1820, 0),
+        (1_760_550_000, 3905, 128),
+    ]
+    .iter()
+    .map(|&(start, duration, average)| Summary {
+        start,
+        duration,
+        min: average / 2,
+        max: average + average / 4,
+        average,
+        samples: (duration / 10) as u16,
+    })
+    .collect();
+    WorkoutHistoryView::new(&sessions, TimeZone::UTC).draw(&mut display)?;
+    Window::new("Workouts", &output_settings).show_static(&display);
+
+    let mut display = SimulatorDisplay::<Rgb>::new(Size::new(240, 240));
+    let now = 1_760_900_000;
+    let mut history = History::new();
+    for i in 0..48 {
+        let bpm = if (14..30).contains(&i) { 52 } else { 64 + (i % 7) as u8 * 3 };
+        history.record(now - (48 - i) * 30 * 60, bpm);
+    }
+    history.record(now, 104);
+    HeartHistoryView::new(&history, now, 40, 100, Some(Alert::High(104))).draw(&mut display)?;
+    Window::new("Heart", &output_settings).show_static(&display);
+
+    let mut display = SimulatorDisplay::<Rgb>::new(Size::new(240, 240));
+    let mut battery = battery_history::History::new();
+    for i in 0..96 {
+        let (percent, state) = if i < 20 {
+            (40 + i as u8 * 3, ChargeState::Charging)
+        } else {
+            (100 - (i - 20) as u8, ChargeState::Discharging)
+        };
+        let time = now - (96 - i) * 30 * 60;
+        battery.record(&battery_history::Sample {
+            time,
+            millivolts: 3500 + percent as u16 * 6,
+            percent,
+            state,
+        });
+    }
+    BatteryHistoryView::new(&battery, now, 25, false, Some(12)).draw(&mut display)?;
+    Window::new("Battery", &output_settings).show_static(&display);
+
+    let mut display = SimulatorDisplay::<Rgb>::new(Size::new(240, 240));
+    BrightnessView::new(60).draw(&mut display)?;
+    Window::new("Brightness", &output_settings).show_static(&display);
     Ok(())
 }
@@ -5,7 +5,7 @@ use core::fmt::Write as _;
 use embedded_graphics::image::Image;
 use embedded_graphics::pixelcolor::Rgb565 as Rgb;
 use embedded_graphics::prelude::{DrawTarget, *};
-use embedded_graphics::primitives::{PrimitiveStyleBuilder, Rectangle};
+use embedded_graphics::primitives::{Circle, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle};
 use embedded_graphics::text::{Text, TextStyleBuilder};
 use embedded_iconoir::prelude::*;
 use embedded_layout::layout::linear::{spacing, LinearLayout};
@@ -14,6 +14,15 @@ use embedded_text::style::TextBoxStyleBuilder;
 use embedded_text::TextBox;
 use u8g2_fonts::{fonts, U8g2TextStyle};
 use time::PrimitiveDateTime;
+use watchful_core::battery_history;
+use watchful_core::calendar::Field;
+use watchful_core::heart_history::{Alert, History, SLOTS};
+use watchful_core::navigation::Direction;
+use watchful_core::ppg::{Estimate, MIN_CONFIDENCE};
+use watchful_core::sleep::NightSummary;
+use watchful_core::timezone::{DstRule, TimeZone};
+use watchful_core::weather::{Forecast, WeatherIcon};
+use watchful_core::workout;
 
 const WIDTH: u32 = 240;
 const HEIGHT: u32 = 240;
@@ -65,24 +74,41 @@ pub enum TouchGesture {
 
 #[derive(PartialEq)]
 pub struct TimeView {
-    pub time: time::PrimitiveDateTime,
+    /// `None` while the time is not known.
+    pub time: Option<time::PrimitiveDateTime>,
     pub battery_level: u32,
     pub battery_charging: bool,
+    pub weather: Option<WeatherComplication>,
+    pub world_clock: Option<WorldClockComplication>,
+    pub steps: Option<u32>,
 }
 
 impl TimeView {
-    pub fn new(time: time::PrimitiveDateTime, battery_level: u32, battery_charging: bool) -> Self {
+    pub fn new(
+        time: Option<time::PrimitiveDateTime>,
+        battery_level: u32,
+        battery_charging: bool,
+        weather: Option<WeatherComplication>,
+        world_clock: Option<WorldClockComplication>,
+        steps: Option<u32>,
+    ) -> Self {
         Self {
             time,
             battery_level,
             battery_charging,
+            weather,
+            world_clock,
+            steps,
         }
     }
     pub fn draw<D: DrawTarget<Color = Rgb>>(&self, display: &mut D) -> Result<(), D::Error> {
         display.clear(Rgb::BLACK)?;
 
         let mut buf: heapless::String<16> = heapless::String::new();
-        write!(buf, "{:02}:{:02}", self.time.hour(), self.time.minute()).unwrap();
+        match self.time {
+            Some(time) => write!(buf, "{:02}:{:02}", time.hour(), time.minute()).unwrap(),
+            None => buf.push_str("--:--").unwrap(),
+        }
         let hm = Text::with_text_style(
             &buf,
             display.bounding_box().center(),
@@ -94,9 +120,11 @@ impl TimeView {
         );
 
         let mut buf: heapless::String<16> = heapless::String::new();
-        write!(buf, "{}", self.time.weekday()).unwrap();
-        buf.truncate(3);
-        write!(buf, " {}", self.time.day()).unwrap();
+        if let Some(time) = self.time {
+            write!(buf, "{}", time.weekday()).unwrap();
+            buf.truncate(3);
+            write!(buf, " {}", time.day()).unwrap();
+        }
         let date = Text::with_text_style(
             &buf,
             display.bounding_box().center(),
@@ -164,28 +192,794 @@ impl TimeView {
             .translate(Point::new(-40, 5))
             .draw(display)?;
 
+        if let Some(weather) = &self.weather {
+            weather.draw(display)?;
+        }
+
+        if let Some(world_clock) = &self.world_clock {
+            world_clock.draw(display)?;
+        }
+
+        if let Some(steps) = self.steps {
+            let mut buf: heapless::String<16> = heapless::String::new();
+            write!(buf, "{} steps", steps).unwrap();
+            Text::with_text_style(
+                &buf,
+                Point::new(WIDTH as i32 / 2, HEIGHT as i32 - 12),
+                perc_text_style(Rgb::CSS_LIGHT_STEEL_BLUE),
+                TextStyleBuilder::new()
+                    .alignment(embedded_graphics::text::Alignment::Center)
+                    .baseline(embedded_graphics::text::Baseline::Alphabetic)
+                    .build(),
+            )
+            .draw(display)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Current temperature and conditions shown in the top left corner of the watch face.
+#[derive(PartialEq, Clone, Copy)]
+pub struct WeatherComplication {
+    pub temperature: i16,
+    pub icon: WeatherIcon,
+}
+
+impl WeatherComplication {
+    pub fn new(temperature: i16, icon: WeatherIcon) -> Self {
+        Self { temperature, icon }
+    }
+
+    pub fn draw<D: DrawTarget<Color = Rgb>>(&self, display: &mut D) -> Result<(), D::Error> {
+        let color = Rgb::CSS_LIGHT_STEEL_BLUE;
+        draw_weather_icon_small(display, self.icon, Point::new(5, 5), color)?;
+
+        let mut buf: heapless::String<8> = heapless::String::new();
+        write!(buf, "{}°", self.temperature).unwrap();
+        Text::with_text_style(
+            &buf,
+            Point::new(34, 24),
+            perc_text_style(color),
+            TextStyleBuilder::new()
+                .alignment(embedded_graphics::text::Alignment::Left)
+                .baseline(embedded_graphics::text::Baseline::Alphabetic)
+                .build(),
+        )
+        .draw(display)?;
+        Ok(())
+    }
+}
+
+/// The time in a second time zone, shown above the step count on the watch face.
+#[derive(PartialEq, Clone, Copy)]
+pub struct WorldClockComplication {
+    pub name: &'static str,
+    pub time: time::PrimitiveDateTime,
+}
+
+impl WorldClockComplication {
+    pub fn new(name: &'static str, time: time::PrimitiveDateTime) -> Self {
+        Self { name, time }
+    }
+
+    pub fn draw<D: DrawTarget<Color = Rgb>>(&self, display: &mut D) -> Result<(), D::Error> {
+        let mut buf: heapless::String<24> = heapless::String::new();
+        write!(buf, "{} {:02}:{:02}", self.name, self.time.hour(), self.time.minute()).unwrap();
+        Text::with_text_style(
+            &buf,
+            Point::new(WIDTH as i32 / 2, HEIGHT as i32 - 44),
+            text_text_style(Rgb::CSS_LIGHT_STEEL_BLUE),
+            TextStyleBuilder::new()
+                .alignment(embedded_graphics::text::Alignment::Center)
+                .baseline(embedded_graphics::text::Baseline::Alphabetic)
+                .build(),
+        )
+        .draw(display)?;
+        Ok(())
+    }
+}
+
+fn draw_weather_icon_small<D: DrawTarget<Color = Rgb>>(
+    display: &mut D,
+    icon: WeatherIcon,
+    pos: Point,
+    color: Rgb,
+) -> Result<(), D::Error> {
+    use icons::size24px::weather;
+    match icon {
+        WeatherIcon::Sun => Image::new(&weather::SunLight::new(color), pos).draw(display),
+        WeatherIcon::CloudsSun => Image::new(&weather::CloudSunny::new(color), pos).draw(display),
+        WeatherIcon::Clouds | WeatherIcon::BrokenClouds => Image::new(&weather::Cloud::new(color), pos).draw(display),
+        WeatherIcon::CloudShowerHeavy => Image::new(&weather::HeavyRain::new(color), pos).draw(display),
+        WeatherIcon::CloudSunRain => Image::new(&weather::Rain::new(color), pos).draw(display),
+        WeatherIcon::Thunderstorm => Image::new(&weather::Thunderstorm::new(color), pos).draw(display),
+        WeatherIcon::Snow => Image::new(&weather::SnowFlake::new(color), pos).draw(display),
+        WeatherIcon::Smog => Image::new(&weather::Fog::new(color), pos).draw(display),
+        WeatherIcon::Unknown => Ok(()),
+    }
+}
+
+fn draw_weather_icon_large<D: DrawTarget<Color = Rgb>>(
+    display: &mut D,
+    icon: WeatherIcon,
+    pos: Point,
+    color: Rgb,
+) -> Result<(), D::Error> {
+    use icons::size48px::weather;
+    match icon {
+        WeatherIcon::Sun => Image::new(&weather::SunLight::new(color), pos).draw(display),
+        WeatherIcon::CloudsSun => Image::new(&weather::CloudSunny::new(color), pos).draw(display),
+        WeatherIcon::Clouds | WeatherIcon::BrokenClouds => Image::new(&weather::Cloud::new(color), pos).draw(display),
+        WeatherIcon::CloudShowerHeavy => Image::new(&weather::HeavyRain::new(color), pos).draw(display),
+        WeatherIcon::CloudSunRain => Image::new(&weather::Rain::new(color), pos).draw(display),
+        WeatherIcon::Thunderstorm => Image::new(&weather::Thunderstorm::new(color), pos).draw(display),
+        WeatherIcon::Snow => Image::new(&weather::SnowFlake::new(color), pos).draw(display),
+        WeatherIcon::Smog => Image::new(&weather::Fog::new(color), pos).draw(display),
+        WeatherIcon::Unknown => Ok(()),
+    }
+}
+
+/// Current conditions at the top and the forecast for the coming days below.
+#[derive(PartialEq, Clone)]
+pub struct WeatherView {
+    pub current: Option<CurrentConditions>,
+    pub forecast: Option<Forecast>,
+}
+
+#[derive(PartialEq, Clone)]
+pub struct CurrentConditions {
+    pub location: heapless::String<32>,
+    pub temperature: i16,
+    pub min: i16,
+    pub max: i16,
+    pub icon: WeatherIcon,
+    /// When the report was received by the watch.
+    pub updated: PrimitiveDateTime,
+}
+
+impl WeatherView {
+    pub fn new(current: Option<CurrentConditions>, forecast: Option<Forecast>) -> Self {
+        Self { current, forecast }
+    }
+
+    pub fn draw<D: DrawTarget<Color = Rgb>>(&self, display: &mut D) -> Result<(), D::Error> {
+        display.clear(Rgb::BLACK)?;
+        let color = Rgb::CSS_LIGHT_STEEL_BLUE;
+        let left = TextStyleBuilder::new()
+            .alignment(embedded_graphics::text::Alignment::Left)
+            .baseline(embedded_graphics::text::Baseline::Alphabetic)
+            .build();
+
+        let Some(current) = &self.current else {
+            Text::with_text_style(
+                "No weather data",
+                display.bounding_box().center(),
+                text_text_style(color),
+                TextStyleBuilder::new()
+                    .alignment(embedded_graphics::text::Alignment::Center)
+                    .build(),
+            )
+            .draw(display)?;
+            return Ok(());
+        };
+
+        Text::with_text_style(&current.location, Point::new(10, 24), date_text_style(color), left).draw(display)?;
+        draw_weather_icon_large(display, current.icon, Point::new(10, 36), color)?;
+
+        let mut buf: heapless::String<16> = heapless::String::new();
+        write!(buf, "{}°", current.temperature).unwrap();
+        Text::with_text_style(&buf, Point::new(70, 76), menu_text_style(Rgb::CSS_CORNSILK), left).draw(display)?;
+
+        let mut buf: heapless::String<16> = heapless::String::new();
+        write!(buf, "{}° / {}°", current.min, current.max).unwrap();
+        Text::with_text_style(&buf, Point::new(150, 76), text_text_style(color), left).draw(display)?;
+
+        if let Some(forecast) = &self.forecast {
+            let start = time::OffsetDateTime::from_unix_timestamp(forecast.timestamp as i64).ok();
+            for (i, day) in forecast.days.iter().enumerate().take(4) {
+                let y = 100 + i as i32 * 30;
+                let mut buf: heapless::String<16> = heapless::String::new();
+                if let Some(start) = start.and_then(|s| s.checked_add(time::Duration::days(i as i64))) {
+                    write!(buf, "{}", start.weekday()).unwrap();
+                    buf.truncate(3);
+                }
+                Text::with_text_style(&buf, Point::new(10, y + 20), text_text_style(color), left).draw(display)?;
+                draw_weather_icon_small(display, day.icon, Point::new(60, y), color)?;
+
+                let mut buf: heapless::String<16> = heapless::String::new();
+                write!(buf, "{}° / {}°", day.min.celsius(), day.max.celsius()).unwrap();
+                Text::with_text_style(&buf, Point::new(100, y + 20), text_text_style(color), left).draw(display)?;
+            }
+        }
+
+        let mut buf: heapless::String<16> = heapless::String::new();
+        write!(buf, "{:02}:{:02}", current.updated.hour(), current.updated.minute()).unwrap();
+        Text::with_text_style(
+            &buf,
+            Point::new(WIDTH as i32 - 10, 24),
+            text_text_style(Rgb::CSS_GRAY),
+            TextStyleBuilder::new()
+                .alignment(embedded_graphics::text::Alignment::Right)
+                .baseline(embedded_graphics::text::Baseline::Alphabetic)
+                .build(),
+        )
+        .draw(display)?;
+
+        Ok(())
+    }
+}
+
+/// Next manoeuvre with the distance to it and the instruction text.
+#[derive(PartialEq, Clone)]
+pub struct NavigationView<'a> {
+    pub direction: Direction,
+    pub distance: &'a str,
+    pub narrative: &'a str,
+    pub progress: u8,
+}
+
+impl<'a> NavigationView<'a> {
+    pub fn new(direction: Direction, distance: &'a str, narrative: &'a str, progress: u8) -> Self {
+        Self {
+            direction,
+            distance,
+            narrative,
+            progress,
+        }
+    }
+
+    pub fn draw<D: DrawTarget<Color = Rgb>>(&self, display: &mut D) -> Result<(), D::Error> {
+        display.clear(Rgb::BLACK)?;
+        let color = Rgb::CSS_DARK_CYAN;
+
+        let pos = Point::new((WIDTH as i32 - 48) / 2, 36);
+        {
+            use icons::size48px::navigation;
+            match self.direction {
+                Direction::Straight => Image::new(&navigation::ArrowUp::new(color), pos).draw(display)?,
+                Direction::SlightLeft => Image::new(&navigation::ArrowTl::new(color), pos).draw(display)?,
+                Direction::Left | Direction::SharpLeft => {
+                    Image::new(&navigation::ArrowLeft::new(color), pos).draw(display)?
+                }
+                Direction::SlightRight => Image::new(&navigation::ArrowTr::new(color), pos).draw(display)?,
+                Direction::Right | Direction::SharpRight => {
+                    Image::new(&navigation::ArrowRight::new(color), pos).draw(display)?
+                }
+                Direction::UTurn => Image::new(&navigation::ArrowDown::new(color), pos).draw(display)?,
+                Direction::Roundabout => {
+                    Circle::new(pos - Point::new(12, 12), 72)
+                        .into_styled(PrimitiveStyle::with_stroke(color, 4))
+                        .draw(display)?;
+                    Image::new(&navigation::ArrowUp::new(color), pos).draw(display)?
+                }
+                Direction::Arrive => Circle::new(pos + Point::new(4, 4), 40)
+                    .into_styled(PrimitiveStyle::with_fill(color))
+                    .draw(display)?,
+                Direction::Unknown => {}
+            }
+        }
+
+        Text::with_text_style(
+            self.distance,
+            Point::new(WIDTH as i32 / 2, 128),
+            menu_text_style(Rgb::CSS_CORNSILK),
+            TextStyleBuilder::new()
+                .alignment(embedded_graphics::text::Alignment::Center)
+                .baseline(embedded_graphics::text::Baseline::Alphabetic)
+                .build(),
+        )
+        .draw(display)?;
+
+        let bounds = Rectangle::new(Point::new(10, 138), Size::new(WIDTH - 20, 80));
+        let textbox_style = TextBoxStyleBuilder::new()
+            .height_mode(embedded_text::style::HeightMode::Exact(
+                embedded_text::style::VerticalOverdraw::Hidden,
+            ))
+            .alignment(embedded_text::alignment::HorizontalAlignment::Center)
+            .build();
+        TextBox::with_textbox_style(self.narrative, bounds, text_text_style(color), textbox_style).draw(display)?;
+
+        let width = (WIDTH - 20) * self.progress.min(100) as u32 / 100;
+        Rectangle::new(Point::new(10, HEIGHT as i32 - 12), Size::new(WIDTH - 20, 6))
+            .into_styled(PrimitiveStyle::with_stroke(color, 1))
+            .draw(display)?;
+        Rectangle::new(Point::new(10, HEIGHT as i32 - 12), Size::new(width, 6))
+            .into_styled(PrimitiveStyle::with_fill(color))
+            .draw(display)?;
+
+        Ok(())
+    }
+}
+
+/// Steps taken today and progress towards the daily goal.
+#[derive(PartialEq, Clone, Copy)]
+pub struct StepsView {
+    pub steps: u32,
+    pub goal: u32,
+}
+
+impl StepsView {
+    pub fn new(steps: u32, goal: u32) -> Self {
+        Self { steps, goal }
+    }
+
+    pub fn draw<D: DrawTarget<Color = Rgb>>(&self, display: &mut D) -> Result<(), D::Error> {
+        display.clear(Rgb::BLACK)?;
+        let color = if self.steps >= self.goal {
+            Rgb::CSS_GREEN
+        } else {
+            Rgb::CSS_DARK_CYAN
+        };
+
+        let mut buf: heapless::String<16> = heapless::String::new();
+        write!(buf, "{}", self.steps).unwrap();
+        Text::with_text_style(
+            &buf,
+            Point::new(WIDTH as i32 / 2, 130),
+            watch_text_style(color),
+            TextStyleBuilder::new()
+                .alignment(embedded_graphics::text::Alignment::Center)
+                .baseline(embedded_graphics::text::Baseline::Alphabetic)
+                .build(),
+        )
+        .draw(display)?;
+
+        let mut buf: heapless::String<16> = heapless::String::new();
+        write!(buf, "of {} steps", self.goal).unwrap();
+        Text::with_text_style(
+            &buf,
+            Point::new(WIDTH as i32 / 2, 170),
+            date_text_style(Rgb::CSS_CORNSILK),
+            TextStyleBuilder::new()
+                .alignment(embedded_graphics::text::Alignment::Center)
+                .baseline(embedded_graphics::text::Baseline::Alphabetic)
+                .build(),
+        )
+        .draw(display)?;
+
+        let progress = (self.steps as u64 * 100 / self.goal.max(1) as u64).min(100) as u32;
+        let width = (WIDTH - 40) * progress / 100;
+        Rectangle::new(Point::new(20, 195), Size::new(WIDTH - 40, 12))
+            .into_styled(PrimitiveStyle::with_stroke(color, 1))
+            .draw(display)?;
+        Rectangle::new(Point::new(20, 195), Size::new(width, 12))
+            .into_styled(PrimitiveStyle::with_fill(color))
+            .draw(display)?;
+
+        Ok(())
+    }
+}
+
+/// Backlight brightness in percent, set by tapping along the slider or swiping left and right.
+#[derive(PartialEq, Clone, Copy)]
+pub struct BrightnessView {
+    pub level: u8,
+}
+
+impl BrightnessView {
+    /// Slider track, tapping anywhere within its height picks the level under the finger.
+    const TRACK: Rectangle = Rectangle::new(Point::new(20, 140), Size::new(WIDTH - 40, 24));
+    /// Level change for a swipe.
+    const STEP: u8 = 10;
+    /// The backlight never goes fully dark while the screen is on.
+    const MIN: u8 = 5;
+
+    pub fn new(level: u8) -> Self {
+        Self {
+            level: level.clamp(Self::MIN, 100),
+        }
+    }
+
+    pub fn draw<D: DrawTarget<Color = Rgb>>(&self, display: &mut D) -> Result<(), D::Error> {
+        display.clear(Rgb::BLACK)?;
+        let centered = TextStyleBuilder::new()
+            .alignment(embedded_graphics::text::Alignment::Center)
+            .baseline(embedded_graphics::text::Baseline::Alphabetic)
+            .build();
+
+        Text::with_text_style(
+            "Brightness",
+            Point::new(WIDTH as i32 / 2, 50),
+            date_text_style(Rgb::CSS_CORNSILK),
+            centered,
+        )
+        .draw(display)?;
+
+        let mut buf: heapless::String<8> = heapless::String::new();
+        write!(buf, "{}%", self.level).unwrap();
+        Text::with_text_style(
+            &buf,
+            Point::new(WIDTH as i32 / 2, 115),
+            menu_text_style(Rgb::CSS_CORNSILK),
+            centered,
+        )
+        .draw(display)?;
+
+        let track = Self::TRACK;
+        let filled = track.size.width * self.level as u32 / 100;
+        track
+            .into_styled(PrimitiveStyle::with_stroke(Rgb::CSS_DARK_CYAN, 1))
+            .draw(display)?;
+        Rectangle::new(track.top_left, Size::new(filled, track.size.height))
+            .into_styled(PrimitiveStyle::with_fill(Rgb::CSS_DARK_CYAN))
+            .draw(display)?;
+        let knob = track.top_left + Point::new(filled as i32, track.size.height as i32 / 2);
+        Circle::with_center(knob, 32)
+            .into_styled(PrimitiveStyle::with_fill(Rgb::CSS_CORNSILK))
+            .draw(display)?;
+        Ok(())
+    }
+
+    /// The level selected by `input`, if it changes it.
+    pub fn on_event(&self, input: InputEvent) -> Option<u8> {
+        let level = match input {
+            InputEvent::Touch(TouchGesture::SingleTap(pos)) => {
+                let track = Self::TRACK;
+                let top = track.top_left.y - track.size.height as i32;
+                let bottom = track.top_left.y + 2 * track.size.height as i32;
+                if pos.y < top || pos.y > bottom {
+                    return None;
+                }
+                let offset = (pos.x - track.top_left.x).clamp(0, track.size.width as i32) as u32;
+                (offset * 100 / track.size.width) as u8
+            }
+            InputEvent::Touch(TouchGesture::SwipeRight(_)) => self.level.saturating_add(Self::STEP),
+            InputEvent::Touch(TouchGesture::SwipeLeft(_)) => self.level.saturating_sub(Self::STEP),
+            _ => return None,
+        };
+        let level = level.clamp(Self::MIN, 100);
+        (level != self.level).then_some(level)
+    }
+}
+
+/// Last night in detail with its hypnogram, and the hours slept on the nights before.
+#[derive(PartialEq, Clone, Copy)]
+pub struct SleepView<'a> {
+    /// Newest first.
+    pub nights: &'a [NightSummary],
+}
+
+impl<'a> SleepView<'a> {
+    pub fn new(nights: &'a [NightSummary]) -> Self {
+        Self { nights }
+    }
+
+    pub fn draw<D: DrawTarget<Color = Rgb>>(&self, display: &mut D) -> Result<(), D::Error> {
+        display.clear(Rgb::BLACK)?;
+        let asleep_color = Rgb::CSS_MEDIUM_PURPLE;
+        let awake_color = Rgb::CSS_DARK_ORANGE;
+        let centered = TextStyleBuilder::new()
+            .alignment(embedded_graphics::text::Alignment::Center)
+            .baseline(embedded_graphics::text::Baseline::Alphabetic)
+            .build();
+
+        let Some(last) = self.nights.first() else {
+            Text::with_text_style(
+                "No sleep yet",
+                display.bounding_box().center(),
+                date_text_style(Rgb::CSS_CORNSILK),
+                centered,
+            )
+            .draw(display)?;
+            return Ok(());
+        };
+
+        let mut buf: heapless::String<16> = heapless::String::new();
+        write!(buf, "{}h {:02}m", last.asleep / 60, last.asleep % 60).unwrap();
+        Text::with_text_style(&buf, Point::new(WIDTH as i32 / 2, 36), menu_text_style(asleep_color), centered)
+            .draw(display)?;
+
+        let clock = |minutes: u16| {
+            let minutes = (last.start + minutes) % (24 * 60);
+            (minutes / 60, minutes % 60)
+        };
+        let (onset, wake) = (clock(last.onset), clock(last.wake));
+        let mut buf: heapless::String<16> = heapless::String::new();
+        write!(buf, "{:02}:{:02} - {:02}:{:02}", onset.0, onset.1, wake.0, wake.1).unwrap();
+        Text::with_text_style(&buf, Point::new(WIDTH as i32 / 2, 66), date_text_style(Rgb::CSS_CORNSILK), centered)
+            .draw(display)?;
+
+        // Hypnogram, asleep slots as full height bars
+        let slots = last.slots().max(1) as u32;
+        let width = WIDTH - 20;
+        for slot in 0..slots {
+            let x = 10 + (slot * width / slots) as i32;
+            let w = ((slot + 1) * width / slots - slot * width / slots).max(1);
+            let (y, h, color) = if last.asleep_in_slot(slot as usize) {
+                (80, 50, asleep_color)
+            } else {
+                (120, 10, awake_color)
+            };
+            Rectangle::new(Point::new(x, y), Size::new(w, h))
+                .into_styled(PrimitiveStyle::with_fill(color))
+                .draw(display)?;
+        }
+
+        // Hours slept on the previous nights, oldest to the left
+        let count = self.nights.len() as i32;
+        let bar_width = 24;
+        let spacing = 8;
+        let left = (WIDTH as i32 - count * bar_width - (count - 1) * spacing) / 2;
+        for (i, night) in self.nights.iter().rev().enumerate() {
+            let x = left + i as i32 * (bar_width + spacing);
+            let height = (night.asleep.min(600) as u32 * 50 / 600).max(1);
+            Rectangle::new(Point::new(x, 200 - height as i32), Size::new(bar_width as u32, height))
+                .into_styled(PrimitiveStyle::with_fill(asleep_color))
+                .draw(display)?;
+
+            let mut buf: heapless::String<8> = heapless::String::new();
+            write!(buf, "{}", (night.asleep + 30) / 60).unwrap();
+            Text::with_text_style(
+                &buf,
+                Point::new(x + bar_width / 2, 222),
+                text_text_style(Rgb::CSS_CORNSILK),
+                centered,
+            )
+            .draw(display)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Background heart rate readings of the last 24 hours against the alert thresholds.
+#[derive(PartialEq, Clone, Copy)]
+pub struct HeartHistoryView<'a> {
+    pub history: &'a History,
+    /// Seconds since 1970, the right edge of the chart.
+    pub now: i64,
+    pub low: u8,
+    pub high: u8,
+    pub alert: Option<Alert>,
+}
+
+impl<'a> HeartHistoryView<'a> {
+    /// Heart rates shown at the bottom and the top of the chart.
+    const CHART_MIN: u32 = 30;
+    const CHART_MAX: u32 = 180;
+    const CHART_TOP: i32 = 80;
+    const CHART_HEIGHT: u32 = 120;
+
+    pub fn new(history: &'a History, now: i64, low: u8, high: u8, alert: Option<Alert>) -> Self {
+        Self {
+            history,
+            now,
+            low,
+            high,
+            alert,
+        }
+    }
+
+    pub fn draw<D: DrawTarget<Color = Rgb>>(&self, display: &mut D) -> Result<(), D::Error> {
+        display.clear(Rgb::BLACK)?;
+        let heart_color = Rgb::CSS_CRIMSON;
+        let centered = TextStyleBuilder::new()
+            .alignment(embedded_graphics::text::Alignment::Center)
+            .baseline(embedded_graphics::text::Baseline::Alphabetic)
+            .build();
+
+        let mut buf: heapless::String<24> = heapless::String::new();
+        let color = match self.alert {
+            Some(Alert::High(bpm)) => {
+                write!(buf, "High {} bpm", bpm).unwrap();
+                Rgb::CSS_ORANGE_RED
+            }
+            Some(Alert::Low(bpm)) => {
+                write!(buf, "Low {} bpm", bpm).unwrap();
+                Rgb::CSS_ORANGE_RED
+            }
+            None => match self.history.latest(self.now) {
+                Some(bpm) => {
+                    write!(buf, "{} bpm", bpm).unwrap();
+                    Rgb::CSS_CORNSILK
+                }
+                None => {
+                    write!(buf, "--- bpm").unwrap();
+                    Rgb::CSS_DARK_GRAY
+                }
+            },
+        };
+        Text::with_text_style(&buf, Point::new(WIDTH as i32 / 2, 46), menu_text_style(color), centered)
+            .draw(display)?;
+
+        // Thresholds
+        for threshold in [self.low, self.high] {
+            let y = self.y(threshold);
+            Rectangle::new(Point::new(10, y), Size::new(WIDTH - 20, 1))
+                .into_styled(PrimitiveStyle::with_fill(Rgb::CSS_DARK_SLATE_GRAY))
+                .draw(display)?;
+        }
+
+        // One bar per slot, from the bottom of the chart up to the reading
+        let width = WIDTH - 20;
+        let slots = SLOTS as u32;
+        for (slot, bpm) in self.history.slots(self.now).enumerate() {
+            let Some(bpm) = bpm else { continue };
+            let slot = slot as u32;
+            let x = 10 + (slot * width / slots) as i32;
+            let w = ((slot + 1) * width / slots - slot * width / slots).max(1);
+            let y = self.y(bpm);
+            let bottom = Self::CHART_TOP + Self::CHART_HEIGHT as i32;
+            let color = if bpm > self.high || bpm < self.low {
+                Rgb::CSS_ORANGE_RED
+            } else {
+                heart_color
+            };
+            Rectangle::new(Point::new(x, y), Size::new(w, (bottom - y).max(1) as u32))
+                .into_styled(PrimitiveStyle::with_fill(color))
+                .draw(display)?;
+        }
+
+        let left = TextStyleBuilder::new()
+            .alignment(embedded_graphics::text::Alignment::Left)
+            .baseline(embedded_graphics::text::Baseline::Alphabetic)
+            .build();
+        let right = TextStyleBuilder::new()
+            .alignment(embedded_graphics::text::Alignment::Right)
+            .baseline(embedded_graphics::text::Baseline::Alphabetic)
+            .build();
+        Text::with_text_style("-24h", Point::new(10, 222), text_text_style(Rgb::CSS_CORNSILK), left)
+            .draw(display)?;
+        Text::with_text_style(
+            "now",
+            Point::new(WIDTH as i32 - 10, 222),
+            text_text_style(Rgb::CSS_CORNSILK),
+            right,
+        )
+        .draw(display)?;
+
+        Ok(())
+    }
+
+    fn y(&self, bpm: u8) -> i32 {
+        let bpm = (bpm as u32).clamp(Self::CHART_MIN, Self::CHART_MAX);
+        let above = (bpm - Self::CHART_MIN) * Self::CHART_HEIGHT / (Self::CHART_MAX - Self::CHART_MIN);
+        Self::CHART_TOP + Self::CHART_HEIGHT as i32 - above as i32
+    }
+}
+
+/// Battery level over the last 48 hours, with the time left at the current rate.
+#[derive(Clone, Copy, PartialEq)]
+pub struct BatteryHistoryView<'a> {
+    pub history: &'a battery_history::History,
+    /// Seconds since 1970, the right edge of the chart.
+    pub now: i64,
+    pub percent: u8,
+    pub charging: bool,
+    pub hours_remaining: Option<u32>,
+}
+
+impl<'a> BatteryHistoryView<'a> {
+    const CHART_TOP: i32 = 80;
+    const CHART_HEIGHT: u32 = 120;
+
+    pub fn new(
+        history: &'a battery_history::History,
+        now: i64,
+        percent: u8,
+        charging: bool,
+        hours_remaining: Option<u32>,
+    ) -> Self {
+        Self {
+            history,
+            now,
+            percent,
+            charging,
+            hours_remaining,
+        }
+    }
+
+    pub fn draw<D: DrawTarget<Color = Rgb>>(&self, display: &mut D) -> Result<(), D::Error> {
+        display.clear(Rgb::BLACK)?;
+        let centered = TextStyleBuilder::new()
+            .alignment(embedded_graphics::text::Alignment::Center)
+            .baseline(embedded_graphics::text::Baseline::Alphabetic)
+            .build();
+
+        let mut buf: heapless::String<16> = heapless::String::new();
+        write!(buf, "{}%", self.percent).unwrap();
+        Text::with_text_style(
+            &buf,
+            Point::new(WIDTH as i32 / 2, 40),
+            menu_text_style(Rgb::CSS_CORNSILK),
+            centered,
+        )
+        .draw(display)?;
+
+        let mut buf: heapless::String<16> = heapless::String::new();
+        match (self.charging, self.hours_remaining) {
+            (true, _) => write!(buf, "Charging").unwrap(),
+            (false, Some(hours)) if hours >= 48 => write!(buf, "{} days left", hours / 24).unwrap(),
+            (false, Some(hours)) => write!(buf, "{} h left", hours).unwrap(),
+            (false, None) => write!(buf, "-- h left").unwrap(),
+        }
+        Text::with_text_style(
+            &buf,
+            Point::new(WIDTH as i32 / 2, 66),
+            text_text_style(Rgb::CSS_DARK_GRAY),
+            centered,
+        )
+        .draw(display)?;
+
+        // One bar per slot, from the bottom of the chart up to the level
+        let width = WIDTH - 20;
+        let slots = battery_history::SLOTS as u32;
+        let bottom = Self::CHART_TOP + Self::CHART_HEIGHT as i32;
+        Rectangle::new(Point::new(10, bottom), Size::new(width, 1))
+            .into_styled(PrimitiveStyle::with_fill(Rgb::CSS_DARK_SLATE_GRAY))
+            .draw(display)?;
+        for (slot, point) in self.history.slots(self.now).enumerate() {
+            let Some(point) = point else { continue };
+            let slot = slot as u32;
+            let x = 10 + (slot * width / slots) as i32;
+            let w = ((slot + 1) * width / slots - slot * width / slots).max(1);
+            let height = (point.percent.min(100) as u32 * Self::CHART_HEIGHT / 100).max(1);
+            let color = if point.charging {
+                Rgb::CSS_DEEP_SKY_BLUE
+            } else if point.percent > 20 {
+                Rgb::CSS_GREEN
+            } else {
+                Rgb::CSS_ORANGE_RED
+            };
+            Rectangle::new(Point::new(x, bottom - height as i32), Size::new(w, height))
+                .into_styled(PrimitiveStyle::with_fill(color))
+                .draw(display)?;
+        }
+
+        let left = TextStyleBuilder::new()
+            .alignment(embedded_graphics::text::Alignment::Left)
+            .baseline(embedded_graphics::text::Baseline::Alphabetic)
+            .build();
+        let right = TextStyleBuilder::new()
+            .alignment(embedded_graphics::text::Alignment::Right)
+            .baseline(embedded_graphics::text::Baseline::Alphabetic)
+            .build();
+        Text::with_text_style("-48h", Point::new(10, 222), text_text_style(Rgb::CSS_CORNSILK), left).draw(display)?;
+        Text::with_text_style(
+            "now",
+            Point::new(WIDTH as i32 - 10, 222),
+            text_text_style(Rgb::CSS_CORNSILK),
+            right,
+        )
+        .draw(display)?;
+
         Ok(())
     }
 }
 
 pub struct WorkoutView {
-    hr: u32,
+    hr: Option<Estimate>,
     duration: time::Duration,
 }
 
 impl WorkoutView {
-    pub fn new(hr: u32, duration: time::Duration) -> Self {
+    /// `hr` is `None` until the heart rate is known.
+    pub fn new(hr: Option<Estimate>, duration: time::Duration) -> Self {
         Self { hr, duration }
     }
     pub fn draw<D: DrawTarget<Color = Rgb>>(&self, display: &mut D) -> Result<(), D::Error> {
         display.clear(Rgb::BLACK)?;
 
         let mut buf: heapless::String<16> = heapless::String::new();
-        write!(buf, "{:03}", self.hr).unwrap();
+        let color = match self.hr {
+            Some(hr) => {
+                write!(buf, "{:03}", hr.bpm).unwrap();
+                if hr.confidence >= MIN_CONFIDENCE {
+                    Rgb::CSS_DARK_CYAN
+                } else {
+                    Rgb::CSS_DARK_GRAY
+                }
+            }
+            None => {
+                write!(buf, "---").unwrap();
+                Rgb::CSS_DARK_GRAY
+            }
+        };
         let hr = Text::with_text_style(
             &buf,
             display.bounding_box().center(),
-            watch_text_style(Rgb::CSS_DARK_CYAN),
+            watch_text_style(color),
             TextStyleBuilder::new()
                 .alignment(embedded_graphics::text::Alignment::Center)
                 .baseline(embedded_graphics::text::Baseline::Alphabetic)
@@ -197,7 +991,7 @@ impl WorkoutView {
             buf,
             "{:03}:{:02}",
             self.duration.whole_minutes(),
-            self.duration.whole_seconds()
+            self.duration.whole_seconds() % 60
         )
         .unwrap();
         let secs = Text::with_text_style(
@@ -269,6 +1063,75 @@ impl TimerView {
 	}
 }
 
+/// The last workout sessions, one per row.
+#[derive(PartialEq, Clone, Copy)]
+pub struct WorkoutHistoryView<'a> {
+    /// Newest first.
+    pub sessions: &'a [workout::Summary],
+    /// Start times are shown in this zone.
+    pub zone: TimeZone,
+}
+
+impl<'a> WorkoutHistoryView<'a> {
+    const ROWS: usize = 4;
+
+    pub fn new(sessions: &'a [workout::Summary], zone: TimeZone) -> Self {
+        Self { sessions, zone }
+    }
+
+    pub fn draw<D: DrawTarget<Color = Rgb>>(&self, display: &mut D) -> Result<(), D::Error> {
+        display.clear(Rgb::BLACK)?;
+        let left = TextStyleBuilder::new()
+            .alignment(embedded_graphics::text::Alignment::Left)
+            .baseline(embedded_graphics::text::Baseline::Alphabetic)
+            .build();
+
+        if self.sessions.is_empty() {
+            Text::with_text_style(
+                "No workouts yet",
+                display.bounding_box().center(),
+                date_text_style(Rgb::CSS_CORNSILK),
+                TextStyleBuilder::new()
+                    .alignment(embedded_graphics::text::Alignment::Center)
+                    .baseline(embedded_graphics::text::Baseline::Alphabetic)
+                    .build(),
+            )
+            .draw(display)?;
+            return Ok(());
+        }
+
+        for (i, session) in self.sessions.iter().take(Self::ROWS).enumerate() {
+            let y = 40 + i as i32 * (HEIGHT as i32 / Self::ROWS as i32);
+
+            let mut buf: heapless::String<24> = heapless::String::new();
+            match time::OffsetDateTime::from_unix_timestamp(self.zone.to_local(session.start)) {
+                Ok(start) => write!(
+                    buf,
+                    "{:02}.{:02} {:02}:{:02}",
+                    start.day(),
+                    start.month() as u8,
+                    start.hour(),
+                    start.minute()
+                )
+                .unwrap(),
+                Err(_) => write!(buf, "--.-- --:--").unwrap(),
+            }
+            Text::with_text_style(&buf, Point::new(12, y), date_text_style(Rgb::CSS_DARK_CYAN), left)
+                .draw(display)?;
+
+            let mut buf: heapless::String<32> = heapless::String::new();
+            write!(buf, "{} min", session.duration / 60).unwrap();
+            if session.average > 0 {
+                write!(buf, "  avg {}  max {}", session.average, session.max).unwrap();
+            }
+            Text::with_text_style(&buf, Point::new(12, y + 20), text_text_style(Rgb::CSS_CORNSILK), left)
+                .draw(display)?;
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum MenuAction {
@@ -280,11 +1143,27 @@ pub enum MenuAction {
     ValidateFirmware,
     Brightness,
     TimeSettings,
-    ChangeTimeMinInc,
-    ChangeTimeHourInc,
-    ChangeTimeMinDec,
-    ChangeTimeHourDec,
+    TimeSettingsPage(TimePage),
+    /// Step a field of the date and time by the given amount.
+    AdjustTime(Field, i32),
     Reset,
+    Apps,
+    Weather,
+    Steps,
+    Sleep,
+    WorkoutHistory,
+    MoreApps,
+    HeartHistory,
+    BatteryHistory,
+    MoreSettings,
+    ToggleRaiseToWake,
+    ToggleSleepMode,
+    CycleHeartInterval,
+    ToggleBatteryCalibration,
+    ZoneSettings,
+    CycleDstRule,
+    CycleWorldClock,
+    Back,
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -295,22 +1174,42 @@ pub enum MenuView {
         find_phone: MenuItem,
         settings: MenuItem,
     },
+    Apps {
+        weather: MenuItem,
+        steps: MenuItem,
+        sleep: MenuItem,
+        workouts: MenuItem,
+    },
+    MoreApps {
+        heart: MenuItem,
+        battery: MenuItem,
+    },
     Settings {
         firmware: MenuItem,
         brightness: MenuItem,
         time_settings: MenuItem,
         reset: MenuItem,
     },
+    MoreSettings {
+        raise_to_wake: MenuItem,
+        sleep_mode: MenuItem,
+        heart_interval: MenuItem,
+        calibration: MenuItem,
+    },
+    ZoneSettings {
+        dst: MenuItem,
+        world_clock: MenuItem,
+    },
     Firmware {
         details: FirmwareDetails,
         item: MenuItem,
     },
     TimeSettings {
-    	details: TimeDetails,
-        min_inc: MenuControl,
-        hour_inc: MenuControl,
-        min_dec: MenuControl,
-        hour_dec: MenuControl,
+        details: TimeDetails,
+        left_inc: MenuControl,
+        right_inc: MenuControl,
+        left_dec: MenuControl,
+        right_dec: MenuControl,
     },
 }
 
@@ -324,6 +1223,22 @@ impl MenuView {
         }
     }
 
+    pub fn apps() -> Self {
+        Self::Apps {
+            weather: MenuItem::new("Weather", 0),
+            steps: MenuItem::new("Steps", 1),
+            sleep: MenuItem::new("Sleep", 2),
+            workouts: MenuItem::new("Workouts", 3),
+        }
+    }
+
+    pub fn more_apps() -> Self {
+        Self::MoreApps {
+            heart: MenuItem::new("Heart", 0),
+            battery: MenuItem::new("Battery", 1),
+        }
+    }
+
     pub fn settings() -> Self {
         Self::Settings {
             firmware: MenuItem::new("Firmware", 0),
@@ -333,6 +1248,37 @@ impl MenuView {
         }
     }
 
+    /// `heart_interval` is the minutes between background heart rate readings, 0 when off.
+    /// `calibrating` is whether a charge cycle is being recorded to calibrate the battery.
+    pub fn more_settings(raise_to_wake: bool, sleep_mode: bool, heart_interval: u8, calibrating: bool) -> Self {
+        let heart_interval = match heart_interval {
+            0 => "HR: Off",
+            10 => "HR: 10 min",
+            30 => "HR: 30 min",
+            60 => "HR: 60 min",
+            _ => "HR: On",
+        };
+        Self::MoreSettings {
+            raise_to_wake: MenuItem::new(if raise_to_wake { "Raise: On" } else { "Raise: Off" }, 0),
+            sleep_mode: MenuItem::new(if sleep_mode { "Sleep: On" } else { "Sleep: Off" }, 1),
+            heart_interval: MenuItem::new(heart_interval, 2),
+            calibration: MenuItem::new(if calibrating { "Calib: On" } else { "Calib: Off" }, 3),
+        }
+    }
+
+    /// `world_clock` is the name of the zone shown as a second clock, if any.
+    pub fn zone_settings(dst: DstRule, world_clock: Option<&'static str>) -> Self {
+        let dst = match dst {
+            DstRule::None => "DST: Off",
+            DstRule::Eu => "DST: EU",
+            DstRule::Us => "DST: US",
+        };
+        Self::ZoneSettings {
+            dst: MenuItem::new(dst, 0),
+            world_clock: MenuItem::new(world_clock.unwrap_or("World: Off"), 1),
+        }
+    }
+
     pub fn firmware_settings(details: FirmwareDetails) -> Self {
         let valid = details.validated;
         Self::Firmware {
@@ -342,12 +1288,16 @@ impl MenuView {
     }
 
     pub fn time_settings(details: TimeDetails) -> Self {
+        let (inc, dec) = match details.page {
+            TimePage::Year => ("+10", "-10"),
+            _ => ("+", "-"),
+        };
         Self::TimeSettings {
             details,
-            hour_inc: MenuControl::new("+", 0),
-            min_inc: MenuControl::new("+", 1),
-            hour_dec: MenuControl::new("-", 2),
-            min_dec: MenuControl::new("-", 3),
+            left_inc: MenuControl::new("+", 0),
+            right_inc: MenuControl::new(inc, 1),
+            left_dec: MenuControl::new("-", 2),
+            right_dec: MenuControl::new(dec, 3),
         }
     }
     
@@ -367,6 +1317,23 @@ impl MenuView {
                 settings.draw(display)?;
             }
 
+            Self::Apps {
+                weather,
+                steps,
+                sleep,
+                workouts,
+            } => {
+                weather.draw(display)?;
+                steps.draw(display)?;
+                sleep.draw(display)?;
+                workouts.draw(display)?;
+            }
+
+            Self::MoreApps { heart, battery } => {
+                heart.draw(display)?;
+                battery.draw(display)?;
+            }
+
             Self::Settings { firmware, brightness, time_settings, reset } => {
                 firmware.draw(display)?;
                 brightness.draw(display)?;
@@ -374,17 +1341,40 @@ impl MenuView {
                 reset.draw(display)?;
             }
 
+            Self::MoreSettings {
+                raise_to_wake,
+                sleep_mode,
+                heart_interval,
+                calibration,
+            } => {
+                raise_to_wake.draw(display)?;
+                sleep_mode.draw(display)?;
+                heart_interval.draw(display)?;
+                calibration.draw(display)?;
+            }
+
+            Self::ZoneSettings { dst, world_clock } => {
+                dst.draw(display)?;
+                world_clock.draw(display)?;
+            }
+
             Self::Firmware { details, item } => {
                 details.draw(display)?;
                 item.draw(display)?;
             }
 
-			Self::TimeSettings { details, min_inc, hour_inc, min_dec, hour_dec } => {
+            Self::TimeSettings {
+                details,
+                left_inc,
+                right_inc,
+                left_dec,
+                right_dec,
+            } => {
                 details.draw(display)?;
-                min_inc.draw(display)?;
-                hour_inc.draw(display)?;
-                min_dec.draw(display)?;
-                hour_dec.draw(display)?;
+                left_inc.draw(display)?;
+                right_inc.draw(display)?;
+                left_dec.draw(display)?;
+                right_dec.draw(display)?;
             }
         }
 
@@ -407,6 +1397,41 @@ impl MenuView {
                     Some(MenuAction::FindPhone)
                 } else if settings.is_clicked(input) {
                     Some(MenuAction::Settings)
+                } else if let InputEvent::Touch(TouchGesture::SwipeUp(_)) = input {
+                    Some(MenuAction::Apps)
+                } else {
+                    None
+                }
+            }
+            Self::Apps {
+                weather,
+                steps,
+                sleep,
+                workouts,
+            } => {
+                if weather.is_clicked(input) {
+                    Some(MenuAction::Weather)
+                } else if steps.is_clicked(input) {
+                    Some(MenuAction::Steps)
+                } else if sleep.is_clicked(input) {
+                    Some(MenuAction::Sleep)
+                } else if workouts.is_clicked(input) {
+                    Some(MenuAction::WorkoutHistory)
+                } else if let InputEvent::Touch(TouchGesture::SwipeUp(_)) = input {
+                    Some(MenuAction::MoreApps)
+                } else if let InputEvent::Touch(TouchGesture::SwipeDown(_)) = input {
+                    Some(MenuAction::Back)
+                } else {
+                    None
+                }
+            }
+            Self::MoreApps { heart, battery } => {
+                if heart.is_clicked(input) {
+                    Some(MenuAction::HeartHistory)
+                } else if battery.is_clicked(input) {
+                    Some(MenuAction::BatteryHistory)
+                } else if let InputEvent::Touch(TouchGesture::SwipeDown(_)) = input {
+                    Some(MenuAction::Back)
                 } else {
                     None
                 }
@@ -420,6 +1445,41 @@ impl MenuView {
                 	Some(MenuAction::TimeSettings)
                 } else if reset.is_clicked(input) {
                     Some(MenuAction::Reset)
+                } else if let InputEvent::Touch(TouchGesture::SwipeUp(_)) = input {
+                    Some(MenuAction::MoreSettings)
+                } else {
+                    None
+                }
+            }
+            Self::MoreSettings {
+                raise_to_wake,
+                sleep_mode,
+                heart_interval,
+                calibration,
+            } => {
+                if raise_to_wake.is_clicked(input) {
+                    Some(MenuAction::ToggleRaiseToWake)
+                } else if sleep_mode.is_clicked(input) {
+                    Some(MenuAction::ToggleSleepMode)
+                } else if heart_interval.is_clicked(input) {
+                    Some(MenuAction::CycleHeartInterval)
+                } else if calibration.is_clicked(input) {
+                    Some(MenuAction::ToggleBatteryCalibration)
+                } else if let InputEvent::Touch(TouchGesture::SwipeUp(_)) = input {
+                    Some(MenuAction::ZoneSettings)
+                } else if let InputEvent::Touch(TouchGesture::SwipeDown(_)) = input {
+                    Some(MenuAction::Back)
+                } else {
+                    None
+                }
+            }
+            Self::ZoneSettings { dst, world_clock } => {
+                if dst.is_clicked(input) {
+                    Some(MenuAction::CycleDstRule)
+                } else if world_clock.is_clicked(input) {
+                    Some(MenuAction::CycleWorldClock)
+                } else if let InputEvent::Touch(TouchGesture::SwipeDown(_)) = input {
+                    Some(MenuAction::Back)
                 } else {
                     None
                 }
@@ -431,15 +1491,26 @@ impl MenuView {
                     None
                 }
             }
-            Self::TimeSettings { details: _, min_inc, hour_inc, min_dec, hour_dec } => {
-                if min_inc.is_clicked(input) {
-                    Some(MenuAction::ChangeTimeMinInc)
-                } else if hour_inc.is_clicked(input) {
-					Some(MenuAction::ChangeTimeHourInc)
-				} else if min_dec.is_clicked(input) {
-                    Some(MenuAction::ChangeTimeMinDec)
-                } else if hour_dec.is_clicked(input) {
-					Some(MenuAction::ChangeTimeHourDec)
+            Self::TimeSettings {
+                details,
+                left_inc,
+                right_inc,
+                left_dec,
+                right_dec,
+            } => {
+                let [(left, left_step), (right, right_step)] = details.page.fields();
+                if left_inc.is_clicked(input) {
+                    Some(MenuAction::AdjustTime(left, left_step))
+                } else if right_inc.is_clicked(input) {
+                    Some(MenuAction::AdjustTime(right, right_step))
+                } else if left_dec.is_clicked(input) {
+                    Some(MenuAction::AdjustTime(left, -left_step))
+                } else if right_dec.is_clicked(input) {
+                    Some(MenuAction::AdjustTime(right, -right_step))
+                } else if let InputEvent::Touch(TouchGesture::SwipeUp(_)) = input {
+                    Some(MenuAction::TimeSettingsPage(details.page.next()))
+                } else if let InputEvent::Touch(TouchGesture::SwipeDown(_)) = input {
+                    Some(MenuAction::Back)
                 } else {
                     None
                 }
@@ -661,29 +1732,60 @@ impl FirmwareDetails {
     }
 }
 
+/// Part of the date and time the time settings show, swipe up for the next.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TimePage {
+    Time,
+    Date,
+    Year,
+}
+
+impl TimePage {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Time => Self::Date,
+            Self::Date => Self::Year,
+            Self::Year => Self::Time,
+        }
+    }
+
+    /// The fields stepped by the left and right controls, and by how much.
+    pub fn fields(self) -> [(Field, i32); 2] {
+        match self {
+            Self::Time => [(Field::Hour, 1), (Field::Minute, 1)],
+            Self::Date => [(Field::Day, 1), (Field::Month, 1)],
+            Self::Year => [(Field::Year, 1), (Field::Year, 10)],
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq)]
 pub struct TimeDetails {
-    time: PrimitiveDateTime,
+    /// `None` while the time is not known.
+    time: Option<PrimitiveDateTime>,
+    page: TimePage,
 }
 
 impl TimeDetails {
-    pub const fn new(
-        time: PrimitiveDateTime,
-    ) -> Self {
-        Self {
-            time,
-        }
+    pub const fn new(time: Option<PrimitiveDateTime>, page: TimePage) -> Self {
+        Self { time, page }
+    }
+
+    pub fn page(&self) -> TimePage {
+        self.page
     }
 
     pub fn draw<D: DrawTarget<Color = Rgb>>(&self, display: &mut D) -> Result<(), D::Error> {
         let mut buf: heapless::String<16> = heapless::String::new();
-        write!(
-            buf,
-            "{:02}:{:02}",
-            self.time.hour(),
-            self.time.minute(),
-        )
-        .unwrap();
+        match (self.time, self.page) {
+            (Some(time), TimePage::Time) => write!(buf, "{:02}:{:02}", time.hour(), time.minute()).unwrap(),
+            (Some(time), TimePage::Date) => write!(buf, "{:02}.{:02}", time.day(), time.month() as u8).unwrap(),
+            (Some(time), TimePage::Year) => write!(buf, "{}", time.year()).unwrap(),
+            (None, TimePage::Time) => buf.push_str("--:--").unwrap(),
+            (None, TimePage::Date) => buf.push_str("--.--").unwrap(),
+            (None, TimePage::Year) => buf.push_str("----").unwrap(),
+        }
 
 		let hm = Text::with_text_style(
             &buf,